@@ -15,7 +15,9 @@ fn main() {
         Profile::Core,
         Fallbacks::All,
         [
-            "GL_ARB_bindless_texture"
+            "GL_ARB_bindless_texture",
+            "GL_KHR_parallel_shader_compile",
+            "GL_ARB_sparse_texture"
         ]
     ).write_bindings(GlobalGenerator, &mut file).unwrap();
 }
\ No newline at end of file
@@ -0,0 +1,63 @@
+use cgmath::{Matrix4, Vector3, vec3};
+use super::Vertex;
+
+// Axis aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // Returns None if there are no vertices
+    pub fn from_vertices(vertices: &[Vertex]) -> Option<Aabb> {
+        let first = vertices.first()?.position;
+        let mut aabb = Aabb::new(first, first);
+
+        for vertex in vertices.iter() {
+            aabb.expand(vertex.position);
+        }
+
+        Some(aabb)
+    }
+
+    pub fn expand(&mut self, point: Vector3<f32>) {
+        self.min = vec3(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z));
+        self.max = vec3(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z));
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    // Box enclosing this one after a transform, larger than it for rotations
+    pub fn transform(&self, transform: &Matrix4<f32>) -> Aabb {
+        let corners = self.corners().map(|corner| (transform * corner.extend(1.0)).truncate());
+        let mut aabb = Aabb::new(corners[0], corners[0]);
+
+        for corner in corners.iter() {
+            aabb.expand(*corner);
+        }
+
+        aabb
+    }
+
+    // Corners ordered by bits, x = bit 0, y = bit 1, z = bit 2 (set means max)
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let mut corners = [self.min; 8];
+
+        for (i, corner) in corners.iter_mut().enumerate() {
+            *corner = vec3(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z }
+            );
+        }
+
+        corners
+    }
+}
@@ -0,0 +1,214 @@
+use std::rc::Rc;
+use cgmath::{Matrix4, Quaternion, Vector3, VectorSpace};
+use super::{Buffer, GlError, JointTransform, Skeleton, gl};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    // Slerp for rotations
+    Linear
+}
+
+// One keyframe value per time, like a glTF animation sampler's output
+pub enum ChannelValues {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>)
+}
+
+impl ChannelValues {
+    pub fn len(&self) -> usize {
+        match self {
+            ChannelValues::Translation(values) => values.len(),
+            ChannelValues::Rotation(values) => values.len(),
+            ChannelValues::Scale(values) => values.len()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Keyframes for one property of one joint, times are in seconds and ascending
+pub struct Channel {
+    pub joint: usize,
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+    pub interpolation: Interpolation
+}
+
+impl Channel {
+    // Index of the keyframe before time and how far it is towards the next one
+    fn keyframe(&self, time: f32) -> (usize, usize, f32) {
+        let next = self.times.partition_point(|&keyframe_time| keyframe_time <= time);
+
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+        if next >= self.times.len() {
+            let last = self.times.len() - 1;
+            return (last, last, 0.0);
+        }
+
+        let previous = next - 1;
+        let span = self.times[next] - self.times[previous];
+        let factor = if span > 0.0 { (time - self.times[previous]) / span } else { 0.0 };
+
+        match self.interpolation {
+            Interpolation::Step => (previous, previous, 0.0),
+            Interpolation::Linear => (previous, next, factor)
+        }
+    }
+
+    fn apply(&self, time: f32, transform: &mut JointTransform) {
+        if self.times.is_empty() {
+            return;
+        }
+
+        let (a, b, factor) = self.keyframe(time);
+
+        match &self.values {
+            ChannelValues::Translation(values) => transform.translation = values[a].lerp(values[b], factor),
+            ChannelValues::Rotation(values) => transform.rotation = values[a].slerp(values[b], factor),
+            ChannelValues::Scale(values) => transform.scale = values[a].lerp(values[b], factor)
+        }
+    }
+}
+
+pub struct Animation {
+    pub channels: Vec<Channel>,
+    duration: f32
+}
+
+impl Animation {
+    // Duration is the last keyframe time of any channel.
+    // Errors if a channel doesn't have exactly one value per time
+    pub fn new(channels: Vec<Channel>) -> Result<Animation, GlError> {
+        if let Some(channel) = channels.iter().find(|channel| channel.times.len() != channel.values.len()) {
+            return Err(GlError::KeyframeCountMismatch(channel.joint, channel.times.len(), channel.values.len()));
+        }
+
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration: f32, &time| duration.max(time));
+
+        Ok(Animation { channels, duration })
+    }
+
+    pub fn get_duration(&self) -> f32 {
+        self.duration
+    }
+
+    // Overwrites the animated properties of the pose, joints without channels are left as is
+    pub fn sample(&self, time: f32, pose: &mut [JointTransform]) {
+        for channel in self.channels.iter() {
+            if let Some(transform) = pose.get_mut(channel.joint) {
+                channel.apply(time, transform);
+            }
+        }
+    }
+}
+
+// Plays an animation on a skeleton, writing the bone matrices to an SSBO every update
+pub struct AnimationPlayer {
+    skeleton: Rc<Skeleton>,
+    animation: Rc<Animation>,
+    time: f32,
+    pub speed: f32,
+    pub looping: bool,
+    playing: bool,
+    pose: Vec<JointTransform>,
+    palette: Vec<Matrix4<f32>>,
+    bone_buffer: Buffer<Matrix4<f32>>
+}
+
+impl AnimationPlayer {
+    // Starts playing and looping from the beginning at normal speed
+    pub fn new(skeleton: Rc<Skeleton>, animation: Rc<Animation>) -> AnimationPlayer {
+        let mut player = AnimationPlayer {
+            pose: skeleton.rest_pose.clone(),
+            palette: Vec::with_capacity(skeleton.joint_count()),
+            skeleton,
+            animation,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+            playing: true,
+            bone_buffer: Buffer::new()
+        };
+
+        player.sample();
+        player.bone_buffer.set_data_mut(player.palette.clone());
+
+        player
+    }
+
+    // Advances by dt seconds scaled by speed, non looping animations stop at either end
+    pub fn update(&mut self, dt: f32) {
+        if !self.playing {
+            return;
+        }
+
+        let duration = self.animation.get_duration();
+        self.time += dt * self.speed;
+
+        if self.looping && duration > 0.0 {
+            self.time = self.time.rem_euclid(duration);
+        } else if self.time < 0.0 || self.time > duration {
+            self.time = self.time.clamp(0.0, duration);
+            self.playing = false;
+        }
+
+        self.sample();
+
+        // Written in place, so updates don't allocate
+        if let Some(mut bones) = self.bone_buffer.map_write(0, self.palette.len()) {
+            bones.copy_from_slice(&self.palette);
+        }
+    }
+
+    fn sample(&mut self) {
+        self.pose.copy_from_slice(&self.skeleton.rest_pose);
+        self.animation.sample(self.time, &mut self.pose);
+        self.skeleton.compute_palette(&self.pose, &mut self.palette);
+    }
+
+    // Switches animation from its start, keeping speed and looping
+    pub fn set_animation(&mut self, animation: Rc<Animation>) {
+        self.animation = animation;
+        self.time = 0.0;
+        self.playing = true;
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    // Takes effect on the next update
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn get_palette(&self) -> &Vec<Matrix4<f32>> {
+        &self.palette
+    }
+
+    // The palette is a mat4 array indexed by joint, e.g. `buffer Bones { mat4 bones[]; };`
+    pub fn bind(&self, binding: u32) {
+        self.bone_buffer.bind_base(gl::SHADER_STORAGE_BUFFER, binding);
+    }
+}
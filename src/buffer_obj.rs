@@ -1,16 +1,25 @@
-use std::ops::Range;
-use super::gl;
+use std::{borrow::Cow, cell::Cell, ops::{Range, Deref, DerefMut}};
+use super::{GlError, RenderStats, gl};
 
 pub struct Buffer<T> {
     id: u32,
     data: Vec<T>,
-    binding_index: u32
+    binding_index: u32,
+    // Only owned buffers are deleted on drop
+    owned: bool,
+    // See set_growth, capacity is the element count the GPU storage can hold
+    grow: bool,
+    capacity: Cell<usize>,
+    // Elements last sent to the GPU, kept through release_data so read_back only reads those
+    // and not the spare capacity. None for from_raw buffers, whose contents aren't known
+    sent_len: Cell<Option<usize>>
 }
 
 impl<T> Buffer<T> {
     pub fn new() -> Buffer<T> {
         let mut buffer = Buffer {
-            data: Vec::<T>::new(), id: 0, binding_index: 0
+            data: Vec::<T>::new(), id: 0, binding_index: 0, owned: true, grow: false, capacity: Cell::new(0),
+            sent_len: Cell::new(Some(0))
         };
 
         unsafe {
@@ -20,7 +29,34 @@ impl<T> Buffer<T> {
         buffer
     }
 
+    // Wraps a buffer created elsewhere, it is only deleted on drop if owned is true.
+    // Inner data starts empty, so it doesn't reflect the buffer's contents
+    pub fn from_raw(id: u32, owned: bool) -> Buffer<T> {
+        Buffer {
+            data: Vec::<T>::new(), id, binding_index: 0, owned, grow: false, capacity: Cell::new(0),
+            sent_len: Cell::new(None)
+        }
+    }
+
+    // Gives up ownership, the buffer has to be deleted by the caller
+    pub fn into_raw(mut self) -> u32 {
+        self.owned = false;
+        self.id
+    }
+
+    // Buffers handed out by other code should be set to not owned to avoid double frees
+    pub fn set_owned(&mut self, owned: bool) {
+        self.owned = owned;
+    }
+
+    pub fn is_owned(&self) -> bool {
+        self.owned
+    }
+
     pub fn send_data(&self) {
+        RenderStats::record_upload(self.byte_len());
+        self.sent_len.set(Some(self.data.len()));
+
         unsafe {
             gl::NamedBufferStorage(
                 self.id,
@@ -29,21 +65,85 @@ impl<T> Buffer<T> {
                 0 as gl::types::GLbitfield
             );
         }
+
+        self.capacity.set(self.data.len());
     }
 
     pub fn send_data_mut(&self) {
+        self.send_slice_mut(&self.data);
+    }
+
+    // Sends data to mutable storage like send_data_mut, but from a borrowed slice instead of
+    // the CPU copy, which is left as is. For callers that keep their own copy and upload it
+    // every frame, get_data doesn't reflect what was sent but read_back does
+    pub fn send_slice_mut(&self, data: &[T]) {
+        RenderStats::record_upload(std::mem::size_of_val(data));
+        self.sent_len.set(Some(data.len()));
+
+        if self.grow && data.len() <= self.capacity.get() {
+            // Still fits, so the storage is kept and only the contents are written
+            unsafe {
+                gl::NamedBufferSubData(
+                    self.id,
+                    0,
+                    std::mem::size_of_val(data) as isize,
+                    data.as_ptr() as *const gl::types::GLvoid
+                );
+            }
+
+            return;
+        }
+
+        if !self.grow {
+            unsafe {
+                gl::NamedBufferData(
+                    self.id,
+                    std::mem::size_of_val(data) as isize,
+                    data.as_ptr() as *const gl::types::GLvoid,
+                    gl::DYNAMIC_DRAW
+                );
+            }
+
+            self.capacity.set(data.len());
+            return;
+        }
+
+        let capacity = data.len().max(self.capacity.get() * 2);
+
         unsafe {
             gl::NamedBufferData(
                 self.id,
-                (self.data.len() * std::mem::size_of::<T>()) as isize,
-                self.data.as_ptr() as *const gl::types::GLvoid,
+                (capacity * std::mem::size_of::<T>()) as isize,
+                std::ptr::null(),
                 gl::DYNAMIC_DRAW
             );
+            gl::NamedBufferSubData(
+                self.id,
+                0,
+                std::mem::size_of_val(data) as isize,
+                data.as_ptr() as *const gl::types::GLvoid
+            );
         }
+
+        self.capacity.set(capacity);
+    }
+
+    // With growth on, mutable storage (set_data_mut, push, remove) is only reallocated when the
+    // data outgrows it, at double the old capacity, and is never shrunk. Meant for streaming
+    // geometry whose size changes every frame. The storage can be larger than the data, so
+    // shaders reading the buffer as an SSBO should take the length from len(), not .length()
+    pub fn set_growth(&mut self, enabled: bool) {
+        self.grow = enabled;
+    }
+
+    // Elements the GPU storage holds, at least len()
+    pub fn capacity(&self) -> usize {
+        self.capacity.get()
     }
 
     pub unsafe fn send_data_index(&self, index: usize) {
         let size = std::mem::size_of::<T>();
+        RenderStats::record_upload(size);
 
         gl::NamedBufferSubData(
             self.id,
@@ -55,6 +155,7 @@ impl<T> Buffer<T> {
 
     pub unsafe fn send_data_range(&self, range: Range<usize>) {
         let size = std::mem::size_of::<T>();
+        RenderStats::record_upload((range.end - range.start) * size);
 
         gl::NamedBufferSubData(
             self.id,
@@ -123,10 +224,119 @@ impl<T> Buffer<T> {
         unsafe { self.send_data_range(range) }
     }
 
+    // Writes straight into GPU memory, skipping the inner data which is not updated.
+    // Only works on mutable buffers (set_data_mut), immutable storage isn't mappable.
+    // Offset and len are in elements, returns None if the range runs past the elements
+    // last sent or mapping fails
+    pub fn map_write(&self, offset: usize, len: usize) -> Option<MappedBufferMut<'_, T>> {
+        if offset.checked_add(len)? > self.gpu_len() {
+            return None;
+        }
+
+        let size = std::mem::size_of::<T>();
+
+        let ptr = unsafe {
+            gl::MapNamedBufferRange(
+                self.id,
+                (offset * size) as isize,
+                (len * size) as isize,
+                gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT
+            )
+        };
+
+        if ptr.is_null() {
+            return None;
+        }
+
+        Some(MappedBufferMut {
+            buffer: self,
+            data: unsafe { std::slice::from_raw_parts_mut(ptr as *mut T, len) }
+        })
+    }
+
     pub fn get_data(&self) -> &Vec<T> {
         &self.data
     }
 
+    // Frees the CPU side copy, len and get_data are empty afterwards. Only for buffers that
+    // are never updated again, such as static geometry, read_back still gives the contents
+    pub fn release_data(&mut self) {
+        self.data = Vec::new();
+    }
+
+    // Elements last sent, or the whole storage for from_raw buffers
+    fn gpu_len(&self) -> usize {
+        self.sent_len.get().unwrap_or_else(|| {
+            let mut size = 0;
+            unsafe {
+                gl::GetNamedBufferParameteriv(self.id, gl::BUFFER_SIZE, &mut size);
+            }
+
+            size as usize / std::mem::size_of::<T>().max(1)
+        })
+    }
+
+    // Copies the elements last sent from GPU memory, stalls until pending writes are done.
+    // Spare capacity from set_growth isn't included. Buffers from from_raw are read whole
+    pub fn read_back(&self) -> Vec<T> where T: Copy {
+        let len = self.gpu_len();
+        let mut data = Vec::with_capacity(len);
+
+        unsafe {
+            gl::GetNamedBufferSubData(
+                self.id,
+                0,
+                (len * std::mem::size_of::<T>()) as isize,
+                data.as_mut_ptr() as *mut gl::types::GLvoid
+            );
+            data.set_len(len);
+        }
+
+        data
+    }
+
+    // The CPU copy if it is kept, otherwise read back from the GPU
+    pub fn get_data_or_read_back(&self) -> Cow<'_, [T]> where T: Copy {
+        if self.data.is_empty() {
+            Cow::Owned(self.read_back())
+        } else {
+            Cow::Borrowed(&self.data)
+        }
+    }
+
+    // Keeps only the given ranges, packed from the start of the buffer in their current order,
+    // then reuploads as a mutable buffer. Returns the new start of each range in the order given,
+    // e.g. to update mesh offsets. Ranges must not overlap, indices into a compacted vertex
+    // buffer have to be rebased by the caller. Needs the CPU side copy, see release_data
+    pub fn compact(&mut self, ranges: &[Range<usize>]) -> Result<Vec<usize>, GlError> where T: Copy {
+        if self.data.is_empty() && self.sent_len.get() != Some(0) && ranges.iter().any(|range| !range.is_empty()) {
+            return Err(GlError::BufferDataReleased(self.id));
+        }
+
+        if let Some(range) = ranges.iter().find(|range| range.start > range.end || range.end > self.data.len()) {
+            return Err(GlError::InvalidBufferRange(self.id, range.start, range.end, self.data.len()));
+        }
+
+        let mut order: Vec<usize> = (0..ranges.len()).collect();
+        order.sort_by_key(|&i| ranges[i].start);
+
+        if let Some(pair) = order.windows(2).find(|pair| ranges[pair[0]].end > ranges[pair[1]].start) {
+            return Err(GlError::OverlappingBufferRanges(self.id, ranges[pair[0]].end, ranges[pair[1]].start));
+        }
+
+        let mut data = Vec::with_capacity(ranges.iter().map(|range| range.len()).sum());
+        let mut offsets = vec![0; ranges.len()];
+
+        for i in order {
+            offsets[i] = data.len();
+            data.extend_from_slice(&self.data[ranges[i].clone()]);
+        }
+
+        self.set_data_mut(data);
+
+        Ok(offsets)
+    }
+
     // Unsafe functions designed to be called from VAO
     // These need to exist because the buffer handles its own binding_index
     pub unsafe fn add_vertex_to_vertex_array(&mut self, vao_id: u32, binding_index: u32) {
@@ -145,14 +355,56 @@ impl<T> Buffer<T> {
         gl::VertexArrayAttribBinding(vao_id, attrib_index, self.binding_index)
     }
 
+    // Vertex buffer binding the buffer was last added to a VAO at
+    pub fn get_binding_index(&self) -> u32 {
+        self.binding_index
+    }
+
+    // Binds the whole buffer to an indexed target such as gl::SHADER_STORAGE_BUFFER,
+    // gl::UNIFORM_BUFFER or gl::ATOMIC_COUNTER_BUFFER. index is the shader's
+    // layout(binding = index), and stays bound until something else is bound there,
+    // so buffers shared between passes need distinct indices per target
+    pub fn bind_base(&self, target: gl::types::GLenum, index: u32) {
+        unsafe {
+            gl::BindBufferBase(target, index, self.id);
+        }
+    }
+
+    // Same as bind_base for elements [start, start + len). For uniform buffers the byte offset
+    // has to be a multiple of capabilities::uniform_buffer_offset_alignment
+    pub fn bind_range(&self, target: gl::types::GLenum, index: u32, start: usize, len: usize) {
+        unsafe {
+            gl::BindBufferRange(
+                target,
+                index,
+                self.id,
+                (start * self.element_size()) as isize,
+                (len * self.element_size()) as isize
+            );
+        }
+    }
+
     pub unsafe fn set_divisor(&mut self, vao_id: u32, divisor: u32) {
         gl::VertexArrayBindingDivisor(vao_id, self.binding_index, divisor);
     }
 
+    // Element count, not bytes
     pub fn len(&self) -> usize {
         self.data.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.data.len() * self.element_size()
+    }
+
+    pub fn element_size(&self) -> usize {
+        std::mem::size_of::<T>()
+    }
+
     pub fn get_id(&self) -> u32 {
         self.id
     }
@@ -160,8 +412,38 @@ impl<T> Buffer<T> {
 
 impl<T> Drop for Buffer<T> {
     fn drop(&mut self) {
+        if !self.owned { return; }
+
         unsafe {
             gl::DeleteBuffers(1, &self.id);
         }
     }
+}
+
+// Unmaps the buffer when dropped
+pub struct MappedBufferMut<'a, T> {
+    buffer: &'a Buffer<T>,
+    data: &'a mut [T]
+}
+
+impl<T> Deref for MappedBufferMut<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.data
+    }
+}
+
+impl<T> DerefMut for MappedBufferMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.data
+    }
+}
+
+impl<T> Drop for MappedBufferMut<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::UnmapNamedBuffer(self.buffer.get_id());
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,113 @@
+use std::{cell::RefCell, collections::HashSet, ffi::CStr, rc::Rc};
+use super::gl;
+
+// Queries need a current context, so results are cached per thread (like StateCache) and
+// dropped by StateCache::make_current, which is how context switches are announced
+thread_local! {
+    static CACHE: RefCell<Cache> = RefCell::new(Cache::default());
+}
+
+#[derive(Default)]
+struct Cache {
+    version: Option<(i32, i32)>,
+    extensions: Option<Rc<HashSet<String>>>,
+    max_samples: Option<i32>,
+    uniform_buffer_offset_alignment: Option<isize>
+}
+
+fn cached<T: Clone>(field: fn(&mut Cache) -> &mut Option<T>, query: impl FnOnce() -> T) -> T {
+    if let Some(value) = CACHE.with(|cache| field(&mut cache.borrow_mut()).clone()) {
+        return value;
+    }
+
+    let value = query();
+    CACHE.with(|cache| *field(&mut cache.borrow_mut()) = Some(value.clone()));
+
+    value
+}
+
+// Forgets every cached result, called by StateCache::make_current
+pub fn invalidate() {
+    CACHE.with(|cache| *cache.borrow_mut() = Cache::default());
+}
+
+pub fn gl_version() -> (i32, i32) {
+    cached(|cache| &mut cache.version, || {
+        let mut major = 0;
+        let mut minor = 0;
+
+        unsafe {
+            gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+            gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        }
+
+        (major, minor)
+    })
+}
+
+pub fn supports_version(major: i32, minor: i32) -> bool {
+    gl_version() >= (major, minor)
+}
+
+// Name including the prefix, e.g. "GL_ARB_bindless_texture"
+pub fn has_extension(name: &str) -> bool {
+    let extensions = cached(|cache| &mut cache.extensions, || {
+        let mut count = 0;
+
+        unsafe {
+            gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+
+            Rc::new((0..count as u32).filter_map(|i| {
+                let extension = gl::GetStringi(gl::EXTENSIONS, i);
+
+                (!extension.is_null()).then(|| CStr::from_ptr(extension as *const _).to_string_lossy().into_owned())
+            }).collect())
+        }
+    });
+
+    extensions.contains(name)
+}
+
+pub fn max_samples() -> i32 {
+    cached(|cache| &mut cache.max_samples, || {
+        let mut samples = 0;
+
+        unsafe {
+            gl::GetIntegerv(gl::MAX_SAMPLES, &mut samples);
+        }
+
+        samples
+    })
+}
+
+// Offsets given to glBindBufferRange on GL_UNIFORM_BUFFER have to be multiples of this
+pub fn uniform_buffer_offset_alignment() -> isize {
+    cached(|cache| &mut cache.uniform_buffer_offset_alignment, || {
+        let mut alignment = 0;
+
+        unsafe {
+            gl::GetIntegerv(gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT, &mut alignment);
+        }
+
+        alignment.max(1) as isize
+    })
+}
+
+// Highest sample count supported for a format, target being e.g. gl::RENDERBUFFER
+// or gl::TEXTURE_2D_MULTISAMPLE. Falls back to max_samples if the query gives nothing
+pub fn max_format_samples(target: gl::types::GLenum, internal_format: gl::types::GLenum) -> i32 {
+    let mut samples = 0;
+
+    unsafe {
+        gl::GetInternalformativ(target, internal_format, gl::SAMPLES, 1, &mut samples);
+    }
+
+    if samples > 0 { samples } else { max_samples() }
+}
+
+// Largest supported count not above requested for every format, 0 stays 0
+pub fn clamp_samples(requested: i32, formats: &[(gl::types::GLenum, gl::types::GLenum)]) -> i32 {
+    formats.iter().fold(requested.min(max_samples()), |samples, &(target, internal_format)| {
+        samples.min(max_format_samples(target, internal_format))
+    }).max(0)
+}
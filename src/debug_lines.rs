@@ -0,0 +1,112 @@
+use cgmath::{Matrix4, Vector3};
+use memoffset::offset_of;
+use super::{Aabb, Buffer, GlError, ShaderCodeBundle, ShaderProgram, VertexArray, gl};
+
+const DEBUG_LINES_VERTEX: &str = r#"
+#version 460 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aColor;
+
+uniform mat4 viewProjection;
+
+out vec3 color;
+
+void main() {
+    color = aColor;
+    gl_Position = viewProjection * vec4(aPos, 1.0);
+}
+"#;
+
+const DEBUG_LINES_FRAGMENT: &str = r#"
+#version 460 core
+in vec3 color;
+
+out vec4 FragColor;
+
+void main() {
+    FragColor = vec4(color, 1.0);
+}
+"#;
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct LineVertex {
+    pub position: Vector3<f32>,
+    pub color: Vector3<f32>
+}
+
+// Accumulates line segments each frame for visualising normals, bounds, frustums, etc.
+// Lines are kept until clear() is called
+pub struct DebugLines {
+    lines: Vec<LineVertex>,
+    vertex_array: VertexArray,
+    vertex_buffer: Buffer<LineVertex>,
+    shader_program: ShaderProgram
+}
+
+impl DebugLines {
+    pub fn new() -> Result<DebugLines, GlError> {
+        let shader_program = ShaderProgram::new(ShaderCodeBundle {
+            vertex: Some(DEBUG_LINES_VERTEX.to_owned()),
+            fragment: Some(DEBUG_LINES_FRAGMENT.to_owned()),
+            ..Default::default()
+        })?;
+
+        let mut debug_lines = DebugLines {
+            lines: Vec::new(),
+            vertex_array: VertexArray::new(),
+            vertex_buffer: Buffer::new(),
+            shader_program
+        };
+
+        // Line counts change every frame, so the storage is only reallocated when outgrown
+        debug_lines.vertex_buffer.set_growth(true);
+        debug_lines.vertex_array.add_vertex_buffer(&mut debug_lines.vertex_buffer);
+        debug_lines.vertex_array.add_attrib(&mut debug_lines.vertex_buffer, 3, offset_of!(LineVertex, position) as u32, gl::FLOAT);
+        debug_lines.vertex_array.add_attrib(&mut debug_lines.vertex_buffer, 3, offset_of!(LineVertex, color) as u32, gl::FLOAT);
+
+        Ok(debug_lines)
+    }
+
+    pub fn add_line(&mut self, a: Vector3<f32>, b: Vector3<f32>, color: Vector3<f32>) {
+        self.lines.push(LineVertex { position: a, color });
+        self.lines.push(LineVertex { position: b, color });
+    }
+
+    pub fn add_aabb(&mut self, aabb: &Aabb, color: Vector3<f32>) {
+        let corners = aabb.corners();
+
+        // Corners that differ by exactly one bit share an edge
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.add_line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.lines.len() / 2
+    }
+
+    // Uploads accumulated lines and draws them in one call
+    pub fn draw(&mut self, view_projection: &Matrix4<f32>) -> Result<(), GlError> {
+        if self.lines.is_empty() {
+            return Ok(());
+        }
+
+        self.vertex_buffer.send_slice_mut(&self.lines);
+
+        self.shader_program.use_program();
+        self.shader_program.set_mat4("viewProjection", view_projection)?;
+        self.vertex_array.draw_arrays(gl::LINES, 0, self.lines.len() as i32, 1);
+
+        Ok(())
+    }
+}
@@ -1,17 +1,44 @@
 use std::{fmt::Display, error::Error, ffi::NulError, io};
 
-use super::ShaderCompileType;
+use super::{ShaderCompileType, FramebufferStatus};
 
 #[derive(Debug)]
 pub enum GlError{
     CStringError(NulError),
     UniformNotFound(String, u32),
+    AttributeNotFound(String, u32),
     ShaderCompileError(ShaderCompileType, u32, String),
     IoError(io::Error),
     UniformInvalidIndex(String, u32),
-    FramebufferNotComplete(u32),
+    UniformArrayTooLong(String, usize, i32),
+    UniformBindingMismatch(String, u32, u32, u32),
+    ProgramNotLinked(u32, String),
+    FramebufferNotComplete(u32, FramebufferStatus),
     UniformBufferMissing,
-    CannotResize(u32)
+    UniformBufferTooSmall(String, isize, isize),
+    UniformArrayIndex(String, u32, u32),
+    CountBufferMissing,
+    MixedTopologies(u32, u32),
+    CannotResize(u32),
+    UnsupportedPixelFormat(u32),
+    UnsupportedTextureTarget(u32, u32),
+    BufferOverflow(u32, usize, usize),
+    InvalidBufferRange(u32, usize, usize, usize),
+    OverlappingBufferRanges(u32, usize, usize),
+    BufferDataReleased(u32),
+    InvalidInstanceIndex(u32, usize),
+    InvalidAttachmentIndex(u32, usize, usize),
+    InvalidImageFormat(u32),
+    InvalidTextureSize(i32, i32, i32),
+    InvalidMipLevel(i32, i32),
+    InvalidLevelSize(i32, i32, i32, i32, i32),
+    ImageDataTooShort(usize, usize),
+    JointCountMismatch(usize, usize, usize),
+    InvalidJointParent(usize, usize),
+    JointParentCycle(usize),
+    KeyframeCountMismatch(usize, usize, usize),
+    GlCallError(u32),
+    UnsupportedFeature(String)
 }
 
 impl Display for GlError {
@@ -23,14 +50,85 @@ impl Display for GlError {
             GlError::ShaderCompileError(type_, id, error) =>
                 write!(f, "Shader '{}' with ID {} failed to compile:\n{}", type_, id, error),
             GlError::IoError(io_error) => write!(f, "{}", io_error),
+            GlError::AttributeNotFound(attribute, id) => {
+                write!(f, "Attribute '{}' was not found in shader {}", attribute, id)
+            },
             GlError::UniformInvalidIndex(ub_name, id) => {
                 write!(f, "Uniform block '{}' was not found in shader {}", ub_name, id)
             },
-            GlError::FramebufferNotComplete(id) => {
-                write!(f, "Framebuffer '{}' is not complete", id)
+            GlError::UniformArrayTooLong(uniform, len, size) => {
+                write!(f, "Uniform array '{}' holds {} values, {} were given", uniform, size, len)
+            },
+            GlError::UniformBindingMismatch(ub_name, id, expected, actual) => {
+                write!(f, "Uniform block '{}' in shader {} reads binding {}, but its buffer is bound to {}", ub_name, id, actual, expected)
+            },
+            GlError::ProgramNotLinked(id, log) => {
+                write!(f, "Shader program {} was used without being linked:\n{}", id, log)
+            },
+            GlError::FramebufferNotComplete(id, status) => {
+                write!(f, "Framebuffer '{}' is not complete: {}", id, status)
+            },
+            GlError::UniformBufferTooSmall(ub_name, size, minimum) => {
+                write!(f, "Uniform buffer '{}' can't be resized to {} bytes, it needs at least {}", ub_name, size, minimum)
+            },
+            GlError::UniformArrayIndex(ub_name, index, count) => {
+                write!(f, "Element {} of uniform block array '{}' is out of bounds, it has {}", index, ub_name, count)
             },
             GlError::UniformBufferMissing => write!(f, "Uniform buffer is not present"),
-            GlError::CannotResize(id) => write!(f, "Cannot resize texture '{}'", id)
+            GlError::CountBufferMissing => write!(f, "Count buffer is not present, call enable_count_buffer first"),
+            GlError::MixedTopologies(first, other) => write!(f, "Meshes drawn in one call must share a topology, found {:#X} and {:#X}", first, other),
+            GlError::CannotResize(id) => write!(f, "Cannot resize texture '{}'", id),
+            GlError::UnsupportedPixelFormat(format) => {
+                write!(f, "Format {:#X} cannot be converted to 8 bit pixels", format)
+            },
+            GlError::UnsupportedTextureTarget(id, target) => {
+                write!(f, "Texture {} has target {:#X}, only single sample 2D textures can be read back", id, target)
+            },
+            GlError::BufferOverflow(id, len, capacity) => {
+                write!(f, "Writing {} elements to buffer '{}' which only holds {}", len, id, capacity)
+            },
+            GlError::InvalidBufferRange(id, start, end, len) => {
+                write!(f, "Range {}..{} is invalid for buffer '{}' which holds {} elements", start, end, id, len)
+            },
+            GlError::OverlappingBufferRanges(id, end, start) => {
+                write!(f, "Ranges of buffer '{}' overlap, one ends at {} but the next starts at {}", id, end, start)
+            },
+            GlError::BufferDataReleased(id) => {
+                write!(f, "The CPU side copy of buffer '{}' was released, it can't be edited", id)
+            },
+            GlError::InvalidInstanceIndex(index, len) => {
+                write!(f, "Instance {} is out of bounds, the model has {} instances", index, len)
+            },
+            GlError::InvalidAttachmentIndex(id, index, len) => {
+                write!(f, "Cannot set color attachment {} of framebuffer {}, it has {} attachments", index, id, len)
+            },
+            GlError::InvalidImageFormat(format) => {
+                write!(f, "Format {:#X} cannot be used for image load/store", format)
+            },
+            GlError::InvalidTextureSize(width, height, max) => {
+                write!(f, "Texture size {}x{} is invalid, sizes must be between 1 and {}", width, height, max)
+            },
+            GlError::InvalidMipLevel(level, levels) => {
+                write!(f, "Mip level {} is out of range, texture has {} levels", level, levels)
+            },
+            GlError::InvalidLevelSize(level, width, height, level_width, level_height) => {
+                write!(f, "Data of size {}x{} given for mip level {}, which is {}x{}", width, height, level, level_width, level_height)
+            },
+            GlError::ImageDataTooShort(len, needed) => {
+                write!(f, "Image data holds {} bytes, {} are needed", len, needed)
+            },
+            GlError::JointCountMismatch(parents, inverse_binds, rest_pose) => {
+                write!(f, "Skeleton has {} parents, {} inverse bind matrices and {} rest transforms", parents, inverse_binds, rest_pose)
+            },
+            GlError::InvalidJointParent(joint, parent) => {
+                write!(f, "Joint {} has parent {}, which is not a joint of the skeleton", joint, parent)
+            },
+            GlError::JointParentCycle(joint) => write!(f, "Joint {} is its own ancestor", joint),
+            GlError::KeyframeCountMismatch(joint, times, values) => {
+                write!(f, "Channel for joint {} has {} keyframe times but {} values", joint, times, values)
+            },
+            GlError::GlCallError(code) => write!(f, "OpenGL call failed with error {:#X}", code),
+            GlError::UnsupportedFeature(feature) => write!(f, "'{}' is not supported by this context", feature)
         }
     }
 }
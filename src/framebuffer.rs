@@ -1,26 +1,51 @@
-use std::rc::Rc;
+use std::{cell::RefCell, fmt::Display, rc::Rc};
 use cgmath::{Matrix4, vec3};
 use crate::model_utils::create_quad;
 
-use super::{GlError, Texture, RenderBuffer, MultiBindModel, ModelTrait, ShaderProgram, gl};
+use super::{GlError, Texture, RenderBuffer, MultiBindModel, ModelTrait, ShaderProgram, StateCache, ContextId, gl, capabilities};
 
 pub struct Framebuffer {
     id: u32,
     textures: Vec<Rc<Texture>>,
     draw_buffers: Vec<gl::types::GLenum>,
-    quad: MultiBindModel, // Uses this since each FB has a separate 1 mesh quad
+    // Attachments fragment outputs currently go to, all of draw_buffers unless set_draw_buffers was used
+    active_draw_buffers: Vec<gl::types::GLenum>,
+    // Uses this since each FB has a separate 1 mesh quad. Made on the first draw,
+    // so framebuffers that are only rendered into don't allocate one
+    quad: RefCell<Option<MultiBindModel>>,
+    // Textures drawn by the quad, kept here as well for when it's made
+    linked_textures: Vec<Rc<Texture>>,
+    linked_multisample_textures: Vec<Rc<Texture>>,
     width: i32,
     height: i32,
-    pub render_buffer: Option<RenderBuffer>
+    pub render_buffer: Option<RenderBuffer>,
+    // Sampleable depth attachment from gen_depth_texture, e.g. for shadow maps
+    depth_texture: Option<Rc<Texture>>,
+    // Buffers cleared by bind_pass, e.g. gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT.
+    // 0 means the pass accumulates on top of the previous contents
+    pub clear_mask: gl::types::GLbitfield,
+    // Depth bind_pass clears to, 1.0 normally or 0.0 with reverse-Z (see RenderState::get_clear_depth)
+    pub default_clear_depth: f32,
+    // Attachment size relative to the size given to set_size, e.g. 0.5 for half-res effects
+    scale: f32,
+    // 0 unless the attachments are multisampled
+    samples: i32,
+    // Only owned framebuffers are deleted on drop
+    owned: bool,
+    resize_pending: bool,
+    context: ContextId
 }
 
 impl Framebuffer {
+    // See FramebufferBuilder for attachments with different formats
     pub fn new(
         width: i32,
         height: i32,
         tex_num: usize,
         has_rb: bool
     ) -> Result<Framebuffer, GlError> {
+        Texture::check_size(width, height)?;
+
         let mut framebuffer = Framebuffer::new_default(width, height);
 
         unsafe {
@@ -28,33 +53,118 @@ impl Framebuffer {
         }
         
         // Set up renderbuffer, all these assume framebuffer is bound
-        framebuffer.gen_textures(tex_num);
+        framebuffer.gen_textures(tex_num)?;
         if has_rb { framebuffer.gen_render_buffer() };
         framebuffer.check_status()?;
 
         Ok(framebuffer)
     }
 
-    pub fn new_default(width: i32, height: i32) -> Framebuffer {
-        // Create quad model for framebuffer
-        let model_transforms = vec![Matrix4::<f32>::from_translation(vec3(0.0, 0.0, 0.0))];
-        let quad = create_quad(model_transforms);
+    // Attachments are multisampled, their textures can be linked and sampled as sampler2DMS
+    // for a custom resolve, or the framebuffer can be blitted to resolve in hardware.
+    // samples is clamped to what the hardware supports, get_samples gives the actual count
+    pub fn new_multisample(
+        width: i32,
+        height: i32,
+        tex_num: usize,
+        has_rb: bool,
+        samples: i32
+    ) -> Result<Framebuffer, GlError> {
+        Texture::check_size(width, height)?;
+
+        let mut formats = vec![(gl::TEXTURE_2D_MULTISAMPLE, gl::RGBA16F)];
+        if has_rb { formats.push((gl::RENDERBUFFER, gl::DEPTH24_STENCIL8)) };
+
+        let mut framebuffer = Framebuffer::new_default(width, height);
+        framebuffer.samples = capabilities::clamp_samples(samples, &formats);
+
+        unsafe {
+            gl::CreateFramebuffers(1, &mut framebuffer.id);
+        }
+
+        framebuffer.gen_textures(tex_num)?;
+        if has_rb { framebuffer.gen_render_buffer() };
+        framebuffer.check_status()?;
+
+        Ok(framebuffer)
+    }
+
+    // No color attachments, only a depth texture, for shadow maps and depth prepasses
+    pub fn new_depth_only(width: i32, height: i32) -> Result<Framebuffer, GlError> {
+        Texture::check_size(width, height)?;
+
+        let mut framebuffer = Framebuffer::new_default(width, height);
+
+        unsafe {
+            gl::CreateFramebuffers(1, &mut framebuffer.id);
+        }
+
+        framebuffer.gen_textures(0)?;
+        framebuffer.gen_depth_texture();
+        framebuffer.check_status()?;
+
+        Ok(framebuffer)
+    }
 
+    pub fn new_default(width: i32, height: i32) -> Framebuffer {
         Framebuffer {
             id: 0,
             textures: Vec::new(),
             draw_buffers: Vec::new(),
-            quad,
+            active_draw_buffers: Vec::new(),
+            quad: RefCell::new(None),
+            linked_textures: Vec::new(),
+            linked_multisample_textures: Vec::new(),
             width,
             height,
             render_buffer: None,
+            depth_texture: None,
+            clear_mask: 0,
+            default_clear_depth: 1.0,
+            scale: 1.0,
+            samples: 0,
+            owned: true,
+            resize_pending: false,
+            context: StateCache::current_context()
         }
     }
 
-    pub fn gen_textures(&mut self, n: usize) {
+    // Wraps a framebuffer created elsewhere, it is only deleted on drop if owned is true.
+    // Its attachments aren't known, so get_link and resizing only cover ones added after
+    pub fn from_raw(id: u32, width: i32, height: i32, owned: bool) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new_default(width, height);
+
+        framebuffer.id = id;
+        framebuffer.owned = owned;
+
+        framebuffer
+    }
+
+    // Gives up ownership, the framebuffer has to be deleted by the caller.
+    // Attachments created by this framebuffer are still dropped
+    pub fn into_raw(mut self) -> u32 {
+        self.owned = false;
+        self.id
+    }
+
+    // Framebuffers from new() start owned
+    pub fn set_owned(&mut self, owned: bool) {
+        self.owned = owned;
+    }
+
+    pub fn is_owned(&self) -> bool {
+        self.owned
+    }
+
+    // Errors if a texture can't be allocated, e.g. for a 0 sized framebuffer
+    pub fn gen_textures(&mut self, n: usize) -> Result<(), GlError> {
         unsafe {
             for i in 0..n {
-                let texture = Texture::new_mut(self.width, self.height);
+                let texture = if self.samples > 0 {
+                    Texture::new_multisample(self.width, self.height, self.samples, gl::RGBA16F)
+                } else {
+                    Texture::try_new_mut(self.width, self.height)?
+                };
                 let attachment = gl::COLOR_ATTACHMENT0 + i as u32;
 
                 // Bind to framebuffer
@@ -69,21 +179,109 @@ impl Framebuffer {
                 self.draw_buffers.push(attachment);
             }
 
-            gl::NamedFramebufferDrawBuffers(
+            // Without color attachments the default COLOR_ATTACHMENT0 read and draw
+            // buffers point at nothing, which some drivers report as incomplete
+            if self.draw_buffers.is_empty() {
+                gl::NamedFramebufferReadBuffer(self.id, gl::NONE);
+            }
+        }
+
+        self.apply_draw_buffers(self.draw_buffers.clone());
+
+        Ok(())
+    }
+
+    // Adds a color attachment of any format after the existing ones, e.g. gl::RG16F
+    // for motion vectors or gl::R32UI for object ids. gen_textures always uses gl::RGBA16F
+    pub fn gen_color_texture(&mut self, internal_format: gl::types::GLenum) {
+        let texture = if self.samples > 0 {
+            Texture::new_multisample(self.width, self.height, self.samples, internal_format)
+        } else {
+            let (data_format, data_type) = Texture::empty_data_format(internal_format);
+            Texture::new_mut_format(self.width, self.height, internal_format, data_format, data_type)
+        };
+
+        unsafe {
+            gl::NamedFramebufferTexture(
                 self.id,
-                self.draw_buffers.len() as i32,
-                self.draw_buffers.as_ptr()
+                gl::COLOR_ATTACHMENT0 + self.textures.len() as u32,
+                texture.get_id(),
+                0
             );
         }
+
+        self.push_attachment(Rc::new(texture));
+    }
+
+    // Limits fragment outputs to some of the color attachments, by index. Output i of the shader
+    // goes to the i-th attachment given here, e.g. [2] makes output 0 write to attachment 2.
+    // Adding an attachment or gen_textures makes every attachment active again
+    pub fn set_draw_buffers(&mut self, attachments: &[usize]) {
+        self.apply_draw_buffers(attachments.iter().map(|index| gl::COLOR_ATTACHMENT0 + *index as u32).collect());
+    }
+
+    fn apply_draw_buffers(&mut self, draw_buffers: Vec<gl::types::GLenum>) {
+        self.send_draw_buffers(&draw_buffers);
+        self.active_draw_buffers = draw_buffers;
+    }
+
+    fn send_draw_buffers(&self, draw_buffers: &[gl::types::GLenum]) {
+        unsafe {
+            if draw_buffers.is_empty() {
+                gl::NamedFramebufferDrawBuffer(self.id, gl::NONE);
+            } else {
+                gl::NamedFramebufferDrawBuffers(self.id, draw_buffers.len() as i32, draw_buffers.as_ptr());
+            }
+        }
+    }
+
+    pub fn active_draw_buffers(&self) -> &[gl::types::GLenum] {
+        &self.active_draw_buffers
+    }
+
+    // Every color attachment in order, whether active or not
+    pub fn get_draw_buffers(&self) -> &[gl::types::GLenum] {
+        &self.draw_buffers
+    }
+
+    // Attaches a 32 bit float depth texture, which unlike the render buffer can be sampled.
+    // Used instead of gen_render_buffer, there is no stencil
+    pub fn gen_depth_texture(&mut self) {
+        self.gen_depth_texture_format(gl::DEPTH_COMPONENT32F);
+    }
+
+    // Same as gen_depth_texture, gl::DEPTH24_STENCIL8 and gl::DEPTH32F_STENCIL8 add a stencil
+    pub fn gen_depth_texture_format(&mut self, internal_format: gl::types::GLenum) {
+        let texture = if self.samples > 0 {
+            Texture::new_multisample(self.width, self.height, self.samples, internal_format)
+        } else {
+            let (data_format, data_type) = Texture::empty_data_format(internal_format);
+            Texture::new_mut_format(self.width, self.height, internal_format, data_format, data_type)
+        };
+
+        unsafe {
+            gl::NamedFramebufferTexture(self.id, depth_attachment(internal_format), texture.get_id(), 0);
+        }
+
+        self.depth_texture = Some(Rc::new(texture));
+    }
+
+    pub fn get_depth_texture(&self) -> Option<Rc<Texture>> {
+        self.depth_texture.clone()
     }
 
     pub fn gen_render_buffer(&mut self) {
-        let render_buffer = RenderBuffer::new(self.width, self.height);
+        self.gen_render_buffer_format(gl::DEPTH24_STENCIL8);
+    }
+
+    // Render buffer with a depth format other than gl::DEPTH24_STENCIL8, see RenderBuffer::new_format
+    pub fn gen_render_buffer_format(&mut self, internal_format: gl::types::GLenum) {
+        let render_buffer = RenderBuffer::new_format(self.width, self.height, self.samples, internal_format);
 
         unsafe {
             gl::NamedFramebufferRenderbuffer(
                 self.get_id(),
-                gl::DEPTH_STENCIL_ATTACHMENT,
+                depth_attachment(internal_format),
                 gl::RENDERBUFFER,
                 render_buffer.get_id()
             );
@@ -93,12 +291,12 @@ impl Framebuffer {
     }
 
     pub fn check_status(&self) -> Result<(), GlError> {
-        unsafe {
-            if gl::CheckNamedFramebufferStatus(self.id, gl::FRAMEBUFFER) == gl::FRAMEBUFFER_COMPLETE {
-                Ok(())
-            } else {
-                Err(GlError::FramebufferNotComplete(self.id))
-            }
+        let status = unsafe { gl::CheckNamedFramebufferStatus(self.id, gl::FRAMEBUFFER) };
+
+        if status == gl::FRAMEBUFFER_COMPLETE {
+            Ok(())
+        } else {
+            Err(GlError::FramebufferNotComplete(self.id, FramebufferStatus::from(status)))
         }
     }
 
@@ -126,11 +324,29 @@ impl Framebuffer {
     }
 
     pub fn link_push(&mut self, texture: Rc<Texture>) {
-        self.quad.meshes[0].diffuse_textures.push(texture);
+        let quad = self.quad.get_mut();
+
+        if texture.get_samples() > 0 {
+            if let Some(quad) = quad {
+                quad.meshes[0].multisample_textures.push(Rc::clone(&texture));
+            }
+            self.linked_multisample_textures.push(texture);
+        } else {
+            if let Some(quad) = quad {
+                quad.meshes[0].diffuse_textures.push(Rc::clone(&texture));
+            }
+            self.linked_textures.push(texture);
+        }
     }
 
     pub fn unlink(&mut self) {
-        self.quad.meshes[0].diffuse_textures.clear();
+        if let Some(quad) = self.quad.get_mut() {
+            quad.meshes[0].diffuse_textures.clear();
+            quad.meshes[0].multisample_textures.clear();
+        }
+
+        self.linked_textures.clear();
+        self.linked_multisample_textures.clear();
     }
 
     // Get output texture at index
@@ -146,37 +362,168 @@ impl Framebuffer {
         self.textures.len()
     }
 
+    // Never clears, contents from previous draws are kept
     pub fn bind(&self) {
+        StateCache::check_context(&self.context, "Framebuffer", self.id);
+        StateCache::bind_framebuffer(self.id);
+    }
+
+    // Clears with the current clear color/depth/stencil values
+    pub fn bind_and_clear(&self, flags: gl::types::GLbitfield) {
+        self.bind();
+
+        if flags != 0 {
+            unsafe {
+                gl::Clear(flags);
+            }
+        }
+    }
+
+    // The clear_ functions take their values per call and don't touch the global clear color,
+    // depth or stencil, so clearing one framebuffer can't change how another is cleared.
+    // They also don't need the framebuffer bound. index is the color attachment,
+    // also when set_draw_buffers left it inactive
+    pub fn clear_color(&self, index: usize, color: [f32; 4]) {
+        self.with_all_draw_buffers(|| unsafe {
+            gl::ClearNamedFramebufferfv(self.id, gl::COLOR, index as i32, color.as_ptr());
+        });
+    }
+
+    // Clears every color attachment in draw_buffers
+    pub fn clear_all_colors(&self, color: [f32; 4]) {
+        self.with_all_draw_buffers(|| unsafe {
+            for index in 0..self.draw_buffers.len() {
+                gl::ClearNamedFramebufferfv(self.id, gl::COLOR, index as i32, color.as_ptr());
+            }
+        });
+    }
+
+    // Color clears address draw buffer slots rather than attachments, so while only some
+    // attachments are active every one is activated for the clear, putting slot i on attachment i
+    fn with_all_draw_buffers(&self, clear: impl FnOnce()) {
+        let partial = self.active_draw_buffers != self.draw_buffers;
+
+        if partial {
+            self.send_draw_buffers(&self.draw_buffers);
+        }
+
+        clear();
+
+        if partial {
+            self.send_draw_buffers(&self.active_draw_buffers);
+        }
+    }
+
+    pub fn clear_depth(&self, depth: f32) {
         unsafe {
-            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            gl::ClearNamedFramebufferfv(self.id, gl::DEPTH, 0, &depth);
         }
     }
 
-    pub fn unbind() {
+    pub fn clear_depth_stencil(&self, depth: f32, stencil: i32) {
+        unsafe {
+            gl::ClearNamedFramebufferfi(self.id, gl::DEPTH_STENCIL, 0, depth, stencil);
+        }
+    }
+
+    // Tells the driver the contents of these color attachments won't be read again,
+    // e.g. after resolving, so tiled GPUs can skip writing them back to memory
+    pub fn invalidate(&self, attachments: &[usize]) {
+        let attachments: Vec<gl::types::GLenum> = attachments.iter()
+            .map(|index| if self.id == 0 { gl::COLOR } else { gl::COLOR_ATTACHMENT0 + *index as u32 })
+            .collect();
+
+        unsafe {
+            gl::InvalidateNamedFramebufferData(self.id, attachments.len() as i32, attachments.as_ptr());
+        }
+    }
+
+    // Same as invalidate for the depth/stencil buffer, typically right after the main pass
+    pub fn invalidate_depth_stencil(&self) {
+        let attachments = if self.id == 0 {
+            [gl::DEPTH, gl::STENCIL]
+        } else {
+            [gl::DEPTH_ATTACHMENT, gl::STENCIL_ATTACHMENT]
+        };
+
         unsafe {
-            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::InvalidateNamedFramebufferData(self.id, attachments.len() as i32, attachments.as_ptr());
         }
     }
 
+    // Binds and clears whatever the pass declared in clear_mask, depth being cleared
+    // to default_clear_depth instead of the global clear depth
+    pub fn bind_pass(&self) {
+        if self.clear_mask & gl::DEPTH_BUFFER_BIT != 0 {
+            self.clear_depth(self.default_clear_depth);
+        }
+
+        self.bind_and_clear(self.clear_mask & !gl::DEPTH_BUFFER_BIT);
+    }
+
+    pub fn unbind() {
+        StateCache::bind_framebuffer(0);
+    }
+
     // Requires shader program bind
+    // Binds without clearing, use bind_and_clear first if the target needs it
     pub fn draw(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
         self.bind();
 
-        self.quad.draw(shader_program)?;
+        let mut quad = self.quad.borrow_mut();
+        let quad = quad.get_or_insert_with(|| {
+            // Create quad model for framebuffer
+            let model_transforms = vec![Matrix4::<f32>::from_translation(vec3(0.0, 0.0, 0.0))];
+            let mut quad: MultiBindModel = create_quad(model_transforms);
+
+            quad.meshes[0].diffuse_textures = self.linked_textures.clone();
+            quad.meshes[0].multisample_textures = self.linked_multisample_textures.clone();
+
+            quad
+        });
+
+        quad.draw(shader_program)?;
 
         Ok(())
     }
 
+    // Size of the attachments, with scale applied
     pub fn get_size(&self) -> (i32, i32) {
         return (self.width, self.height);
     }
 
-    pub fn set_size(&mut self, width: i32, height: i32) -> Result<(), GlError> {
+    // Takes effect on the next set_size
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    pub fn get_samples(&self) -> i32 {
+        self.samples
+    }
+
+    pub fn get_scale(&self) -> f32 {
+        self.scale
+    }
+
+    // Width and height are the full size, the stored scale is applied to them.
+    // A zero or negative size, e.g. from a minimized window, is deferred rather than applied
+    // and the attachments keep their old size until a valid size is given
+    pub fn set_size(&mut self, width: i32, height: i32) -> Result<ResizeResult, GlError> {
+        if width <= 0 || height <= 0 {
+            self.resize_pending = true;
+            return Ok(ResizeResult::Deferred);
+        }
+
+        // Tiny sizes at a low scale would otherwise round down to nothing
+        let width = ((width as f32 * self.scale).round() as i32).max(1);
+        let height = ((height as f32 * self.scale).round() as i32).max(1);
+
         self.width = width;
         self.height = height;
+        self.resize_pending = false;
 
         unsafe {
-            for texture in self.textures.iter() {
+            for texture in self.textures.iter().chain(self.depth_texture.iter()) {
                 texture.resize(width, height)?;
             }
 
@@ -185,7 +532,103 @@ impl Framebuffer {
             }
         }
 
-        Ok(())
+        // Some drivers drop attachments when their storage is re-specified
+        self.reattach();
+        self.check_status()?;
+
+        Ok(ResizeResult::Resized)
+    }
+
+    // Whether the last set_size was deferred and the attachments are out of date
+    pub fn is_resize_pending(&self) -> bool {
+        self.resize_pending
+    }
+
+    // Swaps the color attachment at index, e.g. for ping-ponging history textures.
+    // An index equal to len() adds a new attachment, larger ones are an error
+    pub fn set_attachment(&mut self, index: usize, texture: Rc<Texture>) -> Result<(), GlError> {
+        if index > self.textures.len() {
+            return Err(GlError::InvalidAttachmentIndex(self.id, index, self.textures.len()));
+        }
+
+        let attachment = gl::COLOR_ATTACHMENT0 + index as u32;
+
+        unsafe {
+            gl::NamedFramebufferTexture(self.id, attachment, texture.get_id(), 0);
+        }
+
+        if index == self.textures.len() {
+            self.push_attachment(texture);
+        } else {
+            self.textures[index] = texture;
+        }
+
+        self.check_status()
+    }
+
+    // Stores a texture already attached at the next color attachment and activates it
+    fn push_attachment(&mut self, texture: Rc<Texture>) {
+        let attachment = gl::COLOR_ATTACHMENT0 + self.textures.len() as u32;
+
+        self.textures.push(texture);
+        self.draw_buffers.push(attachment);
+
+        // Undoes the NONE read buffer of a framebuffer that started without colors
+        if self.draw_buffers.len() == 1 {
+            unsafe { gl::NamedFramebufferReadBuffer(self.id, attachment) };
+        }
+
+        self.apply_draw_buffers(self.draw_buffers.clone());
+    }
+
+    // Attaches one face (0-5, in +X, -X, +Y, -Y, +Z, -Z order) of a cubemap as color attachment 0,
+    // so a cubemap can be rendered one face at a time with six view matrices.
+    // The texture isn't stored, so reattach or set_size will restore attachment 0
+    pub fn attach_cubemap_face(&mut self, texture: &Texture, face: u32, level: u32) -> Result<(), GlError> {
+        self.attach_texture_layer(texture, face, level)
+    }
+
+    // Same as attach_cubemap_face for a slice of a 3D texture or a layer of an array texture
+    pub fn attach_texture_layer(&mut self, texture: &Texture, layer: u32, level: u32) -> Result<(), GlError> {
+        unsafe {
+            gl::NamedFramebufferTextureLayer(
+                self.id,
+                gl::COLOR_ATTACHMENT0,
+                texture.get_id(),
+                level as i32,
+                layer as i32
+            );
+        }
+
+        if self.draw_buffers.is_empty() {
+            self.apply_draw_buffers(vec![gl::COLOR_ATTACHMENT0]);
+        }
+
+        self.check_status()
+    }
+
+    // Re-binds all current attachments to the framebuffer
+    pub fn reattach(&self) {
+        unsafe {
+            for (texture, attachment) in self.textures.iter().zip(self.draw_buffers.iter()) {
+                gl::NamedFramebufferTexture(self.id, *attachment, texture.get_id(), 0);
+            }
+
+            if let Some(depth_texture) = &self.depth_texture {
+                let attachment = depth_attachment(depth_texture.get_internal_format());
+
+                gl::NamedFramebufferTexture(self.id, attachment, depth_texture.get_id(), 0);
+            }
+
+            if let Some(rbo) = &self.render_buffer {
+                gl::NamedFramebufferRenderbuffer(
+                    self.id,
+                    depth_attachment(rbo.get_internal_format()),
+                    gl::RENDERBUFFER,
+                    rbo.get_id()
+                );
+            }
+        }
     }
 
     pub fn get_id(&self) -> u32 {
@@ -193,10 +636,163 @@ impl Framebuffer {
     }
 }
 
+// Formats with a stencil part go to the combined attachment so the stencil is attached too
+fn depth_attachment(internal_format: gl::types::GLenum) -> gl::types::GLenum {
+    match internal_format {
+        gl::DEPTH24_STENCIL8 | gl::DEPTH32F_STENCIL8 => gl::DEPTH_STENCIL_ATTACHMENT,
+        _ => gl::DEPTH_ATTACHMENT
+    }
+}
+
 impl Drop for Framebuffer {
     fn drop(&mut self) {
+        if !self.owned { return; }
+
+        StateCache::forget_framebuffer(self.id);
+
         unsafe {
             gl::DeleteFramebuffers(1, &self.id);
         }
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeResult {
+    Resized,
+    // The size was zero, the old attachments are kept
+    Deferred
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DepthAttachment {
+    Texture(gl::types::GLenum),
+    RenderBuffer(gl::types::GLenum)
+}
+
+// Framebuffer with per-attachment formats, e.g. a G-buffer:
+// FramebufferBuilder::new(w, h).color_attachment(gl::RGBA16F).color_attachment(gl::RG16F)
+//     .depth_texture(gl::DEPTH_COMPONENT32F).build()
+// Color attachments are numbered in the order they are added
+#[derive(Debug, Clone)]
+pub struct FramebufferBuilder {
+    width: i32,
+    height: i32,
+    color_formats: Vec<gl::types::GLenum>,
+    depth: Option<DepthAttachment>,
+    samples: i32
+}
+
+impl FramebufferBuilder {
+    pub fn new(width: i32, height: i32) -> FramebufferBuilder {
+        FramebufferBuilder { width, height, color_formats: Vec::new(), depth: None, samples: 0 }
+    }
+
+    pub fn color_attachment(mut self, internal_format: gl::types::GLenum) -> FramebufferBuilder {
+        self.color_formats.push(internal_format);
+        self
+    }
+
+    // Sampleable depth, replaces an earlier depth_renderbuffer
+    pub fn depth_texture(mut self, internal_format: gl::types::GLenum) -> FramebufferBuilder {
+        self.depth = Some(DepthAttachment::Texture(internal_format));
+        self
+    }
+
+    // Depth that is only tested against, replaces an earlier depth_texture
+    pub fn depth_renderbuffer(mut self, internal_format: gl::types::GLenum) -> FramebufferBuilder {
+        self.depth = Some(DepthAttachment::RenderBuffer(internal_format));
+        self
+    }
+
+    // Clamped on build to what every attachment format supports, as with Framebuffer::new_multisample
+    pub fn samples(mut self, samples: i32) -> FramebufferBuilder {
+        self.samples = samples;
+        self
+    }
+
+    pub fn build(self) -> Result<Framebuffer, GlError> {
+        Texture::check_size(self.width, self.height)?;
+
+        let mut framebuffer = Framebuffer::new_default(self.width, self.height);
+
+        if self.samples > 0 {
+            let mut formats: Vec<_> = self.color_formats.iter()
+                .map(|format| (gl::TEXTURE_2D_MULTISAMPLE, *format))
+                .collect();
+
+            match self.depth {
+                Some(DepthAttachment::Texture(format)) => formats.push((gl::TEXTURE_2D_MULTISAMPLE, format)),
+                Some(DepthAttachment::RenderBuffer(format)) => formats.push((gl::RENDERBUFFER, format)),
+                None => ()
+            }
+
+            framebuffer.samples = capabilities::clamp_samples(self.samples, &formats);
+        }
+
+        unsafe {
+            gl::CreateFramebuffers(1, &mut framebuffer.id);
+        }
+
+        // Starts without colors so the read and draw buffers are NONE until one is added
+        framebuffer.gen_textures(0)?;
+        for format in self.color_formats {
+            framebuffer.gen_color_texture(format);
+        }
+
+        match self.depth {
+            Some(DepthAttachment::Texture(format)) => framebuffer.gen_depth_texture_format(format),
+            Some(DepthAttachment::RenderBuffer(format)) => framebuffer.gen_render_buffer_format(format),
+            None => ()
+        }
+
+        framebuffer.check_status()?;
+
+        Ok(framebuffer)
+    }
+}
+
+// Reasons glCheckNamedFramebufferStatus can give for a framebuffer not being complete
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FramebufferStatus {
+    Undefined,
+    IncompleteAttachment,
+    MissingAttachment,
+    IncompleteDrawBuffer,
+    IncompleteReadBuffer,
+    Unsupported,
+    IncompleteMultisample,
+    IncompleteLayerTargets,
+    Unknown(gl::types::GLenum)
+}
+
+impl From<gl::types::GLenum> for FramebufferStatus {
+    fn from(status: gl::types::GLenum) -> Self {
+        match status {
+            gl::FRAMEBUFFER_UNDEFINED => FramebufferStatus::Undefined,
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => FramebufferStatus::IncompleteAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => FramebufferStatus::MissingAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => FramebufferStatus::IncompleteDrawBuffer,
+            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => FramebufferStatus::IncompleteReadBuffer,
+            gl::FRAMEBUFFER_UNSUPPORTED => FramebufferStatus::Unsupported,
+            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => FramebufferStatus::IncompleteMultisample,
+            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => FramebufferStatus::IncompleteLayerTargets,
+            _ => FramebufferStatus::Unknown(status)
+        }
+    }
+}
+
+impl Display for FramebufferStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FramebufferStatus::Undefined => write!(f, "default framebuffer does not exist"),
+            FramebufferStatus::IncompleteAttachment => write!(f, "an attachment is incomplete"),
+            FramebufferStatus::MissingAttachment => write!(f, "no images are attached"),
+            FramebufferStatus::IncompleteDrawBuffer => write!(f, "a draw buffer has no attachment"),
+            FramebufferStatus::IncompleteReadBuffer => write!(f, "the read buffer has no attachment"),
+            FramebufferStatus::Unsupported => write!(f, "attachment formats are not supported together"),
+            FramebufferStatus::IncompleteMultisample => write!(f, "attachments have different sample counts"),
+            FramebufferStatus::IncompleteLayerTargets => write!(f, "attachments are not all layered"),
+            FramebufferStatus::Unknown(status) => write!(f, "unknown status {:#X}", status)
+        }
+    }
 }
\ No newline at end of file
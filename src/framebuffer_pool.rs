@@ -0,0 +1,143 @@
+use std::{cell::RefCell, ops::{Deref, DerefMut}, rc::{Rc, Weak}};
+use super::{Framebuffer, FramebufferBuilder, GlError, gl};
+
+// What a pooled framebuffer has to match to be handed out again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferDesc {
+    pub width: i32,
+    pub height: i32,
+    pub color_attachments: usize,
+    // Internal format of every color attachment, gl::RGBA16F like Framebuffer::new
+    pub color_format: gl::types::GLenum,
+    // gl::DEPTH24_STENCIL8 render buffer, as with Framebuffer::new
+    pub render_buffer: bool,
+    // 0 for single sampled, otherwise the requested (not clamped) sample count
+    pub samples: i32
+}
+
+type FreeList = RefCell<Vec<(FramebufferDesc, Framebuffer)>>;
+
+// Recycles transient framebuffers, e.g. for post-process chains where each effect only needs its
+// target until the next one has read it. Returned framebuffers keep their contents, so passes
+// acquiring one should clear it. They are filed under what they have when returned, so resizing
+// one is fine, and ones with attachments no desc can describe are deleted instead.
+// Textures taken with get_link stay shared with the framebuffer, so they shouldn't be kept
+// after the PooledFramebuffer is dropped
+pub struct FramebufferPool {
+    free: Rc<FreeList>
+}
+
+impl FramebufferPool {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> FramebufferPool {
+        FramebufferPool { free: Rc::new(RefCell::new(Vec::new())) }
+    }
+
+    // Reuses a free framebuffer matching desc, or creates one if there is none
+    pub fn acquire(&self, desc: FramebufferDesc) -> Result<PooledFramebuffer, GlError> {
+        let reused = {
+            let mut free = self.free.borrow_mut();
+            free.iter().position(|(free_desc, _)| *free_desc == desc).map(|index| free.swap_remove(index).1)
+        };
+
+        let framebuffer = match reused {
+            Some(framebuffer) => framebuffer,
+            None => {
+                let mut builder = FramebufferBuilder::new(desc.width, desc.height).samples(desc.samples);
+                for _ in 0..desc.color_attachments {
+                    builder = builder.color_attachment(desc.color_format);
+                }
+                if desc.render_buffer {
+                    builder = builder.depth_renderbuffer(gl::DEPTH24_STENCIL8);
+                }
+
+                builder.build()?
+            }
+        };
+
+        Ok(PooledFramebuffer {
+            framebuffer: Some(framebuffer),
+            desc,
+            pool: Rc::downgrade(&self.free)
+        })
+    }
+
+    // Deletes every framebuffer not currently acquired, e.g. after a window resize
+    // left framebuffers of the old size that will never match again
+    pub fn clear(&self) {
+        self.free.borrow_mut().clear();
+    }
+
+    // Framebuffers waiting to be reused
+    pub fn free_count(&self) -> usize {
+        self.free.borrow().len()
+    }
+}
+
+// Goes back to its pool when dropped, or is deleted if the pool no longer exists
+pub struct PooledFramebuffer {
+    framebuffer: Option<Framebuffer>,
+    desc: FramebufferDesc,
+    pool: Weak<FreeList>
+}
+
+impl PooledFramebuffer {
+    pub fn get_desc(&self) -> FramebufferDesc {
+        self.desc
+    }
+}
+
+impl Deref for PooledFramebuffer {
+    type Target = Framebuffer;
+
+    fn deref(&self) -> &Framebuffer {
+        self.framebuffer.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledFramebuffer {
+    fn deref_mut(&mut self) -> &mut Framebuffer {
+        self.framebuffer.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledFramebuffer {
+    fn drop(&mut self) {
+        if let (Some(mut framebuffer), Some(pool)) = (self.framebuffer.take(), self.pool.upgrade()) {
+            // Changed through DerefMut, e.g. resized, so it's filed under what it is now
+            let desc = match current_desc(&framebuffer, self.desc) {
+                Some(desc) => desc,
+                None => return
+            };
+
+            // Linked inputs and pass settings belong to whoever acquired it, the next user sets their own
+            framebuffer.unlink();
+            framebuffer.set_draw_buffers(&(0..desc.color_attachments).collect::<Vec<_>>());
+            framebuffer.clear_mask = 0;
+            framebuffer.default_clear_depth = 1.0;
+            framebuffer.set_scale(1.0);
+
+            pool.borrow_mut().push((desc, framebuffer));
+        }
+    }
+}
+
+// The desc the framebuffer matches now, starting from the one it was acquired with. None if
+// it has attachments a desc can't describe, such as a depth texture or colors of another format
+fn current_desc(framebuffer: &Framebuffer, acquired: FramebufferDesc) -> Option<FramebufferDesc> {
+    let colors = framebuffer.get_link();
+    let render_buffer = match &framebuffer.render_buffer {
+        Some(render_buffer) if render_buffer.get_internal_format() == gl::DEPTH24_STENCIL8 => true,
+        Some(_) => return None,
+        None => false
+    };
+
+    if framebuffer.get_depth_texture().is_some()
+        || colors.iter().any(|texture| texture.get_internal_format() != acquired.color_format) {
+        return None;
+    }
+
+    let (width, height) = framebuffer.get_size();
+
+    Some(FramebufferDesc { width, height, color_attachments: colors.len(), render_buffer, ..acquired })
+}
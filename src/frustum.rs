@@ -0,0 +1,68 @@
+use cgmath::{Matrix, Matrix4, Vector3, Vector4, InnerSpace, vec3};
+use super::{Aabb, Mesh};
+
+// View frustum as six inward facing planes (normal in xyz, distance in w),
+// in whatever space the matrix it was built from maps out of
+pub struct Frustum {
+    planes: [Vector4<f32>; 6]
+}
+
+impl Frustum {
+    // Gribb/Hartmann extraction, pass projection * view for world space planes
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Frustum {
+        let rows = [matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3)];
+        let mut planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[3] + rows[2],
+            rows[3] - rows[2]
+        ];
+
+        // Normalized so sphere tests can compare against the radius directly
+        for plane in planes.iter_mut() {
+            *plane /= plane.truncate().magnitude();
+        }
+
+        Frustum { planes }
+    }
+
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+
+    // Tests the corner furthest along each plane's normal, can give false positives
+    // near frustum corners but never culls anything visible
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let corner = vec3(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z }
+            );
+
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    // Two level test for one instance of a mesh, the sphere rejects most meshes cheaply
+    // and only those it can't reject pay for the AABB. Meshes without bounds are kept
+    pub fn is_mesh_visible(&self, mesh: &Mesh, transform: &Matrix4<f32>) -> bool {
+        if let Some((center, radius)) = mesh.bounding_sphere() {
+            // Radius scaled by the largest axis so non-uniform scale stays conservative
+            let scale = transform.x.truncate().magnitude()
+                .max(transform.y.truncate().magnitude())
+                .max(transform.z.truncate().magnitude());
+
+            if !self.intersects_sphere((transform * center.extend(1.0)).truncate(), radius * scale) {
+                return false;
+            }
+        }
+
+        match mesh.get_aabb() {
+            Some(aabb) => self.intersects_aabb(&aabb.transform(transform)),
+            None => true
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use super::gl;
+use std::{fs::File, io::{BufWriter, Write}, path::Path};
+use super::{GlError, gl};
 
 pub struct GlImage {
     pub bytes: Vec<u8>,
@@ -37,4 +38,232 @@ impl GlImage {
 
         result
     }
-}
\ No newline at end of file
+
+    // Writes 8 bit pixels, rows top to bottom, as an uncompressed PNG. Meant for debug output,
+    // so the deflate stream only uses stored blocks and files are about the size of the pixels
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), GlError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_png(&mut file)?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    // Same as save_png, into any writer
+    pub fn write_png<W: Write>(&self, mut writer: W) -> Result<(), GlError> {
+        let (channels, color_type) = match self.data_format {
+            gl::RED => (1, 0),
+            gl::RG => (2, 4),
+            gl::RGB => (3, 2),
+            gl::RGBA => (4, 6),
+            _ => return Err(GlError::UnsupportedPixelFormat(self.data_format))
+        };
+        let stride = self.width as usize * channels;
+        let needed = stride * self.height as usize;
+
+        if self.bytes.len() < needed {
+            return Err(GlError::ImageDataTooShort(self.bytes.len(), needed));
+        }
+
+        // Every row starts with filter type 0 (none)
+        let mut raw = Vec::with_capacity((stride + 1) * self.height as usize);
+        for row in self.bytes.chunks_exact(stride).take(self.height as usize) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+        writer.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'])?;
+        write_png_chunk(&mut writer, b"IHDR", &header)?;
+        write_png_chunk(&mut writer, b"IDAT", &zlib_stored(&raw))?;
+        write_png_chunk(&mut writer, b"IEND", &[])?;
+
+        Ok(())
+    }
+}
+
+fn write_png_chunk<W: Write>(writer: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(chunk_type)?;
+    writer.write_all(data)?;
+
+    let crc = !crc32(crc32(0xFFFFFFFF, chunk_type), data);
+    writer.write_all(&crc.to_be_bytes())
+}
+
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+
+    crc
+}
+
+// zlib stream of uncompressed deflate blocks, each holding at most 65535 bytes
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+
+        out.push(blocks.peek().is_none() as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Chunks of a PNG with their CRCs checked, as (type, data)
+    fn read_chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']);
+
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let chunk_type: [u8; 4] = rest[4..8].try_into().unwrap();
+            let data = &rest[8..8 + len];
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+
+            assert_eq!(crc, !crc32(crc32(0xFFFFFFFF, &chunk_type), data), "bad CRC on {:?}", chunk_type);
+            chunks.push((chunk_type, data.to_vec()));
+            rest = &rest[12 + len..];
+        }
+
+        chunks
+    }
+
+    // Inflates a zlib stream made only of stored blocks, checking the Adler-32 at the end
+    fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(stream[0] & 0x0F, 8, "not deflate");
+        assert_eq!(((stream[0] as u16) << 8 | stream[1] as u16) % 31, 0, "bad zlib header check");
+
+        let mut data = Vec::new();
+        let mut position = 2;
+
+        loop {
+            let header = stream[position];
+            assert_eq!(header >> 1 & 0b11, 0, "not a stored block");
+
+            let len = u16::from_le_bytes([stream[position + 1], stream[position + 2]]);
+            let nlen = u16::from_le_bytes([stream[position + 3], stream[position + 4]]);
+            assert_eq!(len, !nlen);
+
+            position += 5;
+            data.extend_from_slice(&stream[position..position + len as usize]);
+            position += len as usize;
+
+            if header & 1 == 1 {
+                break;
+            }
+        }
+
+        let (mut a, mut b) = (1u32, 0u32);
+        for byte in data.iter() {
+            a = (a + *byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(&stream[position..], &((b << 16) | a).to_be_bytes());
+
+        data
+    }
+
+    // Decodes a PNG written by write_png back into its pixels
+    fn decode(png: &[u8]) -> (u32, u32, u8, Vec<u8>) {
+        let chunks = read_chunks(png);
+        let header = &chunks[0].1;
+
+        assert_eq!(&chunks[0].0, b"IHDR");
+        assert_eq!(&chunks.last().unwrap().0, b"IEND");
+
+        let width = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        assert_eq!(&header[8..], &[8, header[9], 0, 0, 0]);
+
+        let stream: Vec<u8> = chunks.iter()
+            .filter(|(chunk_type, _)| chunk_type == b"IDAT")
+            .flat_map(|(_, data)| data.iter().copied())
+            .collect();
+        let raw = inflate_stored(&stream);
+        let stride = raw.len() / height as usize;
+
+        let mut pixels = Vec::new();
+        for row in raw.chunks_exact(stride) {
+            assert_eq!(row[0], 0, "rows should be unfiltered");
+            pixels.extend_from_slice(&row[1..]);
+        }
+
+        (width, height, header[9], pixels)
+    }
+
+    fn image(width: i32, height: i32, data_format: gl::types::GLenum, channels: usize) -> GlImage {
+        GlImage {
+            bytes: (0..width as usize * height as usize * channels).map(|i| (i * 7 % 251) as u8).collect(),
+            internal_format: gl::RGBA8,
+            data_format,
+            width,
+            height
+        }
+    }
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(!crc32(0xFFFFFFFF, b"123456789"), 0xCBF43926);
+        assert_eq!(&zlib_stored(b"Wikipedia")[16..], &0x11E60398u32.to_be_bytes());
+    }
+
+    #[test]
+    fn png_round_trips() {
+        for (data_format, channels, color_type) in [(gl::RED, 1, 0), (gl::RG, 2, 4), (gl::RGB, 3, 2), (gl::RGBA, 4, 6)] {
+            let image = image(5, 3, data_format, channels);
+            let mut png = Vec::new();
+            image.write_png(&mut png).unwrap();
+
+            assert_eq!(decode(&png), (5, 3, color_type, image.bytes));
+        }
+    }
+
+    #[test]
+    fn png_round_trips_over_several_blocks() {
+        let image = image(200, 100, gl::RGBA, 4);
+        let mut png = Vec::new();
+        image.write_png(&mut png).unwrap();
+
+        assert_eq!(decode(&png).3, image.bytes);
+    }
+
+    #[test]
+    fn png_rejects_short_data() {
+        let mut image = image(4, 4, gl::RGB, 3);
+        image.bytes.pop();
+
+        assert!(matches!(image.write_png(Vec::new()), Err(GlError::ImageDataTooShort(47, 48))));
+    }
+}
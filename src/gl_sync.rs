@@ -0,0 +1,49 @@
+use super::gl;
+
+// Fence inserted into the command stream, signaled once the GPU has finished every command
+// before it. Used to know when a persistently mapped region can be written again
+pub struct GlSync {
+    sync: gl::types::GLsync
+}
+
+impl GlSync {
+    pub fn insert() -> GlSync {
+        let sync = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+
+        GlSync { sync }
+    }
+
+    // Blocks for up to timeout_ns, flushing so the fence is guaranteed to be reached.
+    // Returns whether the fence was signaled in time
+    pub fn wait(&self, timeout_ns: u64) -> bool {
+        let result = unsafe { gl::ClientWaitSync(self.sync, gl::SYNC_FLUSH_COMMANDS_BIT, timeout_ns) };
+
+        result == gl::ALREADY_SIGNALED || result == gl::CONDITION_SATISFIED
+    }
+
+    // Doesn't block or flush
+    pub fn is_signaled(&self) -> bool {
+        let mut status = 0;
+
+        unsafe {
+            gl::GetSynciv(self.sync, gl::SYNC_STATUS, 1, std::ptr::null_mut(), &mut status);
+        }
+
+        status == gl::SIGNALED as i32
+    }
+
+    // Makes the server wait instead of the CPU, e.g. for syncing between shared contexts
+    pub fn wait_gpu(&self) {
+        unsafe {
+            gl::WaitSync(self.sync, 0, gl::TIMEOUT_IGNORED);
+        }
+    }
+}
+
+impl Drop for GlSync {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteSync(self.sync);
+        }
+    }
+}
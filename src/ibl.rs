@@ -0,0 +1,459 @@
+use std::rc::Rc;
+use cgmath::{Matrix4, Point3, Vector3, Deg, perspective, vec3};
+use super::{Buffer, Framebuffer, GlError, ShaderCodeBundle, ShaderProgram, Texture, VertexArray, StateCache, gl};
+
+const CUBE_VERTEX: &str = r#"
+#version 460 core
+layout (location = 0) in vec3 aPos;
+
+uniform mat4 viewProjection;
+
+out vec3 localPos;
+
+void main() {
+    localPos = aPos;
+    gl_Position = viewProjection * vec4(aPos, 1.0);
+}
+"#;
+
+const EQUIRECTANGULAR_FRAGMENT: &str = r#"
+#version 460 core
+in vec3 localPos;
+
+uniform sampler2D equirectangular;
+
+out vec4 FragColor;
+
+// atan gives longitude over [-PI, PI], asin latitude over [-PI / 2, PI / 2]
+const vec2 invAtan = vec2(0.1591, 0.3183);
+
+void main() {
+    vec3 direction = normalize(localPos);
+    vec2 uv = vec2(atan(direction.z, direction.x), asin(direction.y)) * invAtan + 0.5;
+
+    FragColor = vec4(texture(equirectangular, uv).rgb, 1.0);
+}
+"#;
+
+const IRRADIANCE_FRAGMENT: &str = r#"
+#version 460 core
+in vec3 localPos;
+
+uniform samplerCube environment;
+
+out vec4 FragColor;
+
+const float PI = 3.14159265359;
+
+void main() {
+    vec3 N = normalize(localPos);
+    vec3 up = abs(N.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+    vec3 right = normalize(cross(up, N));
+    up = normalize(cross(N, right));
+
+    vec3 irradiance = vec3(0.0);
+    float sampleDelta = 0.025;
+    float sampleCount = 0.0;
+
+    // Convolve the hemisphere around N
+    for (float phi = 0.0; phi < 2.0 * PI; phi += sampleDelta) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += sampleDelta) {
+            vec3 tangentSample = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 sampleVec = tangentSample.x * right + tangentSample.y * up + tangentSample.z * N;
+
+            irradiance += texture(environment, sampleVec).rgb * cos(theta) * sin(theta);
+            sampleCount++;
+        }
+    }
+
+    FragColor = vec4(PI * irradiance / sampleCount, 1.0);
+}
+"#;
+
+const IMPORTANCE_SAMPLE: &str = r#"
+const float PI = 3.14159265359;
+
+float radicalInverseVdC(uint bits) {
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return float(bits) * 2.3283064365386963e-10;
+}
+
+vec2 hammersley(uint i, uint n) {
+    return vec2(float(i) / float(n), radicalInverseVdC(i));
+}
+
+vec3 importanceSampleGGX(vec2 Xi, vec3 N, float roughness) {
+    float a = roughness * roughness;
+
+    float phi = 2.0 * PI * Xi.x;
+    float cosTheta = sqrt((1.0 - Xi.y) / (1.0 + (a * a - 1.0) * Xi.y));
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+
+    vec3 H = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+
+    vec3 up = abs(N.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, N));
+    vec3 bitangent = cross(N, tangent);
+
+    return normalize(tangent * H.x + bitangent * H.y + N * H.z);
+}
+"#;
+
+const PREFILTER_FRAGMENT: &str = r#"
+in vec3 localPos;
+
+uniform samplerCube environment;
+uniform float roughness;
+
+out vec4 FragColor;
+
+void main() {
+    // Assumes view direction equals the normal
+    vec3 N = normalize(localPos);
+    vec3 V = N;
+
+    const uint SAMPLE_COUNT = 1024u;
+    vec3 prefiltered = vec3(0.0);
+    float totalWeight = 0.0;
+
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec2 Xi = hammersley(i, SAMPLE_COUNT);
+        vec3 H = importanceSampleGGX(Xi, N, roughness);
+        vec3 L = normalize(2.0 * dot(V, H) * H - V);
+
+        float NdotL = max(dot(N, L), 0.0);
+        if (NdotL > 0.0) {
+            prefiltered += texture(environment, L).rgb * NdotL;
+            totalWeight += NdotL;
+        }
+    }
+
+    FragColor = vec4(prefiltered / totalWeight, 1.0);
+}
+"#;
+
+const FULLSCREEN_VERTEX: &str = r#"
+#version 460 core
+out vec2 texCoord;
+
+// Single triangle covering the screen, no vertex buffer needed
+void main() {
+    texCoord = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    gl_Position = vec4(texCoord * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const BRDF_FRAGMENT: &str = r#"
+in vec2 texCoord;
+
+out vec2 FragColor;
+
+float geometrySchlickGGX(float NdotV, float roughness) {
+    // IBL uses a different k than direct lighting
+    float k = (roughness * roughness) / 2.0;
+
+    return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+float geometrySmith(vec3 N, vec3 V, vec3 L, float roughness) {
+    float NdotV = max(dot(N, V), 0.0);
+    float NdotL = max(dot(N, L), 0.0);
+
+    return geometrySchlickGGX(NdotV, roughness) * geometrySchlickGGX(NdotL, roughness);
+}
+
+vec2 integrateBRDF(float NdotV, float roughness) {
+    vec3 V = vec3(sqrt(1.0 - NdotV * NdotV), 0.0, NdotV);
+    vec3 N = vec3(0.0, 0.0, 1.0);
+
+    float A = 0.0;
+    float B = 0.0;
+
+    const uint SAMPLE_COUNT = 1024u;
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec2 Xi = hammersley(i, SAMPLE_COUNT);
+        vec3 H = importanceSampleGGX(Xi, N, roughness);
+        vec3 L = normalize(2.0 * dot(V, H) * H - V);
+
+        float NdotL = max(L.z, 0.0);
+        float NdotH = max(H.z, 0.0);
+        float VdotH = max(dot(V, H), 0.0);
+
+        if (NdotL > 0.0) {
+            float G = geometrySmith(N, V, L, roughness);
+            float G_Vis = (G * VdotH) / (NdotH * NdotV);
+            float Fc = pow(1.0 - VdotH, 5.0);
+
+            A += (1.0 - Fc) * G_Vis;
+            B += Fc * G_Vis;
+        }
+    }
+
+    return vec2(A, B) / float(SAMPLE_COUNT);
+}
+
+void main() {
+    FragColor = integrateBRDF(texCoord.x, texCoord.y);
+}
+"#;
+
+// Precomputed image based lighting maps for PBR shading
+pub struct Ibl {
+    // Diffuse, sampled with the surface normal
+    pub irradiance: Rc<Texture>,
+    // Specular, each mip level is a higher roughness from 0.0 to 1.0
+    pub prefiltered: Rc<Texture>,
+    // Scale (r) and bias (g) to F0, indexed by (NdotV, roughness)
+    pub brdf_lut: Rc<Texture>
+}
+
+impl Ibl {
+    // Sizes that look fine for most environments
+    pub fn new(environment: &Texture) -> Result<Ibl, GlError> {
+        Ibl::with_sizes(environment, 32, 128, 5, 512)
+    }
+
+    // Renders all three maps, GL viewport, framebuffer, depth test and culling are restored after
+    pub fn with_sizes(
+        environment: &Texture,
+        irradiance_size: i32,
+        prefiltered_size: i32,
+        prefiltered_levels: i32,
+        brdf_lut_size: i32
+    ) -> Result<Ibl, GlError> {
+        let cube_shader = |fragment: String| ShaderProgram::new(ShaderCodeBundle {
+            vertex: Some(CUBE_VERTEX.to_owned()),
+            fragment: Some(fragment),
+            ..Default::default()
+        });
+
+        let irradiance_shader = cube_shader(IRRADIANCE_FRAGMENT.to_owned())?;
+        let prefilter_shader = cube_shader(format!("#version 460 core\n{}{}", IMPORTANCE_SAMPLE, PREFILTER_FRAGMENT))?;
+        let brdf_shader = ShaderProgram::new(ShaderCodeBundle {
+            vertex: Some(FULLSCREEN_VERTEX.to_owned()),
+            fragment: Some(format!("#version 460 core\n{}{}", IMPORTANCE_SAMPLE, BRDF_FRAGMENT)),
+            ..Default::default()
+        })?;
+
+        let irradiance = Texture::new_cubemap(irradiance_size, gl::RGBA16F, 1);
+        let prefiltered = Texture::new_cubemap(prefiltered_size, gl::RGBA16F, prefiltered_levels);
+        let brdf_lut = Texture::new_immutable(brdf_lut_size, brdf_lut_size, gl::RG16F, 1);
+
+        unsafe {
+            gl::TextureParameteri(brdf_lut.get_id(), gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(brdf_lut.get_id(), gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        }
+
+        let capture = Capture::begin();
+        let (framebuffer, cube_array) = (&capture.framebuffer, &capture.cube_array);
+
+        environment.ready_texture(0);
+
+        // Irradiance
+        irradiance_shader.use_program();
+        irradiance_shader.set_int("environment", 0)?;
+        Ibl::render_cube(framebuffer, &irradiance_shader, &irradiance, cube_array, irradiance_size, 0)?;
+
+        // Prefiltered specular, one roughness per mip
+        prefilter_shader.use_program();
+        prefilter_shader.set_int("environment", 0)?;
+        for level in 0..prefiltered_levels {
+            let roughness = level as f32 / (prefiltered_levels - 1).max(1) as f32;
+            let size = (prefiltered_size >> level).max(1);
+
+            prefilter_shader.set_float("roughness", roughness)?;
+            Ibl::render_cube(framebuffer, &prefilter_shader, &prefiltered, cube_array, size, level)?;
+        }
+
+        // BRDF LUT
+        brdf_shader.use_program();
+        unsafe {
+            gl::NamedFramebufferTexture(framebuffer.get_id(), gl::COLOR_ATTACHMENT0, brdf_lut.get_id(), 0);
+            gl::Viewport(0, 0, brdf_lut_size, brdf_lut_size);
+        }
+        framebuffer.check_status()?;
+        VertexArray::new().draw_arrays(gl::TRIANGLES, 0, 3, 1);
+
+        drop(capture);
+
+        Ok(Ibl {
+            irradiance: Rc::new(irradiance),
+            prefiltered: Rc::new(prefiltered),
+            brdf_lut: Rc::new(brdf_lut)
+        })
+    }
+
+    // Projects an equirectangular (latitude/longitude) map, e.g. loaded from an .hdr file,
+    // onto a float cubemap with a full mip chain so it can be passed straight to Ibl::new
+    pub fn equirectangular_to_cubemap(equirectangular: &Texture, size: i32) -> Result<Texture, GlError> {
+        let shader_program = ShaderProgram::new(ShaderCodeBundle {
+            vertex: Some(CUBE_VERTEX.to_owned()),
+            fragment: Some(EQUIRECTANGULAR_FRAGMENT.to_owned()),
+            ..Default::default()
+        })?;
+
+        let levels = 32 - (size.max(1) as u32).leading_zeros() as i32;
+        let cubemap = Texture::new_cubemap(size, gl::RGBA16F, levels);
+
+        let capture = Capture::begin();
+
+        equirectangular.ready_texture(0);
+        shader_program.use_program();
+        shader_program.set_int("equirectangular", 0)?;
+        Ibl::render_cube(&capture.framebuffer, &shader_program, &cubemap, &capture.cube_array, size, 0)?;
+
+        drop(capture);
+
+        unsafe {
+            gl::GenerateTextureMipmap(cubemap.get_id());
+        }
+
+        Ok(cubemap)
+    }
+
+    // Requires shader program bind
+    fn render_cube(
+        framebuffer: &Framebuffer,
+        shader_program: &ShaderProgram,
+        target: &Texture,
+        cube_array: &VertexArray,
+        size: i32,
+        level: i32
+    ) -> Result<(), GlError> {
+        let projection = perspective(Deg(90.0), 1.0, 0.1, 10.0);
+
+        unsafe {
+            gl::Viewport(0, 0, size, size);
+        }
+
+        for (face, view) in Ibl::capture_views().iter().enumerate() {
+            unsafe {
+                gl::NamedFramebufferTextureLayer(framebuffer.get_id(), gl::COLOR_ATTACHMENT0, target.get_id(), level, face as i32);
+            }
+            framebuffer.check_status()?;
+
+            shader_program.set_mat4("viewProjection", &(projection * view))?;
+            cube_array.draw_arrays(gl::TRIANGLES, 0, 36, 1);
+        }
+
+        Ok(())
+    }
+
+    // Views from the origin towards each cubemap face, in GL face order (+X, -X, +Y, -Y, +Z, -Z)
+    pub fn capture_views() -> [Matrix4<f32>; 6] {
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let look = |direction: Vector3<f32>, up: Vector3<f32>| {
+            Matrix4::look_at_rh(origin, Point3::new(direction.x, direction.y, direction.z), up)
+        };
+
+        [
+            look(vec3(1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+            look(vec3(-1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+            look(vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0)),
+            look(vec3(0.0, -1.0, 0.0), vec3(0.0, 0.0, -1.0)),
+            look(vec3(0.0, 0.0, 1.0), vec3(0.0, -1.0, 0.0)),
+            look(vec3(0.0, 0.0, -1.0), vec3(0.0, -1.0, 0.0))
+        ]
+    }
+
+    // Unit cube as 12 triangles, culling is disabled so winding doesn't matter
+    fn cube_vertices() -> Vec<Vector3<f32>> {
+        let corners = [
+            vec3(-1.0, -1.0, -1.0), vec3(1.0, -1.0, -1.0), vec3(1.0, 1.0, -1.0), vec3(-1.0, 1.0, -1.0),
+            vec3(-1.0, -1.0, 1.0), vec3(1.0, -1.0, 1.0), vec3(1.0, 1.0, 1.0), vec3(-1.0, 1.0, 1.0)
+        ];
+        let faces = [
+            [0, 1, 2, 3], [5, 4, 7, 6], // Back, front
+            [4, 0, 3, 7], [1, 5, 6, 2], // Left, right
+            [3, 2, 6, 7], [4, 5, 1, 0]  // Top, bottom
+        ];
+
+        faces
+            .iter()
+            .flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d])
+            .map(|index| corners[index])
+            .collect()
+    }
+}
+
+// Framebuffer and cube used to render into cubemap faces, along with the GL state
+// they change so it can be restored on drop, also when a capture errors part way
+struct Capture {
+    framebuffer: Framebuffer,
+    cube_array: VertexArray,
+    _cube_buffer: Buffer<Vector3<f32>>,
+    previous_draw_framebuffer: i32,
+    previous_read_framebuffer: i32,
+    previous_viewport: [i32; 4],
+    depth_test: bool,
+    cull_face: bool,
+    seamless: bool
+}
+
+impl Capture {
+    fn begin() -> Capture {
+        let mut framebuffer_id = 0;
+        let mut previous_draw_framebuffer = 0;
+        let mut previous_read_framebuffer = 0;
+        let mut previous_viewport = [0; 4];
+        let depth_test;
+        let cull_face;
+        let seamless;
+
+        unsafe {
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_draw_framebuffer);
+            gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_framebuffer);
+            gl::GetIntegerv(gl::VIEWPORT, previous_viewport.as_mut_ptr());
+            depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+            cull_face = StateCache::is_cull_face();
+            seamless = gl::IsEnabled(gl::TEXTURE_CUBE_MAP_SEAMLESS) == gl::TRUE;
+
+            gl::Disable(gl::DEPTH_TEST);
+            StateCache::set_cull_face(false);
+            gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+
+            gl::CreateFramebuffers(1, &mut framebuffer_id);
+        }
+
+        // Attachments change for every face, so the framebuffer's own texture handling isn't used
+        let framebuffer = Framebuffer::from_raw(framebuffer_id, 0, 0, true);
+        framebuffer.bind();
+
+        let (mut cube_array, mut cube_buffer) = (VertexArray::new(), Buffer::new());
+        cube_array.add_vertex_buffer(&mut cube_buffer);
+        cube_array.add_attrib(&mut cube_buffer, 3, 0, gl::FLOAT);
+        cube_buffer.set_data(Ibl::cube_vertices());
+
+        Capture {
+            framebuffer,
+            cube_array,
+            _cube_buffer: cube_buffer,
+            previous_draw_framebuffer,
+            previous_read_framebuffer,
+            previous_viewport,
+            depth_test,
+            cull_face,
+            seamless
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let viewport = self.previous_viewport;
+
+        unsafe {
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.previous_draw_framebuffer as u32);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.previous_read_framebuffer as u32);
+            StateCache::invalidate_framebuffer();
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            if self.depth_test { gl::Enable(gl::DEPTH_TEST); }
+            if self.cull_face { StateCache::set_cull_face(true); }
+            if !self.seamless { gl::Disable(gl::TEXTURE_CUBE_MAP_SEAMLESS); }
+        }
+    }
+}
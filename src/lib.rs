@@ -12,8 +12,32 @@ mod framebuffer;
 mod render_buffer;
 mod render_pipeline;
 mod gl_image;
+mod lod;
+mod render_state;
+mod aabb;
+mod debug_lines;
+mod shader_variant;
+mod ibl;
+mod vertex_layout;
+mod pending_shader_program;
+mod state_cache;
+mod material_table;
+mod gl_sync;
+mod skeleton;
+mod animation;
+mod frustum;
+mod render_stats;
+mod framebuffer_pool;
 
 pub mod model_utils;
+pub mod capabilities;
+// Generated bindings, only the lints they trip are allowed
+#[allow(
+    clippy::missing_safety_doc,
+    clippy::missing_transmute_annotations,
+    clippy::too_many_arguments,
+    clippy::unused_unit
+)]
 pub mod gl {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
@@ -32,6 +56,22 @@ pub use framebuffer::*;
 pub use render_buffer::*;
 pub use render_pipeline::*;
 pub use gl_image::*;
+pub use lod::*;
+pub use render_state::*;
+pub use aabb::*;
+pub use debug_lines::*;
+pub use shader_variant::*;
+pub use ibl::*;
+pub use vertex_layout::*;
+pub use pending_shader_program::*;
+pub use state_cache::*;
+pub use material_table::*;
+pub use gl_sync::*;
+pub use skeleton::*;
+pub use animation::*;
+pub use frustum::*;
+pub use render_stats::*;
+pub use framebuffer_pool::*;
 
 // TODO: Implement transparency (see if qsort is fast enough to do it each frame for each model of the scene?)
 // TODO: Add simple and efficient lighting to everything (do serious research when it comes to doing this on forward and deffered pipelines)
@@ -0,0 +1,51 @@
+// Index ranges of decreasing detail for a mesh, level 0 being the most detailed.
+// Each level is used until the camera distance reaches its threshold
+pub struct Lod {
+    levels: Vec<LodLevel>,
+    active: usize
+}
+
+pub struct LodLevel {
+    pub buffer_offset: usize,
+    pub buffer_count: i32,
+    // Max camera distance this level is drawn at, last level is used past all thresholds
+    pub max_distance: f32
+}
+
+impl Lod {
+    pub fn new(levels: Vec<LodLevel>) -> Lod {
+        Lod {
+            levels,
+            active: 0
+        }
+    }
+
+    pub fn push(&mut self, buffer_offset: usize, buffer_count: i32, max_distance: f32) {
+        self.levels.push(LodLevel { buffer_offset, buffer_count, max_distance });
+    }
+
+    // Picks the first level whose threshold is not yet reached
+    pub fn select(&mut self, camera_distance: f32) {
+        self.active = self.levels
+            .iter()
+            .position(|level| camera_distance < level.max_distance)
+            .unwrap_or(self.levels.len().saturating_sub(1));
+    }
+
+    pub fn get_active(&self) -> Option<&LodLevel> {
+        self.levels.get(self.active)
+    }
+
+    pub fn get_active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn get_levels(&self) -> &Vec<LodLevel> {
+        &self.levels
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+}
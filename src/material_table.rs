@@ -0,0 +1,79 @@
+use std::rc::Rc;
+use super::{AlphaMode, Buffer, Mesh, ShaderProgram, Texture, GlError, gl};
+
+// std430 layout of one entry, matches:
+// struct MaterialData { vec4 diffuse; vec4 specular; vec4 emissive; float shininess;
+//     float alphaCutoff; int diffuseLayer; int normalLayer; };
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct MaterialData {
+    pub diffuse: [f32; 4],
+    pub specular: [f32; 4],
+    // w is the emissive strength
+    pub emissive: [f32; 4],
+    pub shininess: f32,
+    pub alpha_cutoff: f32,
+    // Layers in the table's array texture, -1 for none
+    pub diffuse_layer: i32,
+    pub normal_layer: i32
+}
+
+impl MaterialData {
+    // Takes the mesh's constant values, texture layers have to be given since a mesh's
+    // textures aren't part of an array texture
+    pub fn from_mesh(mesh: &Mesh, diffuse_layer: i32, normal_layer: i32) -> MaterialData {
+        let alpha_cutoff = match mesh.alpha_mode {
+            AlphaMode::Mask(cutoff) => cutoff,
+            _ => 0.0
+        };
+
+        MaterialData {
+            diffuse: mesh.diffuse.extend(1.0).into(),
+            specular: mesh.specular.extend(1.0).into(),
+            emissive: mesh.emissive.extend(mesh.emissive_strength).into(),
+            shininess: mesh.shininess,
+            alpha_cutoff,
+            diffuse_layer,
+            normal_layer
+        }
+    }
+}
+
+// Materials indexed per instance through the model's material id attribute,
+// see Model::enable_instance_materials
+pub struct MaterialTable {
+    buffer: Buffer<MaterialData>,
+    // Shared array texture the layers index into, bound as sampler2DArray
+    pub texture_array: Option<Rc<Texture>>
+}
+
+impl MaterialTable {
+    pub fn new(materials: Vec<MaterialData>, texture_array: Option<Rc<Texture>>) -> MaterialTable {
+        let mut buffer = Buffer::new();
+        buffer.set_data_mut(materials);
+
+        MaterialTable { buffer, texture_array }
+    }
+
+    pub fn set_materials(&mut self, materials: Vec<MaterialData>) {
+        self.buffer.set_data_mut(materials);
+    }
+
+    pub fn get_materials(&self) -> &Vec<MaterialData> {
+        self.buffer.get_data()
+    }
+
+    // Binds the materials as an SSBO, and the array texture to texture_unit as materialTextures
+    pub fn bind(&self, shader_program: &ShaderProgram, binding: u32, texture_unit: u32) -> Result<(), GlError> {
+        self.buffer.bind_base(gl::SHADER_STORAGE_BUFFER, binding);
+
+        if let Some(texture_array) = &self.texture_array {
+            texture_array.ready_texture(texture_unit);
+            unsafe {
+                shader_program.set_int_unsafe("materialTextures", texture_unit as i32)?;
+            }
+        }
+
+        Ok(())
+    }
+}
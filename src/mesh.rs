@@ -1,7 +1,7 @@
-use std::rc::Rc;
+use std::{borrow::Cow, io::{Cursor, Write}, rc::Rc};
 
 use cgmath::Vector3;
-use super::{ShaderProgram, GlError, Texture};
+use super::{ShaderProgram, GlError, Texture, Lod, StateCache, Aabb, Vertex, model_utils, gl};
 
 // TODO: sort any meshes with alpha values and render them farthest to closest w/o depth buffer
 
@@ -14,8 +14,43 @@ pub struct Mesh {
     pub displacement_textures: Vec<Rc<Texture>>,
     pub shininess_textures: Vec<Rc<Texture>>,
     pub shininess: f32,
+    pub emissive_textures: Vec<Rc<Texture>>,
+    pub emissive: Vector3<f32>,
+    // Multiplier on top of emissive, like KHR_materials_emissive_strength
+    pub emissive_strength: f32,
+    // Sampled with the second uv set, see LightmapVertex
+    pub lightmap_textures: Vec<Rc<Texture>>,
+    // Bound as sampler2DArray, e.g. terrain splat layers
+    pub array_textures: Vec<Rc<Texture>>,
+    // Bound as sampler2DMS, e.g. linked from a multisampled framebuffer
+    pub multisample_textures: Vec<Rc<Texture>>,
+    pub alpha_mode: AlphaMode,
+    // Green channel direction of the normal textures, sent to the shader as material.normalFlipY
+    pub normal_map_convention: NormalMapConvention,
+    // Disables back face culling while drawing, like glTF's doubleSided
+    pub double_sided: bool,
+    // Draws with the opposite front face, for meshes mirrored by a negative scale
+    pub flip_winding: bool,
+    // Hidden meshes are skipped by draw but keep their place in the buffers
+    pub visible: bool,
+    pub lod: Option<Lod>,
+    // Primitive mode used to draw the mesh's indices, such as gl::LINES or gl::POINTS
+    pub topology: gl::types::GLenum,
+    // Non indexed meshes draw their vertices in order with glDrawArraysInstanced,
+    // their offset and count (LODs included) are in vertices instead of indices
+    pub indexed: bool,
+    // First texture unit set_textures binds to, material textures take consecutive units from it
+    // in the order diffuse, specular, normal, displacement, shininess, emissive, lightmap, array,
+    // multisample. Uniform array indices still count from 0, so units below the base can be
+    // kept for app wide samplers such as shadow maps
+    pub texture_unit_base: i32,
+    // Uniform names set_textures gives the samplers, material.diffuse[i] and so on by default
+    pub sampler_naming: SamplerNaming,
     buffer_offset: usize,
-    buffer_count: i32
+    buffer_count: i32,
+    // Object space bounds of the mesh's range, set by compute_bounds
+    aabb: Option<Aabb>,
+    bounding_sphere: Option<(Vector3<f32>, f32)>
 }
 
 impl Mesh {
@@ -29,18 +64,53 @@ impl Mesh {
             displacement_textures: Vec::new(),
             shininess_textures: Vec::new(),
             shininess: 0.0,
+            emissive_textures: Vec::new(),
+            emissive: Vector3 { x: 0.0, y: 0.0, z: 0.0},
+            emissive_strength: 1.0,
+            lightmap_textures: Vec::new(),
+            array_textures: Vec::new(),
+            multisample_textures: Vec::new(),
+            alpha_mode: AlphaMode::Opaque,
+            normal_map_convention: NormalMapConvention::OpenGl,
+            double_sided: false,
+            flip_winding: false,
+            visible: true,
+            lod: None,
+            topology: gl::TRIANGLES,
+            indexed: true,
+            texture_unit_base: 0,
+            sampler_naming: SamplerNaming::Material,
             buffer_offset,
-            buffer_count
+            buffer_count,
+            aabb: None,
+            bounding_sphere: None
+        }
+    }
+
+    pub fn with_topology(buffer_offset: usize, buffer_count: i32, topology: gl::types::GLenum) -> Mesh {
+        Mesh {
+            topology,
+            ..Mesh::new(buffer_offset, buffer_count)
+        }
+    }
+
+    // For generated geometry without an index buffer, see indexed
+    pub fn non_indexed(first_vertex: usize, vertex_count: i32) -> Mesh {
+        Mesh {
+            indexed: false,
+            ..Mesh::new(first_vertex, vertex_count)
         }
     }
 
     pub unsafe fn set_textures(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
         let mut i: i32 = 0;
+        let mut name = [0; 64];
+        let mut ids = [0; MAX_BATCHED_TEXTURES];
         
         // Diffuse
-        for texture in self.diffuse_textures.iter() {
-            texture.ready_texture(i as u32);
-            shader_program.set_int_unsafe(format!("material.diffuse[{}]", i).as_str(), i)?;
+        for (n, texture) in self.diffuse_textures.iter().enumerate() {
+            batch_texture(&mut ids, texture, self.texture_unit_base, i);
+            shader_program.set_int_unsafe(&self.sampler_name(&mut name, TextureSlot::Diffuse, i, n), self.texture_unit_base + i)?;
             i += 1;
         }
         shader_program.set_int_unsafe("material.diffuseCount", self.diffuse_textures.len() as i32)?;
@@ -49,9 +119,9 @@ impl Mesh {
         }
 
         // Specular
-        for texture in self.specular_textures.iter() {
-            texture.ready_texture(i as u32);
-            shader_program.set_int_unsafe(format!("material.specular[{}]", i).as_str(), i)?;
+        for (n, texture) in self.specular_textures.iter().enumerate() {
+            batch_texture(&mut ids, texture, self.texture_unit_base, i);
+            shader_program.set_int_unsafe(&self.sampler_name(&mut name, TextureSlot::Specular, i, n), self.texture_unit_base + i)?;
             i += 1;
         }
         shader_program.set_int_unsafe("material.specularCount", self.specular_textures.len() as i32)?;
@@ -60,25 +130,29 @@ impl Mesh {
         }
 
         // Normal
-        for texture in self.normal_textures.iter() {
-            texture.ready_texture(i as u32);
-            shader_program.set_int_unsafe(format!("material.normal[{}]", i).as_str(), i)?;
+        for (n, texture) in self.normal_textures.iter().enumerate() {
+            batch_texture(&mut ids, texture, self.texture_unit_base, i);
+            shader_program.set_int_unsafe(&self.sampler_name(&mut name, TextureSlot::Normal, i, n), self.texture_unit_base + i)?;
             i += 1;
         }
         shader_program.set_int_unsafe("material.normalCount", self.normal_textures.len() as i32)?;
+        if !self.normal_textures.is_empty() {
+            let flip_y = self.normal_map_convention == NormalMapConvention::DirectX;
+            shader_program.set_bool_unsafe("material.normalFlipY", flip_y)?;
+        }
 
         // Displacement
-        for texture in self.displacement_textures.iter() {
-            texture.ready_texture(i as u32);
-            shader_program.set_int_unsafe(format!("material.displacement[{}]", i).as_str(), i)?;
+        for (n, texture) in self.displacement_textures.iter().enumerate() {
+            batch_texture(&mut ids, texture, self.texture_unit_base, i);
+            shader_program.set_int_unsafe(&self.sampler_name(&mut name, TextureSlot::Displacement, i, n), self.texture_unit_base + i)?;
             i += 1;
         }
         shader_program.set_int_unsafe("material.displacementCount", self.displacement_textures.len() as i32)?;
 
         // Shininess
-        for texture in self.shininess_textures.iter() {
-            texture.ready_texture(i as u32);
-            shader_program.set_int_unsafe(format!("material.shininess[{}]", i).as_str(), i)?;
+        for (n, texture) in self.shininess_textures.iter().enumerate() {
+            batch_texture(&mut ids, texture, self.texture_unit_base, i);
+            shader_program.set_int_unsafe(&self.sampler_name(&mut name, TextureSlot::Shininess, i, n), self.texture_unit_base + i)?;
             i += 1;
         }
         shader_program.set_int_unsafe("material.shininessCount", self.shininess_textures.len() as i32)?;
@@ -86,9 +160,115 @@ impl Mesh {
             shader_program.set_float_unsafe("material.shininessFloat", self.shininess)?;
         }
 
+        // Emissive
+        for (n, texture) in self.emissive_textures.iter().enumerate() {
+            batch_texture(&mut ids, texture, self.texture_unit_base, i);
+            shader_program.set_int_unsafe(&self.sampler_name(&mut name, TextureSlot::Emissive, i, n), self.texture_unit_base + i)?;
+            i += 1;
+        }
+        shader_program.set_int_unsafe("material.emissiveCount", self.emissive_textures.len() as i32)?;
+        if self.emissive_textures.is_empty() {
+            shader_program.set_vector_3_unsafe("material.emissiveFloat", &self.emissive)?;
+        }
+        shader_program.set_float_unsafe("material.emissiveStrength", self.emissive_strength)?;
+
+        // Lightmap
+        for (n, texture) in self.lightmap_textures.iter().enumerate() {
+            batch_texture(&mut ids, texture, self.texture_unit_base, i);
+            shader_program.set_int_unsafe(&self.sampler_name(&mut name, TextureSlot::Lightmap, i, n), self.texture_unit_base + i)?;
+            i += 1;
+        }
+        shader_program.set_int_unsafe("material.lightmapCount", self.lightmap_textures.len() as i32)?;
+
+        // Array
+        for (n, texture) in self.array_textures.iter().enumerate() {
+            batch_texture(&mut ids, texture, self.texture_unit_base, i);
+            shader_program.set_int_unsafe(&self.sampler_name(&mut name, TextureSlot::Array, i, n), self.texture_unit_base + i)?;
+            i += 1;
+        }
+        shader_program.set_int_unsafe("material.arrayCount", self.array_textures.len() as i32)?;
+
+        // Multisample, sample count is taken from the first texture
+        for (n, texture) in self.multisample_textures.iter().enumerate() {
+            batch_texture(&mut ids, texture, self.texture_unit_base, i);
+            shader_program.set_int_unsafe(&self.sampler_name(&mut name, TextureSlot::Multisample, i, n), self.texture_unit_base + i)?;
+            i += 1;
+        }
+        shader_program.set_int_unsafe("material.multisampleCount", self.multisample_textures.len() as i32)?;
+        if let Some(texture) = self.multisample_textures.first() {
+            shader_program.set_int_unsafe("material.sampleCount", texture.get_samples())?;
+        }
+
+        // Alpha, a cutoff of 0.0 discards nothing
+        let alpha_cutoff = match self.alpha_mode {
+            AlphaMode::Mask(cutoff) => cutoff,
+            _ => 0.0
+        };
+        shader_program.set_float_unsafe("material.alphaCutoff", alpha_cutoff)?;
+
+        StateCache::bind_texture_units(self.texture_unit_base as u32, &ids[..(i as usize).min(MAX_BATCHED_TEXTURES)]);
+
         Ok(())
     }
 
+    // index counts across every slot, slot_index only within the slot
+    fn sampler_name<'a>(&self, buffer: &'a mut [u8; 64], slot: TextureSlot, index: i32, slot_index: usize) -> Cow<'a, str> {
+        match &self.sampler_naming {
+            SamplerNaming::Material => indexed_name(buffer, slot.material_prefix(), index),
+            SamplerNaming::Custom(name) => Cow::Owned(name(slot, slot_index))
+        }
+    }
+
+    // Ids of every bound texture, with a separator after each texture type so
+    // the same textures in different slots don't compare equal
+    pub fn texture_ids(&self) -> Vec<u32> {
+        let mut ids = Vec::new();
+
+        for textures in self.texture_slots() {
+            ids.extend(textures.iter().map(|texture| texture.get_id()));
+            ids.push(u32::MAX);
+        }
+
+        ids
+    }
+
+    // Every texture list in the order set_textures binds them
+    fn texture_slots(&self) -> [&Vec<Rc<Texture>>; 9] {
+        [
+            &self.diffuse_textures,
+            &self.specular_textures,
+            &self.normal_textures,
+            &self.displacement_textures,
+            &self.shininess_textures,
+            &self.emissive_textures,
+            &self.lightmap_textures,
+            &self.array_textures,
+            &self.multisample_textures
+        ]
+    }
+
+    // Blended meshes need blending enabled and should be drawn after opaque ones
+    pub fn needs_blending(&self) -> bool {
+        self.alpha_mode == AlphaMode::Blend
+    }
+
+    // Whether set_textures would set exactly the same state for both meshes.
+    // Called for every mesh in draw, so it compares in place rather than using texture_ids
+    pub fn same_material(&self, other: &Mesh) -> bool {
+        self.alpha_mode == other.alpha_mode
+            && self.normal_map_convention == other.normal_map_convention
+            && self.texture_unit_base == other.texture_unit_base
+            && self.sampler_naming == other.sampler_naming
+            && self.diffuse == other.diffuse
+            && self.specular == other.specular
+            && self.shininess == other.shininess
+            && self.emissive == other.emissive
+            && self.emissive_strength == other.emissive_strength
+            && self.texture_slots().iter().zip(other.texture_slots().iter()).all(|(a, b)| {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.get_id() == b.get_id())
+            })
+    }
+
     pub fn get_offset(&self) -> usize {
         self.buffer_offset
     }
@@ -96,4 +276,179 @@ impl Mesh {
     pub fn get_count(&self) -> i32 {
         self.buffer_count
     }
-}
\ No newline at end of file
+
+    pub fn set_range(&mut self, buffer_offset: usize, buffer_count: i32) {
+        self.buffer_offset = buffer_offset;
+        self.buffer_count = buffer_count;
+    }
+
+    // Caches the AABB and bounding sphere of the vertices this mesh's indices reference,
+    // models call this on load. A range or index outside the buffers leaves both unset
+    pub fn compute_bounds(&mut self, vertices: &[Vertex], indices: &[u32]) {
+        let points: Option<Vec<Vector3<f32>>> = self
+            .range_indices(indices, self.buffer_offset, self.buffer_count as usize)
+            .and_then(|range| range.iter().map(|index| vertices.get(*index as usize).map(|vertex| vertex.position)).collect());
+        let points = points.unwrap_or_default();
+
+        self.aabb = points.first().map(|first| {
+            let mut aabb = Aabb::new(*first, *first);
+            points.iter().for_each(|point| aabb.expand(*point));
+            aabb
+        });
+        self.bounding_sphere = model_utils::calc_bounding_sphere(&points);
+    }
+
+    // Indices of a range of this mesh, e.g. an LOD level. Non indexed meshes get
+    // offset..offset + count, so they can go through the same index based processing.
+    // None if the range runs past the index buffer
+    pub fn range_indices<'a>(&self, indices: &'a [u32], offset: usize, count: usize) -> Option<Cow<'a, [u32]>> {
+        let end = offset.checked_add(count)?;
+
+        if self.indexed {
+            indices.get(offset..end).map(Cow::Borrowed)
+        } else {
+            Some(Cow::Owned((offset as u32..end as u32).collect()))
+        }
+    }
+
+    pub fn get_aabb(&self) -> Option<Aabb> {
+        self.aabb
+    }
+
+    // (center, radius), cheaper to test than the AABB so culling checks it first
+    pub fn bounding_sphere(&self) -> Option<(Vector3<f32>, f32)> {
+        self.bounding_sphere
+    }
+
+    pub fn select_lod(&mut self, camera_distance: f32) {
+        if let Some(lod) = &mut self.lod {
+            lod.select(camera_distance);
+        }
+    }
+
+    // Range that should be drawn, taking the active LOD level into account
+    pub fn get_draw_range(&self) -> (usize, i32) {
+        match self.lod.as_ref().and_then(|lod| lod.get_active()) {
+            Some(level) => (level.buffer_offset, level.buffer_count),
+            None => (self.buffer_offset, self.buffer_count)
+        }
+    }
+}
+
+// Textures set_textures binds in one call, any past this are bound one at a time
+const MAX_BATCHED_TEXTURES: usize = 32;
+
+fn batch_texture(ids: &mut [u32; MAX_BATCHED_TEXTURES], texture: &Texture, unit_base: i32, i: i32) {
+    match ids.get_mut(i as usize) {
+        Some(id) => *id = texture.get_id(),
+        None => texture.ready_texture((unit_base + i) as u32)
+    }
+}
+
+// Formats "prefix[index]" into the buffer, so uniform names don't allocate every draw.
+// A name too long for the buffer is allocated instead of being cut short
+fn indexed_name<'a>(buffer: &'a mut [u8; 64], prefix: &'static str, index: i32) -> Cow<'a, str> {
+    let mut cursor = Cursor::new(&mut buffer[..]);
+
+    if write!(cursor, "{}[{}]", prefix, index).is_ok() {
+        let len = cursor.position() as usize;
+
+        if let Ok(name) = std::str::from_utf8(&buffer[..len]) {
+            return Cow::Borrowed(name);
+        }
+    }
+
+    Cow::Owned(format!("{}[{}]", prefix, index))
+}
+
+// Mirrors glTF's alphaMode, Mask holds the alpha cutoff
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask(f32),
+    Blend
+}
+
+// Which way +Y points in a tangent space normal map. Shaders should negate the
+// green channel when material.normalFlipY is set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalMapConvention {
+    // +Y up, what OpenGL, Blender and glTF use
+    OpenGl,
+    // -Y, used by DirectX engines such as Unreal
+    DirectX
+}
+
+// Texture lists of a mesh, in the order set_textures binds them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureSlot {
+    Diffuse,
+    Specular,
+    Normal,
+    Displacement,
+    Shininess,
+    Emissive,
+    Lightmap,
+    Array,
+    Multisample
+}
+
+impl TextureSlot {
+    fn material_prefix(&self) -> &'static str {
+        match self {
+            TextureSlot::Diffuse => "material.diffuse",
+            TextureSlot::Specular => "material.specular",
+            TextureSlot::Normal => "material.normal",
+            TextureSlot::Displacement => "material.displacement",
+            TextureSlot::Shininess => "material.shininess",
+            TextureSlot::Emissive => "material.emissive",
+            TextureSlot::Lightmap => "material.lightmap",
+            TextureSlot::Array => "material.array",
+            TextureSlot::Multisample => "material.multisample"
+        }
+    }
+}
+
+// How sampler uniforms are named. Only the samplers are renamed, counts and factors
+// such as material.diffuseCount keep their names
+#[derive(Clone)]
+pub enum SamplerNaming {
+    // material.diffuse[i], where i counts across all of the mesh's textures
+    Material,
+    // Called with the slot and the texture's index within it, e.g. for
+    // |slot, n| format!("texture_{}{}", name_of(slot), n + 1)
+    Custom(Rc<dyn Fn(TextureSlot, usize) -> String>)
+}
+
+impl PartialEq for SamplerNaming {
+    fn eq(&self, other: &SamplerNaming) -> bool {
+        match (self, other) {
+            (SamplerNaming::Material, SamplerNaming::Material) => true,
+            (SamplerNaming::Custom(a), SamplerNaming::Custom(b)) => Rc::ptr_eq(a, b),
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use super::indexed_name;
+
+    #[test]
+    fn indexed_name_uses_the_stack_buffer() {
+        let mut buffer = [0; 64];
+
+        assert!(matches!(indexed_name(&mut buffer, "material.diffuse", 3), Cow::Borrowed("material.diffuse[3]")));
+    }
+
+    #[test]
+    fn indexed_name_too_long_for_the_buffer_is_not_truncated() {
+        let mut buffer = [0; 64];
+        let prefix = "material.aVeryLongSamplerArrayNameThatDoesNotFitInTheStackBuffer";
+        let name = indexed_name(&mut buffer, prefix, 12);
+
+        assert_eq!(name, format!("{}[12]", prefix));
+        assert!(matches!(name, Cow::Owned(_)));
+    }
+}
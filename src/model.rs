@@ -1,44 +1,131 @@
-use cgmath::Matrix4;
-use memoffset::offset_of;
+use std::{any::Any, borrow::Cow, cell::RefCell, collections::HashMap};
+use cgmath::{Matrix4, Vector3, Matrix, SquareMatrix, InnerSpace};
 use crate::{Buffer, DrawCommand};
-use super::{ShaderProgram, Mesh, Vertex, GlError, VertexArray, gl, model_utils::calc_vertex_tangents};
+use super::{ShaderProgram, Mesh, Vertex, VertexDescribe, TangentVertex, GlError, VertexArray, StateCache, gl, capabilities, model_utils::{calc_triangle_tangents, is_mirrored}};
 
 pub trait ModelTrait {
-    fn draw(&self, shader_program: &ShaderProgram) -> Result<(), GlError>;
+    // per_mesh is called with each mesh's index after its textures are set, before it is drawn,
+    // so it can set custom uniforms such as a tint
+    fn draw_with(
+        &self,
+        shader_program: &ShaderProgram,
+        per_mesh: &dyn Fn(usize, &Mesh, &ShaderProgram) -> Result<(), GlError>
+    ) -> Result<(), GlError>;
+    fn draw(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+        self.draw_with(shader_program, &|_, _, _| Ok(()))
+    }
+    // Draws only the listed instances, e.g. the ones left after culling, by gathering their
+    // transforms (and per instance materials) into scratch buffers read in place of the originals.
+    // The gather uses the CPU side copies, so transforms written by a compute shader through
+    // transform_buffer_binding aren't seen, cull those on the GPU with indirect draws instead.
+    // Errors if an index is out of bounds
+    fn draw_instances_indexed(&self, shader_program: &ShaderProgram, indices: &[u32]) -> Result<(), GlError>;
     fn get_transform_array_mut(&mut self) -> &mut Buffer<Matrix4<f32>>;
     fn get_transform_array(&self) -> &Buffer<Matrix4<f32>>;
     fn get_meshes_mut(&mut self) -> &mut Vec<Mesh>;
     fn get_meshes(&self) -> &Vec<Mesh>;
+
+    // Panics if index is out of bounds
+    fn set_visible(&mut self, index: usize, visible: bool) {
+        self.get_meshes_mut()[index].visible = visible;
+    }
+
+    // Replaces every instance transform, the instance count follows the new length
+    fn set_transforms(&mut self, transforms: Vec<Matrix4<f32>>) {
+        self.get_transform_array_mut().set_data_mut(transforms);
+    }
+
+    // Binds the transform buffer as an SSBO so a compute shader can write instance transforms.
+    // The CPU side copy isn't updated, and wait_for_transform_writes is needed before drawing
+    fn transform_buffer_binding(&self, binding: u32) {
+        self.get_transform_array().bind_base(gl::SHADER_STORAGE_BUFFER, binding);
+    }
+
+    // Makes compute shader writes to the transform buffer visible to vertex attribute reads
+    fn wait_for_transform_writes(&self) {
+        unsafe {
+            gl::MemoryBarrier(gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+        }
+    }
+
+    // Groups meshes sharing textures next to each other so draw can skip rebinding them
+    fn sort_meshes_by_material(&mut self) {
+        self.get_meshes_mut().sort_by_cached_key(|mesh| mesh.texture_ids());
+    }
+
+    // Meshes without LODs are left untouched
+    fn select_lod(&mut self, camera_distance: f32) {
+        for mesh in self.get_meshes_mut().iter_mut() {
+            mesh.select_lod(camera_distance);
+        }
+    }
 }
 
 pub trait ModelCreateTrait {
-    fn new(vertices: Vec<Vertex>, indices: Vec<u32>, model_transform: Vec<Matrix4<f32>>, meshes: Vec<Mesh>) -> Self;
+    // Calculates tangents for every mesh
+    fn new(vertices: Vec<Vertex>, indices: Vec<u32>, model_transform: Vec<Matrix4<f32>>, meshes: Vec<Mesh>) -> Self where Self: Sized {
+        Self::new_with_tangents(vertices, indices, model_transform, meshes, Tangents::All)
+    }
+
+    fn new_with_tangents(
+        vertices: Vec<Vertex>,
+        indices: Vec<u32>,
+        model_transform: Vec<Matrix4<f32>>,
+        meshes: Vec<Mesh>,
+        tangents: Tangents
+    ) -> Self;
+}
+
+// Which meshes get tangents calculated when a model is created
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tangents {
+    All,
+    // Skips meshes without a normal texture, which cuts load time on large scenes.
+    // compute_tangents covers meshes given a normal texture later
+    NormalMapped
 }
 
-pub struct MultiBindModel {
+// Generic over the vertex type so custom vertex structs can be used,
+// MultiBindModel is the version using the crate's Vertex
+pub struct Model<V = Vertex> {
     pub meshes: Vec<Mesh>,
     pub vertex_array: VertexArray,
-    pub vertex_buffer: Buffer<Vertex>,
+    pub vertex_buffer: Buffer<V>,
     pub element_buffer: Buffer<u32>,
     pub transform_buffer: Buffer<Matrix4<f32>>,
+    // Optional VAO with only positions and transforms for depth only passes
+    pub position_array: Option<VertexArray>,
+    // Optional per instance index into a MaterialTable, located right after the transform
+    pub material_buffer: Option<Buffer<u32>>,
+    // Extra per vertex buffers from add_vertex_stream, each a Buffer of its own vertex type
+    streams: Vec<Box<dyn Any>>,
+    subset: RefCell<Option<InstanceSubset>>
 }
 
+pub type MultiBindModel = Model<Vertex>;
+
 impl ModelCreateTrait for MultiBindModel {
-    fn new(
+    fn new_with_tangents(
         mut vertices: Vec<Vertex>,
-        mut indices: Vec<u32>,
+        indices: Vec<u32>,
         model_transforms: Vec<Matrix4<f32>>,
-        meshes: Vec<Mesh>
+        meshes: Vec<Mesh>,
+        tangents: Tangents
     ) -> Self {
         let mut model = Self {
             meshes,
             vertex_array: VertexArray::new(),
             vertex_buffer: Buffer::new(),
             element_buffer: Buffer::new(),
-            transform_buffer: Buffer::new()
+            transform_buffer: Buffer::new(),
+            position_array: None,
+            material_buffer: None,
+            streams: Vec::new(),
+            subset: RefCell::new(None)
         };
 
-        calc_vertex_tangents(&mut vertices, &mut indices);
+        calc_mesh_tangents(&mut vertices, &indices, &model.meshes, tangents);
+        calc_mesh_bounds(&vertices, &indices, &mut model.meshes);
         model.setup_model(vertices, indices);
         model.setup_transform_attribute(model_transforms);
 
@@ -47,18 +134,187 @@ impl ModelCreateTrait for MultiBindModel {
 }
 
 impl MultiBindModel {
-    pub fn setup_model(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) {
-        self.vertex_array.add_vertex_buffer(&mut self.vertex_buffer);
-        self.vertex_array.set_element_buffer(&mut self.element_buffer);
+    // Bakes every instance of every model into one model with a single identity transform,
+    // meant for static scenery that never moves. Each mesh's indices are laid out contiguously
+    // across all instances, so LODs are dropped since their ranges can't be kept, and strips,
+    // fans and loops become lists. Triangles of mirrored instances are turned around so they
+    // keep facing the same way
+    pub fn merge(models: Vec<MultiBindModel>) -> MultiBindModel {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut meshes = Vec::new();
+
+        for model in models {
+            let source_vertices = model.vertex_buffer.get_data_or_read_back();
+            let source_indices = model.element_buffer.get_data_or_read_back();
+
+            // Every instance gets its own transformed copy of the vertices
+            let base_vertices: Vec<(u32, bool)> = model.transform_buffer
+                .get_data()
+                .iter()
+                .map(|transform| {
+                    let base_vertex = vertices.len() as u32;
+                    let normal_matrix = transform.invert().unwrap_or(*transform).transpose();
+
+                    vertices.extend(source_vertices.iter().map(|vertex| transform_vertex(vertex, transform, &normal_matrix)));
+
+                    (base_vertex, is_mirrored(transform))
+                })
+                .collect();
+
+            for mut mesh in model.meshes {
+                let (offset, count) = (mesh.get_offset(), mesh.get_count());
+                let merged_offset = indices.len();
+
+                let range = mesh.range_indices(&source_indices, offset, count as usize).unwrap_or_default();
+                let (topology, range) = list_indices(mesh.topology, &range);
+                mesh.topology = topology;
+
+                for (base_vertex, mirrored) in base_vertices.iter() {
+                    let first_index = indices.len();
+                    indices.extend(range.iter().map(|index| index + base_vertex));
+
+                    if *mirrored {
+                        reverse_winding(mesh.topology, &mut indices[first_index..]);
+                    }
+                }
+
+                mesh.set_range(merged_offset, (range.len() * base_vertices.len()) as i32);
+                mesh.indexed = true;
+                mesh.lod = None;
+                meshes.push(mesh);
+            }
+        }
+
+        MultiBindModel::new(vertices, indices, vec![Matrix4::identity()], meshes)
+    }
+
+    // Like merge, but meshes from any of the models that share a material (and topology and
+    // face culling) become a single mesh, each in its own model, so a static scene draws with
+    // one call per material. Only the vertices a mesh uses are copied. Batches keep the first
+    // mesh of their group with its range replaced, and LODs are dropped
+    pub fn batch_by_material(models: Vec<MultiBindModel>) -> Vec<MultiBindModel> {
+        let mut batches: Vec<(Mesh, Vec<Vertex>, Vec<u32>)> = Vec::new();
+        // Batches by their textures and draw state, same_material settles the rest
+        let mut batch_lookup: HashMap<(Vec<u32>, gl::types::GLenum, bool, bool), Vec<usize>> = HashMap::new();
 
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 3, offset_of!(Vertex, position) as u32, gl::FLOAT);
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 3, offset_of!(Vertex, normal) as u32, gl::FLOAT);
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 2, offset_of!(Vertex, tex_coord) as u32, gl::FLOAT);
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 3, offset_of!(Vertex, tangent) as u32, gl::FLOAT);
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 3, offset_of!(Vertex, bitangent) as u32, gl::FLOAT);
+        for model in models {
+            let source_vertices = model.vertex_buffer.get_data_or_read_back();
+            let source_indices = model.element_buffer.get_data_or_read_back();
+            let transforms = model.transform_buffer.get_data();
+            // Where each source vertex went in the current instance's copy, reset after each
+            let mut remap = vec![u32::MAX; source_vertices.len()];
 
+            for mut mesh in model.meshes {
+                let range = mesh.range_indices(&source_indices, mesh.get_offset(), mesh.get_count() as usize).unwrap_or_default();
+                let (topology, range) = list_indices(mesh.topology, &range);
+                mesh.topology = topology;
+
+                let candidates = batch_lookup
+                    .entry((mesh.texture_ids(), mesh.topology, mesh.double_sided, mesh.flip_winding))
+                    .or_default();
+                let batch = match candidates.iter().find(|batch| batches[**batch].0.same_material(&mesh)) {
+                    Some(batch) => *batch,
+                    None => {
+                        mesh.indexed = true;
+                        mesh.lod = None;
+                        batches.push((mesh, Vec::new(), Vec::new()));
+                        candidates.push(batches.len() - 1);
+                        batches.len() - 1
+                    }
+                };
+                let (batch_mesh, vertices, indices) = &mut batches[batch];
+
+                for transform in transforms.iter() {
+                    let normal_matrix = transform.invert().unwrap_or(*transform).transpose();
+                    let first_index = indices.len();
+
+                    for index in range.iter() {
+                        let index = *index as usize;
+
+                        if remap[index] == u32::MAX {
+                            remap[index] = vertices.len() as u32;
+                            vertices.push(transform_vertex(&source_vertices[index], transform, &normal_matrix));
+                        }
+
+                        indices.push(remap[index]);
+                    }
+
+                    for index in range.iter() {
+                        remap[*index as usize] = u32::MAX;
+                    }
+
+                    if is_mirrored(transform) {
+                        reverse_winding(batch_mesh.topology, &mut indices[first_index..]);
+                    }
+                }
+            }
+        }
+
+        batches
+            .into_iter()
+            .map(|(mut mesh, vertices, indices)| {
+                mesh.set_range(0, indices.len() as i32);
+                MultiBindModel::new(vertices, indices, vec![Matrix4::identity()], vec![mesh])
+            })
+            .collect()
+    }
+}
+
+impl<V: VertexDescribe + TangentVertex + Copy> Model<V> {
+    // Recalculates tangents from the current vertices, e.g. after a normal texture was assigned
+    // to a mesh skipped by Tangents::NormalMapped, or for custom vertices from new_custom
+    pub fn compute_tangents(&mut self, tangents: Tangents) {
+        let mut vertices = self.get_vertices_cpu().into_owned();
+        let indices = self.get_indices_cpu();
+        calc_mesh_tangents(&mut vertices, &indices, &self.meshes, tangents);
+
+        let mut vertex_arrays = vec![&self.vertex_array];
+        vertex_arrays.extend(self.position_array.as_ref());
+
+        unsafe {
+            replace_vertex_buffer(&vertex_arrays, &mut self.vertex_buffer, vertices);
+        }
+    }
+}
+
+impl<V: VertexDescribe> Model<V> {
+    // For custom vertex types, unlike ModelCreateTrait::new no tangents are calculated,
+    // see compute_tangents
+    pub fn new_custom(
+        vertices: Vec<V>,
+        indices: Vec<u32>,
+        model_transforms: Vec<Matrix4<f32>>,
+        meshes: Vec<Mesh>
+    ) -> Self {
+        let mut model = Self {
+            meshes,
+            vertex_array: VertexArray::new(),
+            vertex_buffer: Buffer::new(),
+            element_buffer: Buffer::new(),
+            transform_buffer: Buffer::new(),
+            position_array: None,
+            material_buffer: None,
+            streams: Vec::new(),
+            subset: RefCell::new(None)
+        };
+
+        model.setup_model(vertices, indices);
+        model.setup_transform_attribute(model_transforms);
+
+        model
+    }
+
+    // indices can be empty if every mesh is non indexed, the element buffer is then left unset
+    pub fn setup_model(&mut self, vertices: Vec<V>, indices: Vec<u32>) {
+        self.vertex_array.add_vertex_buffer(&mut self.vertex_buffer);
+        self.vertex_array.add_layout(&mut self.vertex_buffer);
         self.vertex_buffer.set_data(vertices);
-        self.element_buffer.set_data(indices);
+
+        if !indices.is_empty() {
+            self.vertex_array.set_element_buffer(&mut self.element_buffer);
+            self.element_buffer.set_data(indices);
+        }
     }
     
     pub fn setup_transform_attribute(&mut self, model_transforms: Vec<Matrix4<f32>>) {
@@ -66,37 +322,422 @@ impl MultiBindModel {
         self.vertex_array.add_attrib_divisor(&mut self.transform_buffer, 4);
         self.transform_buffer.set_data_mut(model_transforms);
     }
-}
 
-// TODO: can simply draw same vertices by providing same offset in each mesh
-// TODO: find a way to make this work with different transforms
-impl ModelTrait for MultiBindModel {
-    fn draw(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+    // One material id per instance, read as a uint attribute. Call once, then update
+    // with set_instance_materials
+    pub fn enable_instance_materials(&mut self, material_ids: Vec<u32>) {
+        let mut material_buffer = Buffer::new();
+
+        self.vertex_array.add_vertex_buffer(&mut material_buffer);
+        self.vertex_array.add_attrib_int(&mut material_buffer, 1, 0, gl::UNSIGNED_INT);
         unsafe {
-            self.vertex_array.bind();
+            material_buffer.set_divisor(self.vertex_array.get_id(), 1);
+        }
+        material_buffer.set_data_mut(material_ids);
+
+        self.material_buffer = Some(material_buffer);
+    }
+
+    // Does nothing unless enable_instance_materials was called
+    pub fn set_instance_materials(&mut self, material_ids: Vec<u32>) {
+        if let Some(material_buffer) = &mut self.material_buffer {
+            material_buffer.set_data_mut(material_ids);
+        }
+    }
+
+    // Vertex and index data for CPU side use such as building collision shapes.
+    // Borrowed from the kept copies, or read back if release_cpu_data was called
+    pub fn get_vertices_cpu(&self) -> Cow<'_, [V]> where V: Copy {
+        self.vertex_buffer.get_data_or_read_back()
+    }
+
+    pub fn get_indices_cpu(&self) -> Cow<'_, [u32]> {
+        self.element_buffer.get_data_or_read_back()
+    }
+
+    // Drops the CPU copies of the vertices and indices to save memory, at the cost of a GPU
+    // readback whenever they are needed again. Call right after construction for static models
+    pub fn release_cpu_data(&mut self) {
+        self.vertex_buffer.release_data();
+        self.element_buffer.release_data();
+    }
+
+    // Reads the stream type's attributes from a separate buffer starting at location, e.g. normals
+    // rewritten every frame for morphing while positions stay in the static vertex buffer.
+    // Locations already used by the vertex layout are taken over by the stream.
+    // Returns the index to pass to get_stream_mut
+    pub fn add_vertex_stream<S: VertexDescribe + 'static>(&mut self, data: Vec<S>, location: u32) -> usize {
+        let mut stream = Buffer::new();
+
+        self.vertex_array.add_vertex_buffer(&mut stream);
+        self.vertex_array.set_layout(&mut stream, location);
+        stream.set_data_mut(data);
+
+        self.streams.push(Box::new(stream));
+
+        self.streams.len() - 1
+    }
+
+    // None if the index or type don't match a stream, update it with set_data_mut or set_data_range
+    pub fn get_stream_mut<S: 'static>(&mut self, index: usize) -> Option<&mut Buffer<S>> {
+        self.streams.get_mut(index).and_then(|stream| stream.downcast_mut::<Buffer<S>>())
+    }
+
+    // Creates the position only VAO, attribute locations match the full VAO
+    // so the same vertex shader can be used with either
+    pub fn enable_positions_only(&mut self) {
+        let mut position_array = VertexArray::new();
+
+        position_array.add_vertex_buffer(&mut self.vertex_buffer);
+        position_array.set_element_buffer(&mut self.element_buffer);
+        // Position is always the first attribute of the layout
+        let layout = V::layout();
+        if let Some(position) = layout.attributes.first() {
+            position_array.add_attrib(&mut self.vertex_buffer, position.size, position.offset, position.type_);
+        }
+        position_array.skip_attribs(layout.len().saturating_sub(1) as u32);
+
+        position_array.add_vertex_buffer(&mut self.transform_buffer);
+        position_array.add_attrib_divisor(&mut self.transform_buffer, 4);
+
+        self.position_array = Some(position_array);
+    }
+
+    pub fn disable_positions_only(&mut self) {
+        self.position_array = None;
+    }
+
+    // Draws every mesh without setting textures, for depth and shadow passes.
+    // Uses the full VAO if enable_positions_only wasn't called
+    pub fn draw_positions_only(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+        if self.transform_buffer.is_empty() {
+            return Ok(());
+        }
+
+        shader_program.use_program();
+        let vertex_array = self.position_array.as_ref().unwrap_or(&self.vertex_array);
+
+        unsafe {
+            vertex_array.bind();
 
             for mesh in &self.meshes {
-                mesh.set_textures(shader_program)?;
-                self.vertex_array.draw_elements_offset(
-                    mesh.get_count(),
-                    mesh.get_offset(),
-                    self.transform_buffer.len() as i32
-                );
+                let (offset, count) = mesh.get_draw_range();
+                if count == 0 || !mesh.visible { continue; }
+
+                draw_mesh(vertex_array, mesh, offset, count, self.transform_buffer.len() as i32);
+            }
+
+            StateCache::bind_vertex_array(0);
+        }
+
+        Ok(())
+    }
+
+    fn draw_meshes(
+        &self,
+        shader_program: &ShaderProgram,
+        per_mesh: &dyn Fn(usize, &Mesh, &ShaderProgram) -> Result<(), GlError>,
+        instance_count: i32
+    ) -> Result<(), GlError> {
+        // Nothing would be drawn, so don't touch any GL state
+        if instance_count == 0 {
+            return Ok(());
+        }
+
+        unsafe {
+            self.vertex_array.bind();
+
+            let mut last_mesh: Option<&Mesh> = None;
+
+            for (i, mesh) in self.meshes.iter().enumerate() {
+                let (offset, count) = mesh.get_draw_range();
+                if count == 0 || !mesh.visible { continue; }
+
+                // Consecutive meshes with the same material keep the already bound textures
+                if !last_mesh.is_some_and(|last| last.same_material(mesh)) {
+                    mesh.set_textures(shader_program)?;
+                }
+                last_mesh = Some(mesh);
+                per_mesh(i, mesh, shader_program)?;
+
+                // Back faces of double sided meshes must not be culled,
+                // culling is only restored if it was on to begin with
+                let restore_cull = mesh.double_sided && StateCache::is_cull_face();
+                if restore_cull { StateCache::set_cull_face(false); }
+
+                let restore_front_face = mesh.flip_winding.then(flip_front_face);
+
+                draw_mesh(&self.vertex_array, mesh, offset, count, instance_count);
+
+                if restore_cull { StateCache::set_cull_face(true); }
+                if let Some(front_face) = restore_front_face { StateCache::set_front_face(front_face); }
     
                 // Set back to defaults once configured
                 gl::ActiveTexture(gl::TEXTURE0);
             }
 
-            gl::BindVertexArray(0);
+            StateCache::bind_vertex_array(0);
         }
 
         Ok(())
     }
 
+    // Low level draw into the shared buffers, requires shader program bind. Does not set
+    // any textures so material uniforms need to be set by the caller beforehand.
+    // mode is the range's primitive type, e.g. the topology of the mesh it belongs to
+    pub fn draw_range(
+        &self,
+        mode: gl::types::GLenum,
+        index_offset: usize,
+        index_count: i32,
+        base_vertex: i32,
+        instance_count: i32
+    ) -> Result<(), GlError> {
+        unsafe {
+            self.vertex_array.bind();
+            self.vertex_array.draw_elements_base_vertex(
+                mode,
+                index_count,
+                index_offset,
+                base_vertex,
+                instance_count
+            );
+            StateCache::bind_vertex_array(0);
+        }
+
+        Ok(())
+    }
+}
+
+// TODO: can simply draw same vertices by providing same offset in each mesh
+// TODO: find a way to make this work with different transforms
+impl<V: VertexDescribe> ModelTrait for Model<V> {
+    fn draw_with(
+        &self,
+        shader_program: &ShaderProgram,
+        per_mesh: &dyn Fn(usize, &Mesh, &ShaderProgram) -> Result<(), GlError>
+    ) -> Result<(), GlError> {
+        self.draw_meshes(shader_program, per_mesh, self.transform_buffer.len() as i32)
+    }
+
+    fn draw_instances_indexed(&self, shader_program: &ShaderProgram, indices: &[u32]) -> Result<(), GlError> {
+        let mut subset = self.subset.borrow_mut();
+        let subset = subset.get_or_insert_with(InstanceSubset::new);
+
+        subset.draw(&self.vertex_array, &self.transform_buffer, self.material_buffer.as_ref(), indices, |instance_count| {
+            self.draw_meshes(shader_program, &|_, _, _| Ok(()), instance_count)
+        })
+    }
+
     fn get_transform_array_mut(&mut self) -> &mut Buffer<Matrix4<f32>> { &mut self.transform_buffer }
     fn get_transform_array(&self) -> &Buffer<Matrix4<f32>> { &self.transform_buffer }
     fn get_meshes_mut(&mut self) -> &mut Vec<Mesh> { &mut self.meshes }
     fn get_meshes(&self) -> &Vec<Mesh> { &self.meshes }
+
+    fn set_transforms(&mut self, transforms: Vec<Matrix4<f32>>) {
+        self.transform_buffer.set_data_mut(transforms);
+
+        unsafe {
+            attach_transforms(&self.vertex_array, &mut self.transform_buffer);
+            if let Some(position_array) = &self.position_array {
+                attach_transforms(position_array, &mut self.transform_buffer);
+            }
+        }
+    }
+}
+
+// Processes the index ranges (LODs included) of the meshes picked by tangents
+fn calc_mesh_tangents<V: TangentVertex>(vertices: &mut [V], indices: &[u32], meshes: &[Mesh], tangents: Tangents) {
+    let picked = |mesh: &&Mesh| tangents == Tangents::All || !mesh.normal_textures.is_empty();
+
+    for mesh in meshes.iter().filter(picked) {
+        let lod_ranges = mesh.lod
+            .iter()
+            .flat_map(|lod| lod.get_levels().iter().map(|level| (level.buffer_offset, level.buffer_count)));
+
+        for (offset, count) in std::iter::once((mesh.get_offset(), mesh.get_count())).chain(lod_ranges) {
+            // Skipped like in compute_bounds if the range is outside the buffers
+            match mesh.range_indices(indices, offset, count as usize) {
+                Some(range) if range.iter().all(|index| (*index as usize) < vertices.len()) => {
+                    calc_triangle_tangents(vertices, &range)
+                }
+                _ => ()
+            }
+        }
+    }
+}
+
+fn calc_mesh_bounds(vertices: &[Vertex], indices: &[u32], meshes: &mut [Mesh]) {
+    for mesh in meshes.iter_mut() {
+        mesh.compute_bounds(vertices, indices);
+    }
+}
+
+unsafe fn bind_instance_buffer<T>(vertex_array: &VertexArray, binding_index: u32, buffer_id: u32) {
+    gl::VertexArrayVertexBuffer(
+        vertex_array.get_id(),
+        binding_index,
+        buffer_id,
+        0,
+        std::mem::size_of::<T>() as gl::types::GLint
+    );
+}
+
+// Re-points the VAO's instance binding at the transform buffer and re-applies its divisor,
+// so the VAO keeps stepping through the current storage per instance whatever its length
+unsafe fn attach_transforms(vertex_array: &VertexArray, transform_buffer: &mut Buffer<Matrix4<f32>>) {
+    bind_instance_buffer::<Matrix4<f32>>(vertex_array, transform_buffer.get_binding_index(), transform_buffer.get_id());
+    transform_buffer.set_divisor(vertex_array.get_id(), 1);
+}
+
+// Scratch buffers for draw_instances_indexed, created on first use and kept so later
+// subsets are written into the existing storage instead of new buffers
+struct InstanceSubset {
+    transforms: Buffer<Matrix4<f32>>,
+    materials: Buffer<u32>
+}
+
+impl InstanceSubset {
+    fn new() -> InstanceSubset {
+        let (mut transforms, mut materials) = (Buffer::new(), Buffer::new());
+        transforms.set_growth(true);
+        materials.set_growth(true);
+
+        InstanceSubset { transforms, materials }
+    }
+
+    // The divisor belongs to the binding, so the scratch buffers step per instance like the originals
+    fn draw<F: FnOnce(i32) -> Result<(), GlError>>(
+        &mut self,
+        vertex_array: &VertexArray,
+        transform_buffer: &Buffer<Matrix4<f32>>,
+        material_buffer: Option<&Buffer<u32>>,
+        indices: &[u32],
+        draw: F
+    ) -> Result<(), GlError> {
+        let transforms = transform_buffer.get_data();
+        let materials = material_buffer.map(|buffer| buffer.get_data());
+        let len = materials.map_or(transforms.len(), |materials| materials.len().min(transforms.len()));
+
+        if let Some(index) = indices.iter().find(|index| **index as usize >= len) {
+            return Err(GlError::InvalidInstanceIndex(*index, len));
+        }
+
+        unsafe {
+            self.transforms.clear_inner();
+            for index in indices {
+                self.transforms.push_to_inner(transforms[*index as usize]);
+            }
+            self.transforms.send_data_mut();
+            bind_instance_buffer::<Matrix4<f32>>(vertex_array, transform_buffer.get_binding_index(), self.transforms.get_id());
+
+            if let (Some(material_buffer), Some(materials)) = (material_buffer, materials) {
+                self.materials.clear_inner();
+                for index in indices {
+                    self.materials.push_to_inner(materials[*index as usize]);
+                }
+                self.materials.send_data_mut();
+                bind_instance_buffer::<u32>(vertex_array, material_buffer.get_binding_index(), self.materials.get_id());
+            }
+
+            let result = draw(indices.len() as i32);
+
+            bind_instance_buffer::<Matrix4<f32>>(vertex_array, transform_buffer.get_binding_index(), transform_buffer.get_id());
+            if let Some(material_buffer) = material_buffer {
+                bind_instance_buffer::<u32>(vertex_array, material_buffer.get_binding_index(), material_buffer.get_id());
+            }
+
+            result
+        }
+    }
+}
+
+// Draws a range of the mesh, which has to be bound already
+unsafe fn draw_mesh(vertex_array: &VertexArray, mesh: &Mesh, offset: usize, count: i32, instance_count: i32) {
+    if mesh.indexed {
+        vertex_array.draw_elements_offset(mesh.topology, count, offset, instance_count);
+    } else {
+        vertex_array.draw_arrays_offset(mesh.topology, offset as i32, count, instance_count);
+    }
+}
+
+// Vertex storage is immutable, so updated vertices go in a new buffer bound in place of the old one.
+// The vertex buffer is always at binding 0
+unsafe fn replace_vertex_buffer<V>(vertex_arrays: &[&VertexArray], vertex_buffer: &mut Buffer<V>, vertices: Vec<V>) {
+    let mut new_buffer = Buffer::new();
+    new_buffer.set_data(vertices);
+
+    for vertex_array in vertex_arrays {
+        new_buffer.add_vertex_to_vertex_array(vertex_array.get_id(), 0);
+    }
+
+    *vertex_buffer = new_buffer;
+}
+
+// Strips, fans and loops as the equivalent list, so ranges can be joined into one draw.
+// Degenerate strip triangles are dropped, primitive restart indices aren't handled
+fn list_indices(topology: gl::types::GLenum, indices: &[u32]) -> (gl::types::GLenum, Cow<'_, [u32]>) {
+    let n = indices.len();
+
+    match topology {
+        gl::TRIANGLE_STRIP => {
+            // Every other triangle of a strip is wound the other way round
+            let triangles = (0..n.saturating_sub(2))
+                .map(|i| if i % 2 == 0 { [indices[i], indices[i + 1], indices[i + 2]] } else { [indices[i + 1], indices[i], indices[i + 2]] })
+                .filter(|[a, b, c]| a != b && b != c && a != c)
+                .flatten()
+                .collect();
+
+            (gl::TRIANGLES, Cow::Owned(triangles))
+        },
+        gl::TRIANGLE_FAN => {
+            let triangles = (1..n.saturating_sub(1)).flat_map(|i| [indices[0], indices[i], indices[i + 1]]).collect();
+
+            (gl::TRIANGLES, Cow::Owned(triangles))
+        },
+        gl::LINE_STRIP | gl::LINE_LOOP => {
+            let mut lines: Vec<u32> = (0..n.saturating_sub(1)).flat_map(|i| [indices[i], indices[i + 1]]).collect();
+            if topology == gl::LINE_LOOP && n > 2 {
+                lines.extend_from_slice(&[indices[n - 1], indices[0]]);
+            }
+
+            (gl::LINES, Cow::Owned(lines))
+        },
+        _ => (topology, Cow::Borrowed(indices))
+    }
+}
+
+// Baking in a mirroring transform turns the triangles around, this turns them back.
+// Only needed for triangle lists, see list_indices for strips and fans
+fn reverse_winding(topology: gl::types::GLenum, indices: &mut [u32]) {
+    if topology == gl::TRIANGLES {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+}
+
+// Swaps between clockwise and counter clockwise, returning the previous front face
+fn flip_front_face() -> gl::types::GLenum {
+    let front_face = StateCache::get_front_face();
+    StateCache::set_front_face(if front_face == gl::CW { gl::CCW } else { gl::CW });
+
+    front_face
+}
+
+fn transform_vertex(vertex: &Vertex, transform: &Matrix4<f32>, normal_matrix: &Matrix4<f32>) -> Vertex {
+    let transform_direction = |direction: Vector3<f32>, matrix: &Matrix4<f32>| {
+        let result = (matrix * direction.extend(0.0)).truncate();
+        if result.magnitude2() > 0.0 { result.normalize() } else { result }
+    };
+
+    Vertex {
+        position: (transform * vertex.position.extend(1.0)).truncate(),
+        normal: transform_direction(vertex.normal, normal_matrix),
+        tex_coord: vertex.tex_coord,
+        tangent: transform_direction(vertex.tangent, transform),
+        bitangent: transform_direction(vertex.bitangent, transform)
+    }
 }
 
 pub struct BindlessModel {
@@ -106,15 +747,19 @@ pub struct BindlessModel {
     pub vertex_buffer: Buffer<Vertex>,
     pub element_buffer: Buffer<u32>,
     pub transform_buffer: Buffer<Matrix4<f32>>,
-    pub command_buffer: Buffer<DrawCommand>
+    pub command_buffer: Buffer<DrawCommand>,
+    // Draw count written by the GPU for draw_indirect_count
+    pub count_buffer: Option<Buffer<u32>>,
+    subset: RefCell<Option<InstanceSubset>>
 }
 
 impl ModelCreateTrait for BindlessModel {
-    fn new(
+    fn new_with_tangents(
         mut vertices: Vec<Vertex>,
-        mut indices: Vec<u32>,
+        indices: Vec<u32>,
         model_transforms: Vec<Matrix4<f32>>,
-        meshes: Vec<Mesh>
+        meshes: Vec<Mesh>,
+        tangents: Tangents
     ) -> Self {
         let mut model = Self {
             meshes,
@@ -122,15 +767,16 @@ impl ModelCreateTrait for BindlessModel {
             vertex_buffer: Buffer::new(),
             element_buffer: Buffer::new(),
             transform_buffer: Buffer::new(),
-            command_buffer: Buffer::new()
+            command_buffer: Buffer::new(),
+            count_buffer: None,
+            subset: RefCell::new(None)
         };
 
-        // TODO: generate draw calls and add them
-        // TODO: to buffer
-
-        calc_vertex_tangents(&mut vertices, &mut indices);
+        calc_mesh_tangents(&mut vertices, &indices, &model.meshes, tangents);
+        calc_mesh_bounds(&vertices, &indices, &mut model.meshes);
         model.setup_model(vertices, indices);
         model.setup_transform_attribute(model_transforms);
+        model.reset_commands();
 
         model
     }
@@ -141,11 +787,7 @@ impl BindlessModel {
         self.vertex_array.add_vertex_buffer(&mut self.vertex_buffer);
         self.vertex_array.set_element_buffer(&mut self.element_buffer);
 
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 3, offset_of!(Vertex, position) as u32, gl::FLOAT);
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 3, offset_of!(Vertex, normal) as u32, gl::FLOAT);
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 2, offset_of!(Vertex, tex_coord) as u32, gl::FLOAT);
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 3, offset_of!(Vertex, tangent) as u32, gl::FLOAT);
-        self.vertex_array.add_attrib(&mut self.vertex_buffer, 3, offset_of!(Vertex, bitangent) as u32, gl::FLOAT);
+        self.vertex_array.add_layout(&mut self.vertex_buffer);
 
         self.vertex_buffer.set_data(vertices);
         self.element_buffer.set_data(indices);
@@ -156,41 +798,261 @@ impl BindlessModel {
         self.vertex_array.add_attrib_divisor(&mut self.transform_buffer, 4);
         self.transform_buffer.set_data_mut(model_transforms);
     }
-}
 
-impl ModelTrait for BindlessModel {
-    // TODO: work on making this work with textures so there is one draw call
-    // TODO: Use bindless textures and ubos to do this in one big draw call
-    // TODO: Check if those extensions are supported, if not, just draw
-    // TODO: each mesh individually like normal.
-    // TODO: https://litasa.github.io/blog/2017/09/04/OpenGL-MultiDrawIndirect-with-Individual-Textures
-    // Panics if there is no cbo present in the model
-    fn draw(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+    // Recalculates tangents from the current vertices, see Model::compute_tangents
+    pub fn compute_tangents(&mut self, tangents: Tangents) {
+        let mut vertices = self.get_vertices_cpu().into_owned();
+        let indices = self.get_indices_cpu();
+        calc_mesh_tangents(&mut vertices, &indices, &self.meshes, tangents);
+
+        unsafe {
+            replace_vertex_buffer(&[&self.vertex_array], &mut self.vertex_buffer, vertices);
+        }
+    }
+
+    // Vertex and index data for CPU side use such as building collision shapes.
+    // Borrowed from the kept copies, or read back if release_cpu_data was called
+    pub fn get_vertices_cpu(&self) -> Cow<'_, [Vertex]> {
+        self.vertex_buffer.get_data_or_read_back()
+    }
+
+    pub fn get_indices_cpu(&self) -> Cow<'_, [u32]> {
+        self.element_buffer.get_data_or_read_back()
+    }
+
+    // Drops the CPU copies of the vertices and indices to save memory, at the cost of a GPU
+    // readback whenever they are needed again. Call right after construction for static models
+    pub fn release_cpu_data(&mut self) {
+        self.vertex_buffer.release_data();
+        self.element_buffer.release_data();
+    }
+
+    // Rebuilds one draw command per mesh from the CPU side state, drawing every instance.
+    // Also used to restore the initial commands after a compute pass modified them.
+    // Commands are always indexed, so non indexed meshes are only supported by draw
+    pub fn reset_commands(&mut self) {
+        let instance_count = self.transform_buffer.len() as u32;
+        let commands = self.meshes
+            .iter()
+            .map(|mesh| {
+                let (offset, count) = mesh.get_draw_range();
+                DrawCommand::new(count as u32, instance_count, offset as u32, 0, 0)
+            })
+            .collect();
+
+        self.command_buffer.set_data_mut(commands);
+    }
+
+    // Binds the command buffer as an SSBO so a compute pass can edit the commands,
+    // e.g. zeroing instance counts of culled meshes before the indirect draw
+    pub fn command_buffer_binding(&self, binding: u32) {
+        self.command_buffer.bind_base(gl::SHADER_STORAGE_BUFFER, binding);
+    }
+
+    // Starts with every command drawn
+    pub fn enable_count_buffer(&mut self) {
+        let mut count_buffer = Buffer::new();
+        count_buffer.set_data_mut(vec![self.command_buffer.len() as u32]);
+
+        self.count_buffer = Some(count_buffer);
+    }
+
+    // Binds the count buffer as an SSBO so a compute pass can write the draw count
+    pub fn count_buffer_binding(&self, binding: u32) {
+        if let Some(count_buffer) = &self.count_buffer {
+            count_buffer.bind_base(gl::SHADER_STORAGE_BUFFER, binding);
+        }
+    }
+
+    // Overwrites the start of the command buffer, e.g. with the visible meshes from a CPU
+    // culling pass. The buffer doesn't grow, so there can't be more commands than meshes.
+    // The count buffer is set to the number of commands if enabled
+    pub fn set_commands(&mut self, commands: &[DrawCommand]) -> Result<(), GlError> {
+        if commands.len() > self.command_buffer.len() {
+            return Err(GlError::BufferOverflow(self.command_buffer.get_id(), commands.len(), self.command_buffer.len()));
+        }
+
+        unsafe {
+            // Kept on the CPU side as well, set_transforms updates the commands from there
+            for (index, command) in commands.iter().enumerate() {
+                self.command_buffer.set_data_index_inner(*command, index);
+            }
+
+            // Orphans the old contents so the write doesn't wait on draws still reading them
+            let size = std::mem::size_of_val(commands) as isize;
+            gl::InvalidateBufferSubData(self.command_buffer.get_id(), 0, size);
+            self.command_buffer.send_data_range(0..commands.len());
+
+            if let Some(count_buffer) = &self.count_buffer {
+                let count = commands.len() as u32;
+                gl::NamedBufferSubData(
+                    count_buffer.get_id(),
+                    0,
+                    std::mem::size_of::<u32>() as isize,
+                    &count as *const u32 as *const gl::types::GLvoid
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_meshes(
+        &self,
+        shader_program: &ShaderProgram,
+        per_mesh: &dyn Fn(usize, &Mesh, &ShaderProgram) -> Result<(), GlError>,
+        instance_count: i32
+    ) -> Result<(), GlError> {
+        // Nothing would be drawn, so don't touch any GL state
+        if instance_count == 0 {
+            return Ok(());
+        }
+
         unsafe {
             self.vertex_array.bind();
             // TODO: Generic buffer bind function?
             gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.command_buffer.get_id());
 
-            for mesh in &self.meshes {
-                mesh.set_textures(shader_program)?;
-                self.vertex_array.draw_elements_offset(
-                    mesh.get_count(),
-                    mesh.get_offset(),
-                    self.transform_buffer.len() as i32
-                );
+            let mut last_mesh: Option<&Mesh> = None;
+
+            for (i, mesh) in self.meshes.iter().enumerate() {
+                let (offset, count) = mesh.get_draw_range();
+                if count == 0 || !mesh.visible { continue; }
+
+                // Consecutive meshes with the same material keep the already bound textures
+                if !last_mesh.is_some_and(|last| last.same_material(mesh)) {
+                    mesh.set_textures(shader_program)?;
+                }
+                last_mesh = Some(mesh);
+                per_mesh(i, mesh, shader_program)?;
+
+                // Back faces of double sided meshes must not be culled,
+                // culling is only restored if it was on to begin with
+                let restore_cull = mesh.double_sided && StateCache::is_cull_face();
+                if restore_cull { StateCache::set_cull_face(false); }
+
+                let restore_front_face = mesh.flip_winding.then(flip_front_face);
+
+                draw_mesh(&self.vertex_array, mesh, offset, count, instance_count);
+
+                if restore_cull { StateCache::set_cull_face(true); }
+                if let Some(front_face) = restore_front_face { StateCache::set_front_face(front_face); }
     
                 // Set back to defaults once configured
                 gl::ActiveTexture(gl::TEXTURE0);
             }
 
-            gl::BindVertexArray(0);
+            StateCache::bind_vertex_array(0);
+        }
+
+        Ok(())
+    }
+
+    // Draws the command buffer with the count taken from the count buffer, so GPU culling
+    // can change the number of draws without a readback. Textures are expected to be bindless.
+    // All commands are drawn in one call, so the meshes have to share a topology
+    pub fn draw_indirect_count(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+        if !capabilities::supports_version(4, 6) && !capabilities::has_extension("GL_ARB_indirect_parameters") {
+            return Err(GlError::UnsupportedFeature("GL_ARB_indirect_parameters".to_owned()));
+        }
+
+        let mode = self.meshes.first().map_or(gl::TRIANGLES, |mesh| mesh.topology);
+        if let Some(mesh) = self.meshes.iter().find(|mesh| mesh.topology != mode) {
+            return Err(GlError::MixedTopologies(mode, mesh.topology));
+        }
+
+        let count_buffer = match &self.count_buffer {
+            Some(count_buffer) => count_buffer,
+            None => return Err(GlError::CountBufferMissing)
+        };
+
+        shader_program.use_program();
+
+        unsafe {
+            self.vertex_array.bind();
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.command_buffer.get_id());
+            gl::BindBuffer(gl::PARAMETER_BUFFER, count_buffer.get_id());
+
+            self.vertex_array.draw_elements_multi_indirect_count(mode, self.command_buffer.len() as i32);
+
+            gl::BindBuffer(gl::PARAMETER_BUFFER, 0);
+            StateCache::bind_vertex_array(0);
         }
 
         Ok(())
     }
+}
+
+impl ModelTrait for BindlessModel {
+    // TODO: work on making this work with textures so there is one draw call
+    // TODO: Use bindless textures and ubos to do this in one big draw call
+    // TODO: Check if those extensions are supported, if not, just draw
+    // TODO: each mesh individually like normal.
+    // TODO: https://litasa.github.io/blog/2017/09/04/OpenGL-MultiDrawIndirect-with-Individual-Textures
+    // Panics if there is no cbo present in the model
+    fn draw_with(
+        &self,
+        shader_program: &ShaderProgram,
+        per_mesh: &dyn Fn(usize, &Mesh, &ShaderProgram) -> Result<(), GlError>
+    ) -> Result<(), GlError> {
+        self.draw_meshes(shader_program, per_mesh, self.transform_buffer.len() as i32)
+    }
+
+    fn draw_instances_indexed(&self, shader_program: &ShaderProgram, indices: &[u32]) -> Result<(), GlError> {
+        let mut subset = self.subset.borrow_mut();
+        let subset = subset.get_or_insert_with(InstanceSubset::new);
+
+        subset.draw(&self.vertex_array, &self.transform_buffer, None, indices, |instance_count| {
+            self.draw_meshes(shader_program, &|_, _, _| Ok(()), instance_count)
+        })
+    }
 
     fn get_transform_array_mut(&mut self) -> &mut Buffer<Matrix4<f32>> { &mut self.transform_buffer }
-    fn get_transform_array(&self) -> &Buffer<Matrix4<f32>> { &&self.transform_buffer }
+    fn get_transform_array(&self) -> &Buffer<Matrix4<f32>> { &self.transform_buffer }
     fn get_meshes_mut(&mut self) -> &mut Vec<Mesh> { &mut self.meshes }
     fn get_meshes(&self) -> &Vec<Mesh> { &self.meshes }
-}
\ No newline at end of file
+
+    // Commands keep their ranges, only their instance counts are updated
+    fn set_transforms(&mut self, transforms: Vec<Matrix4<f32>>) {
+        self.transform_buffer.set_data_mut(transforms);
+
+        unsafe {
+            attach_transforms(&self.vertex_array, &mut self.transform_buffer);
+        }
+
+        let instance_count = self.transform_buffer.len() as u32;
+        let command_count = self.command_buffer.len();
+
+        unsafe {
+            for index in 0..command_count {
+                let mut command = self.command_buffer.get_data()[index];
+                command.set_instance_count(instance_count);
+                self.command_buffer.set_data_index_inner(command, index);
+            }
+
+            self.command_buffer.send_data_range(0..command_count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_and_fans_become_lists() {
+        let (topology, triangles) = list_indices(gl::TRIANGLE_STRIP, &[0, 1, 2, 3, 3, 4]);
+        assert_eq!(topology, gl::TRIANGLES);
+        // Odd triangles swap their first two, the degenerate ones around the repeat are dropped
+        assert_eq!(&triangles[..], &[0, 1, 2, 2, 1, 3]);
+
+        let (topology, triangles) = list_indices(gl::TRIANGLE_FAN, &[0, 1, 2, 3]);
+        assert_eq!((topology, &triangles[..]), (gl::TRIANGLES, &[0, 1, 2, 0, 2, 3][..]));
+
+        let (topology, lines) = list_indices(gl::LINE_LOOP, &[0, 1, 2]);
+        assert_eq!((topology, &lines[..]), (gl::LINES, &[0, 1, 1, 2, 2, 0][..]));
+
+        let (topology, points) = list_indices(gl::POINTS, &[4, 5]);
+        assert_eq!((topology, &points[..]), (gl::POINTS, &[4, 5][..]));
+    }
+}
@@ -1,6 +1,7 @@
-use cgmath::{Matrix4, Vector3, Vector2, vec2, Zero};
+use std::{cmp::Ordering, collections::{BinaryHeap, HashMap, HashSet}};
+use cgmath::{Matrix4, Vector3, Vector2, vec2, Zero, VectorSpace, InnerSpace, SquareMatrix};
 use crate::{ModelTrait, ModelCreateTrait};
-use super::{Mesh, Vertex};
+use super::{Mesh, Vertex, TangentVertex};
 
 pub fn create_quad<T: ModelTrait + ModelCreateTrait>(model_transforms: Vec<Matrix4<f32>>) -> T {
     // Flat panel definition
@@ -48,6 +49,90 @@ pub fn create_quad<T: ModelTrait + ModelCreateTrait>(model_transforms: Vec<Matri
 
 // Calculate lines perpendicular to normals for using normal maps
 pub fn calc_vertex_tangents(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+    calc_vertex_tangents_checked(vertices, indices, TangentCheck::None);
+}
+
+// What to do with vertices validate_tangents reports after calculating tangents
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TangentCheck {
+    None,
+    Log,
+    Fix
+}
+
+pub fn calc_vertex_tangents_checked(vertices: &mut [Vertex], indices: &mut [u32], check: TangentCheck) {
+    calc_triangle_tangents(vertices, indices);
+
+    if check == TangentCheck::None {
+        return;
+    }
+
+    let invalid = validate_tangents(vertices);
+    if invalid.is_empty() {
+        return;
+    }
+
+    match check {
+        TangentCheck::Log => println!("DEBUG::MODEL::INVALID_TANGENTS: {} vertices, first {:?}", invalid.len(), &invalid[..invalid.len().min(16)]),
+        _ => fix_tangents(vertices, &invalid)
+    }
+}
+
+// Cosine between the normalised tangent and normal above which they aren't considered perpendicular
+const TANGENT_TOLERANCE: f32 = 0.01;
+
+// Indices of vertices with a zero or non finite tangent, or one that isn't perpendicular to the normal.
+// Tangents aren't required to be unit length, the shader normalises them
+pub fn validate_tangents(vertices: &[Vertex]) -> Vec<usize> {
+    vertices.iter().enumerate().filter_map(|(i, vertex)| {
+        let tangent = vertex.tangent;
+        let normal = vertex.normal;
+
+        let finite = [tangent.x, tangent.y, tangent.z].iter().all(|component| component.is_finite());
+        let valid = finite
+            && tangent.magnitude2() > f32::EPSILON
+            && normal.magnitude2() > f32::EPSILON
+            && tangent.normalize().dot(normal.normalize()).abs() <= TANGENT_TOLERANCE;
+
+        if valid { None } else { Some(i) }
+    }).collect()
+}
+
+// Makes the tangents of the given vertices perpendicular to their normals, keeping the
+// bitangent's handedness. Unusable tangents are replaced with any perpendicular direction
+pub fn fix_tangents(vertices: &mut [Vertex], invalid: &[usize]) {
+    for &i in invalid {
+        let vertex = &mut vertices[i];
+        let normal = vertex.normal;
+        if normal.magnitude2() <= f32::EPSILON || !normal.x.is_finite() || !normal.y.is_finite() || !normal.z.is_finite() {
+            continue;
+        }
+        let normal = normal.normalize();
+
+        let tangent = vertex.tangent;
+        let mut tangent = if tangent.x.is_finite() && tangent.y.is_finite() && tangent.z.is_finite() {
+            tangent - normal * normal.dot(tangent)
+        } else {
+            Vector3::zero()
+        };
+
+        if tangent.magnitude2() <= f32::EPSILON {
+            // Cross with whichever axis is least aligned with the normal
+            let axis = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+            tangent = normal.cross(axis);
+        }
+        let tangent = tangent.normalize();
+
+        let bitangent = vertex.bitangent;
+        let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+
+        vertex.tangent = tangent;
+        vertex.bitangent = normal.cross(tangent) * handedness;
+    }
+}
+
+// Same as calc_vertex_tangents without the checks, for any vertex type with the needed attributes
+pub fn calc_triangle_tangents<V: TangentVertex>(vertices: &mut [V], indices: &[u32]) {
     for i in 0..(indices.len() / 3) {
         let index = i * 3;
 
@@ -56,14 +141,14 @@ pub fn calc_vertex_tangents(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>)
         let index3 = indices[index + 2] as usize;
 
         // Get positions for the vertices that make up the triangle
-        let pos1 = vertices[index1].position;
-        let pos2 = vertices[index2].position;
-        let pos3 = vertices[index3].position;
+        let pos1 = vertices[index1].get_position();
+        let pos2 = vertices[index2].get_position();
+        let pos3 = vertices[index3].get_position();
 
         // Get corresponding texture coordinates
-        let uv1 = vertices[index1].tex_coord;
-        let uv2 = vertices[index2].tex_coord;
-        let uv3 = vertices[index3].tex_coord;
+        let uv1 = vertices[index1].get_tex_coord();
+        let uv2 = vertices[index2].get_tex_coord();
+        let uv3 = vertices[index3].get_tex_coord();
 
         // Calculate deltas
         let edge1 = pos2 - pos1;
@@ -95,12 +180,562 @@ pub fn calc_vertex_tangents(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>)
         bitangent.z = dir_correction * ( - edge2.z * delta_uv1.x + edge1.z * delta_uv2.x);
 
         // Set tangent vector to all vertices of the triangle
-        vertices[index1].tangent = tangent;
-        vertices[index2].tangent = tangent;
-        vertices[index3].tangent = tangent;            
+        vertices[index1].set_tangents(tangent, bitangent);
+        vertices[index2].set_tangents(tangent, bitangent);
+        vertices[index3].set_tangents(tangent, bitangent);
+    }
+}
+
+// Symmetric 4x4 matrix stored as its upper triangle
+type Quadric = [f64; 10];
+
+// Edge collapse candidate, ordered so the cheapest collapse is popped first
+struct Collapse {
+    cost: f64,
+    t: f64,
+    v1: usize,
+    v2: usize,
+    version1: u32,
+    version2: u32
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+    }
+}
+
+fn plane_quadric(normal: [f64; 3], d: f64, weight: f64) -> Quadric {
+    let [a, b, c] = normal;
+
+    [
+        a * a, a * b, a * c, a * d,
+        b * b, b * c, b * d,
+        c * c, c * d,
+        d * d
+    ].map(|x| x * weight)
+}
+
+fn add_quadric(a: &mut Quadric, b: &Quadric) {
+    for i in 0..10 {
+        a[i] += b[i];
+    }
+}
+
+fn quadric_error(q: &Quadric, p: [f64; 3]) -> f64 {
+    let [x, y, z] = p;
+
+    q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+        + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+        + q[7] * z * z + 2.0 * q[8] * z
+        + q[9]
+}
+
+fn to_f64(v: Vector3<f32>) -> [f64; 3] {
+    [v.x as f64, v.y as f64, v.z as f64]
+}
+
+fn sub3(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross3(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0]
+    ]
+}
+
+fn dot3(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn lerp3(a: [f64; 3], b: [f64; 3], t: f64) -> [f64; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+fn normalize3(a: [f64; 3]) -> Option<[f64; 3]> {
+    let len = dot3(a, a).sqrt();
+
+    if len > f64::EPSILON { Some([a[0] / len, a[1] / len, a[2] / len]) } else { None }
+}
+
+fn interpolate_vertex(a: &Vertex, b: &Vertex, t: f32) -> Vertex {
+    let normal = a.normal.lerp(b.normal, t);
+
+    Vertex {
+        position: a.position.lerp(b.position, t),
+        normal: if normal.magnitude2() > 0.0 { normal.normalize() } else { normal },
+        tex_coord: a.tex_coord.lerp(b.tex_coord, t),
+        tangent: a.tangent.lerp(b.tangent, t),
+        bitangent: a.bitangent.lerp(b.bitangent, t)
+    }
+}
+
+// Reduces triangle count with quadric error metric edge collapse (Garland & Heckbert).
+// target_ratio is the fraction of triangles to keep. Collapses that would flip a face or
+// make the mesh non-manifold are rejected, so the result may keep more triangles than asked.
+// Open edges (including UV seams) are weighted heavily so outlines and bounds are preserved,
+// and vertex attributes are interpolated along each collapsed edge
+pub fn simplify(vertices: &[Vertex], indices: &[u32], target_ratio: f32) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = vertices.to_vec();
+    let mut triangles: Vec<[usize; 3]> = indices
+        .chunks_exact(3)
+        .map(|tri| [tri[0] as usize, tri[1] as usize, tri[2] as usize])
+        .collect();
+
+    // Degenerate input triangles are dropped straight away
+    let mut tri_removed: Vec<bool> = triangles
+        .iter()
+        .map(|tri| tri[0] == tri[1] || tri[1] == tri[2] || tri[0] == tri[2])
+        .collect();
+    let mut live_count = tri_removed.iter().filter(|removed| !**removed).count();
+    let target_count = ((live_count as f32) * target_ratio.clamp(0.0, 1.0)).round() as usize;
+
+    let mut vertex_tris: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    let mut vertex_removed = vec![false; vertices.len()];
+    let mut versions = vec![0u32; vertices.len()];
+    let mut quadrics: Vec<Quadric> = vec![[0.0; 10]; vertices.len()];
+    let mut edge_counts: HashMap<(usize, usize), (u32, usize)> = HashMap::new();
+
+    for (i, tri) in triangles.iter().enumerate() {
+        if tri_removed[i] { continue; }
+
+        let p = tri.map(|v| to_f64(vertices[v].position));
+        let normal = cross3(sub3(p[1], p[0]), sub3(p[2], p[0]));
+        let area = dot3(normal, normal).sqrt();
+
+        if let Some(normal) = normalize3(normal) {
+            let q = plane_quadric(normal, -dot3(normal, p[0]), area);
+
+            for v in tri.iter() {
+                add_quadric(&mut quadrics[*v], &q);
+            }
+        }
+
+        for j in 0..3 {
+            let (a, b) = (tri[j], tri[(j + 1) % 3]);
+
+            vertex_tris[a].push(i);
+            edge_counts.entry((a.min(b), a.max(b))).or_insert((0, i)).0 += 1;
+        }
+    }
+
+    // Constrain open edges with a plane perpendicular to their face
+    for ((a, b), (count, tri)) in edge_counts.iter() {
+        if *count != 1 { continue; }
+
+        let tri = triangles[*tri];
+        let p = tri.map(|v| to_f64(vertices[v].position));
+        let face_normal = cross3(sub3(p[1], p[0]), sub3(p[2], p[0]));
+        let (pa, pb) = (to_f64(vertices[*a].position), to_f64(vertices[*b].position));
+        let edge = sub3(pb, pa);
+
+        if let Some(normal) = normalize3(cross3(edge, face_normal)) {
+            let q = plane_quadric(normal, -dot3(normal, pa), 1000.0 * dot3(edge, edge));
+
+            add_quadric(&mut quadrics[*a], &q);
+            add_quadric(&mut quadrics[*b], &q);
+        }
+    }
+
+    let evaluate = |vertices: &Vec<Vertex>, quadrics: &Vec<Quadric>, versions: &Vec<u32>, v1: usize, v2: usize| {
+        let mut q = quadrics[v1];
+        add_quadric(&mut q, &quadrics[v2]);
+
+        let (p1, p2) = (to_f64(vertices[v1].position), to_f64(vertices[v2].position));
+        let (cost, t) = [0.0, 0.5, 1.0]
+            .iter()
+            .map(|t| (quadric_error(&q, lerp3(p1, p2, *t)), *t))
+            .fold((f64::INFINITY, 0.0), |best, cur| if cur.0 < best.0 { cur } else { best });
+
+        Collapse { cost, t, v1, v2, version1: versions[v1], version2: versions[v2] }
+    };
+
+    let mut heap = BinaryHeap::new();
+    for (a, b) in edge_counts.keys() {
+        heap.push(evaluate(&vertices, &quadrics, &versions, *a, *b));
+    }
+
+    let neighbours = |triangles: &Vec<[usize; 3]>, tri_removed: &Vec<bool>, tris: &Vec<usize>, v: usize| {
+        let mut result = HashSet::new();
+
+        for tri in tris.iter().filter(|tri| !tri_removed[**tri]) {
+            result.extend(triangles[*tri].iter().filter(|other| **other != v));
+        }
+
+        result
+    };
+
+    while live_count > target_count {
+        let collapse = match heap.pop() {
+            Some(collapse) => collapse,
+            None => break
+        };
+        let (v1, v2) = (collapse.v1, collapse.v2);
+
+        // Stale entry, one of the vertices changed since it was queued
+        if vertex_removed[v1] || vertex_removed[v2]
+            || versions[v1] != collapse.version1 || versions[v2] != collapse.version2 {
+            continue;
+        }
+
+        // Link condition: the only shared neighbours may be the ones opposite the edge,
+        // otherwise the collapse pinches the surface into a non-manifold shape
+        let n1 = neighbours(&triangles, &tri_removed, &vertex_tris[v1], v1);
+        let n2 = neighbours(&triangles, &tri_removed, &vertex_tris[v2], v2);
+        if !n1.contains(&v2) { continue; }
+
+        let opposite: HashSet<usize> = vertex_tris[v1]
+            .iter()
+            .filter(|tri| !tri_removed[**tri] && triangles[**tri].contains(&v2))
+            .flat_map(|tri| triangles[*tri].iter().copied())
+            .filter(|v| *v != v1 && *v != v2)
+            .collect();
+        if n1.intersection(&n2).any(|v| !opposite.contains(v)) { continue; }
+
+        // Reject collapses that flip or degenerate the remaining faces
+        let new_vertex = interpolate_vertex(&vertices[v1], &vertices[v2], collapse.t as f32);
+        let new_pos = to_f64(new_vertex.position);
+        let flips = vertex_tris[v1].iter().chain(vertex_tris[v2].iter())
+            .filter(|tri| !tri_removed[**tri])
+            .map(|tri| triangles[*tri])
+            .filter(|tri| !(tri.contains(&v1) && tri.contains(&v2)))
+            .any(|tri| {
+                let before = tri.map(|v| to_f64(vertices[v].position));
+                let after = tri.map(|v| if v == v1 || v == v2 { new_pos } else { to_f64(vertices[v].position) });
+                let n_before = cross3(sub3(before[1], before[0]), sub3(before[2], before[0]));
+                let n_after = cross3(sub3(after[1], after[0]), sub3(after[2], after[0]));
+
+                dot3(n_before, n_after) <= 0.0
+            });
+        if flips { continue; }
+
+        // Collapse v2 into v1
+        vertices[v1] = new_vertex;
+        let q2 = quadrics[v2];
+        add_quadric(&mut quadrics[v1], &q2);
+        vertex_removed[v2] = true;
+        versions[v1] += 1;
+
+        for tri in std::mem::take(&mut vertex_tris[v2]) {
+            if tri_removed[tri] { continue; }
+
+            if triangles[tri].contains(&v1) {
+                tri_removed[tri] = true;
+                live_count -= 1;
+            } else {
+                for v in triangles[tri].iter_mut() {
+                    if *v == v2 { *v = v1; }
+                }
+
+                vertex_tris[v1].push(tri);
+            }
+        }
+        vertex_tris[v1].retain(|tri| !tri_removed[*tri]);
+
+        for neighbour in neighbours(&triangles, &tri_removed, &vertex_tris[v1], v1) {
+            heap.push(evaluate(&vertices, &quadrics, &versions, v1, neighbour));
+        }
+    }
+
+    // Compact remaining vertices, keeping their original order
+    let mut remap = vec![u32::MAX; vertices.len()];
+    let mut used = vec![false; vertices.len()];
+    for (tri, removed) in triangles.iter().zip(tri_removed.iter()) {
+        if *removed { continue; }
+
+        for v in tri.iter() { used[*v] = true; }
+    }
+
+    let mut out_vertices = Vec::new();
+    for (i, vertex) in vertices.iter().enumerate() {
+        if used[i] {
+            remap[i] = out_vertices.len() as u32;
+            out_vertices.push(*vertex);
+        }
+    }
+
+    let out_indices = triangles
+        .iter()
+        .zip(tri_removed.iter())
+        .filter(|(_, removed)| !**removed)
+        .flat_map(|(tri, _)| tri.map(|v| remap[v]))
+        .collect();
+
+    (out_vertices, out_indices)
+}
+
+
+const VERTEX_CACHE_SIZE: usize = 32;
+
+// Tom Forsyth's linear-speed vertex cache score
+fn vertex_cache_score(cache_position: Option<usize>, remaining_tris: usize) -> f32 {
+    if remaining_tris == 0 {
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        // The last triangle's vertices get a fixed score so its neighbours aren't favoured too much
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (VERTEX_CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0
+    };
+
+    // Prefer finishing off vertices with few triangles left
+    cache_score + 2.0 * (remaining_tris as f32).powf(-0.5)
+}
+
+// Reorders triangles to improve post-transform vertex cache hits (Forsyth).
+// Only the order of triangles changes, every triangle keeps its winding
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let tri_count = indices.len() / 3;
+
+    let mut vertex_tris: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for (tri, chunk) in indices.chunks_exact(3).enumerate() {
+        for v in chunk.iter() {
+            vertex_tris[*v as usize].push(tri);
+        }
+    }
+
+    let mut remaining: Vec<usize> = vertex_tris.iter().map(|tris| tris.len()).collect();
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = remaining.iter().map(|count| vertex_cache_score(None, *count)).collect();
+    let mut tri_scores: Vec<f32> = indices
+        .chunks_exact(3)
+        .map(|tri| tri.iter().map(|v| vertex_scores[*v as usize]).sum())
+        .collect();
+    let mut emitted = vec![false; tri_count];
+
+    let mut cache: Vec<usize> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut result = Vec::with_capacity(tri_count * 3);
+    let mut best_tri: Option<usize> = None;
+    let mut scan_start = 0;
+
+    for _ in 0..tri_count {
+        // Fall back to a linear scan when nothing in the cache has triangles left
+        let tri = match best_tri {
+            Some(tri) => tri,
+            None => {
+                while emitted[scan_start] { scan_start += 1; }
+
+                (scan_start..tri_count)
+                    .filter(|tri| !emitted[*tri])
+                    .fold(scan_start, |best, tri| if tri_scores[tri] > tri_scores[best] { tri } else { best })
+            }
+        };
+
+        emitted[tri] = true;
+        let tri_vertices = [indices[tri * 3] as usize, indices[tri * 3 + 1] as usize, indices[tri * 3 + 2] as usize];
+        result.extend(tri_vertices.iter().map(|v| *v as u32));
+
+        for v in tri_vertices.iter() {
+            remaining[*v] -= 1;
+            vertex_tris[*v].retain(|other| *other != tri);
+        }
+
+        // Emitted vertices move to the front of the LRU cache
+        let mut new_cache: Vec<usize> = tri_vertices.to_vec();
+        new_cache.dedup();
+        new_cache.extend(cache.iter().filter(|v| !tri_vertices.contains(v)));
+
+        // Vertices pushed out of the cache lose their cache score
+        for v in new_cache.iter().skip(VERTEX_CACHE_SIZE) {
+            cache_position[*v] = None;
+        }
+        let mut touched = new_cache.clone();
+        new_cache.truncate(VERTEX_CACHE_SIZE);
+
+        for (position, v) in new_cache.iter().enumerate() {
+            cache_position[*v] = Some(position);
+        }
+        cache = new_cache;
+
+        for v in touched.drain(..) {
+            let new_score = vertex_cache_score(cache_position[v], remaining[v]);
+            let delta = new_score - vertex_scores[v];
+            vertex_scores[v] = new_score;
+
+            for other in vertex_tris[v].iter() {
+                tri_scores[*other] += delta;
+            }
+        }
 
-        vertices[index1].bitangent = bitangent;
-        vertices[index2].bitangent = bitangent;
-        vertices[index3].bitangent = bitangent;
+        best_tri = cache
+            .iter()
+            .flat_map(|v| vertex_tris[*v].iter().copied())
+            .fold(None, |best: Option<usize>, tri| match best {
+                Some(best) if tri_scores[best] >= tri_scores[tri] => Some(best),
+                _ => Some(tri)
+            });
     }
-}
\ No newline at end of file
+
+    result
+}
+
+// Reorders vertices in the order they are first used so vertex fetches are more linear,
+// indices are remapped to match. Unused vertices are dropped
+pub fn optimize_vertex_fetch(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut remap = vec![u32::MAX; vertices.len()];
+    let mut out_vertices = Vec::with_capacity(vertices.len());
+
+    let out_indices = indices
+        .iter()
+        .map(|index| {
+            let index = *index as usize;
+
+            if remap[index] == u32::MAX {
+                remap[index] = out_vertices.len() as u32;
+                out_vertices.push(vertices[index]);
+            }
+
+            remap[index]
+        })
+        .collect();
+
+    (out_vertices, out_indices)
+}
+
+// Negative scale on an odd number of axes turns the winding order around,
+// meshes drawn with such a transform need Mesh::flip_winding
+pub fn is_mirrored(transform: &Matrix4<f32>) -> bool {
+    transform.determinant() < 0.0
+}
+
+// Ritter's approximate bounding sphere as (center, radius), at most ~5% larger than the
+// minimal one. Returns None if there are no points
+pub fn calc_bounding_sphere(points: &[Vector3<f32>]) -> Option<(Vector3<f32>, f32)> {
+    let farthest_from = |from: Vector3<f32>| {
+        points.iter().copied().fold(from, |farthest, point| {
+            if (point - from).magnitude2() > (farthest - from).magnitude2() { point } else { farthest }
+        })
+    };
+
+    // Initial sphere spans two roughly opposite points
+    let x = farthest_from(*points.first()?);
+    let y = farthest_from(x);
+    let mut center = (x + y) * 0.5;
+    let mut radius = (y - x).magnitude() * 0.5;
+
+    // Grows just enough to take in each point left outside
+    for point in points.iter() {
+        let distance = (point - center).magnitude();
+
+        if distance > radius {
+            let new_radius = (radius + distance) * 0.5;
+            center += (point - center) * ((new_radius - radius) / distance);
+            radius = new_radius;
+        }
+    }
+
+    Some((center, radius))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{vec3, InnerSpace};
+    use crate::{Aabb, Vertex};
+    use super::{simplify, optimize_vertex_cache, optimize_vertex_fetch};
+
+    // Closed unit sphere without seams, rings of slices vertices between two single pole vertices
+    fn uv_sphere(stacks: u32, slices: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = vec![Vertex { position: vec3(0.0, 1.0, 0.0), ..Default::default() }];
+
+        for stack in 1..stacks {
+            let phi = std::f32::consts::PI * stack as f32 / stacks as f32;
+
+            for slice in 0..slices {
+                let theta = std::f32::consts::TAU * slice as f32 / slices as f32;
+                let position = vec3(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+
+                vertices.push(Vertex { position, normal: position.normalize(), ..Default::default() });
+            }
+        }
+
+        let bottom = vertices.len() as u32;
+        vertices.push(Vertex { position: vec3(0.0, -1.0, 0.0), ..Default::default() });
+
+        let ring = |stack: u32, slice: u32| 1 + (stack - 1) * slices + slice % slices;
+        let mut indices = Vec::new();
+
+        for slice in 0..slices {
+            indices.extend([0, ring(1, slice + 1), ring(1, slice)]);
+            indices.extend([bottom, ring(stacks - 1, slice), ring(stacks - 1, slice + 1)]);
+
+            for stack in 1..stacks - 1 {
+                let (a, b) = (ring(stack, slice), ring(stack, slice + 1));
+                let (c, d) = (ring(stack + 1, slice), ring(stack + 1, slice + 1));
+
+                indices.extend([a, b, c, b, d, c]);
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    #[test]
+    fn simplify_sphere_keeps_bounds() {
+        let (vertices, indices) = uv_sphere(16, 32);
+        let (out_vertices, out_indices) = simplify(&vertices, &indices, 0.25);
+
+        assert!(out_indices.len() < indices.len());
+        assert!(out_indices.iter().all(|index| (*index as usize) < out_vertices.len()));
+
+        let before = Aabb::from_vertices(&vertices).unwrap();
+        let after = Aabb::from_vertices(&out_vertices).unwrap();
+
+        assert!((before.min - after.min).magnitude() < 0.1);
+        assert!((before.max - after.max).magnitude() < 0.1);
+    }
+
+    // Triangles by vertex position, each rotated to start at its smallest corner so
+    // the winding is kept but where the list starts doesn't matter
+    fn triangle_set(vertices: &[Vertex], indices: &[u32]) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<[[u32; 3]; 3]> = indices.chunks_exact(3).map(|tri| {
+            let mut corners = [0, 1, 2].map(|i| {
+                let position = vertices[tri[i] as usize].position;
+                [position.x.to_bits(), position.y.to_bits(), position.z.to_bits()]
+            });
+            let start = (0..3).min_by_key(|i| corners[*i]).unwrap();
+            corners.rotate_left(start);
+
+            corners
+        }).collect();
+
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn optimize_vertex_cache_keeps_triangles() {
+        let (vertices, indices) = uv_sphere(8, 16);
+        let optimized = optimize_vertex_cache(&indices, vertices.len());
+
+        assert_eq!(triangle_set(&vertices, &indices), triangle_set(&vertices, &optimized));
+    }
+
+    #[test]
+    fn optimize_vertex_fetch_keeps_triangles() {
+        let (vertices, indices) = uv_sphere(8, 16);
+        let indices = optimize_vertex_cache(&indices, vertices.len());
+        let (out_vertices, out_indices) = optimize_vertex_fetch(&vertices, &indices);
+
+        assert_eq!(out_vertices.len(), vertices.len());
+        assert_eq!(triangle_set(&vertices, &indices), triangle_set(&out_vertices, &out_indices));
+    }
+}
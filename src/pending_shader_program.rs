@@ -0,0 +1,109 @@
+use super::{ShaderProgram, ShaderCodeBundle, ShaderCompileType, GlError, gl, capabilities};
+
+// Handle to a program still being compiled, see ShaderProgram::new_async
+pub struct PendingShaderProgram {
+    id: u32,
+    shader_ids: Vec<(u32, ShaderCompileType)>,
+    // Without the extension there is no completion status to query
+    parallel: bool,
+    done: bool
+}
+
+impl PendingShaderProgram {
+    pub fn new(shader_bundle: ShaderCodeBundle) -> Result<PendingShaderProgram, GlError> {
+        let parallel = capabilities::has_extension("GL_KHR_parallel_shader_compile");
+        let mut pending = PendingShaderProgram {
+            id: 0,
+            shader_ids: Vec::new(),
+            parallel,
+            done: false
+        };
+
+        unsafe {
+            // Let the driver pick how many threads to use
+            if parallel { gl::MaxShaderCompilerThreadsKHR(u32::MAX); }
+
+            for (code, type_) in shader_bundle.get_vec() {
+                if let Some(code) = code {
+                    let id = ShaderProgram::start_compile_shader(code, type_.clone())?;
+                    pending.shader_ids.push((id, type_));
+                }
+            }
+
+            pending.id = gl::CreateProgram();
+
+            for (id, _) in pending.shader_ids.iter() {
+                gl::AttachShader(pending.id, *id);
+            }
+
+            gl::LinkProgram(pending.id);
+        }
+
+        Ok(pending)
+    }
+
+    // None while the driver is still compiling, a result is only given once
+    pub fn poll(&mut self) -> Option<Result<ShaderProgram, GlError>> {
+        if self.done {
+            return None;
+        }
+
+        if self.parallel {
+            let mut complete = gl::FALSE as gl::types::GLint;
+            unsafe { gl::GetProgramiv(self.id, gl::COMPLETION_STATUS_KHR, &mut complete) };
+
+            if complete != gl::TRUE as gl::types::GLint {
+                return None;
+            }
+        }
+
+        self.done = true;
+
+        Some(self.finish())
+    }
+
+    // Blocks until compilation finishes
+    pub fn wait(mut self) -> Result<ShaderProgram, GlError> {
+        self.done = true;
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<ShaderProgram, GlError> {
+        unsafe {
+            // Shader errors are more useful than the link error they cause
+            let result = self.shader_ids.iter()
+                .try_for_each(|(id, type_)| ShaderProgram::check_compile_errors(*id, type_.clone()))
+                .and_then(|_| ShaderProgram::check_compile_errors(self.id, ShaderCompileType::Program));
+
+            let program = result.map(|_| {
+                let mut program = ShaderProgram::from_raw(std::mem::take(&mut self.id));
+                program.collect_warnings(&self.shader_ids);
+                program
+            });
+
+            for (id, _) in self.shader_ids.drain(..) {
+                gl::DeleteShader(id);
+            }
+
+            if program.is_err() {
+                gl::DeleteProgram(std::mem::take(&mut self.id));
+            }
+
+            program
+        }
+    }
+}
+
+impl Drop for PendingShaderProgram {
+    fn drop(&mut self) {
+        unsafe {
+            for (id, _) in self.shader_ids.iter() {
+                gl::DeleteShader(*id);
+            }
+
+            if self.id != 0 {
+                gl::DeleteProgram(self.id);
+            }
+        }
+    }
+}
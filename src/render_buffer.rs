@@ -1,39 +1,57 @@
 use super::gl;
 
 pub struct RenderBuffer {
-    id: u32
+    id: u32,
+    samples: i32,
+    internal_format: gl::types::GLenum
 }
 
 impl RenderBuffer {
     // Requires framebuffer to be bound
     pub fn new(width: i32, height: i32) -> RenderBuffer {
+        RenderBuffer::new_multisample(width, height, 0)
+    }
+
+    // A sample count of 0 is the same as new
+    pub fn new_multisample(width: i32, height: i32, samples: i32) -> RenderBuffer {
+        RenderBuffer::new_format(width, height, samples, gl::DEPTH24_STENCIL8)
+    }
+
+    // Depth only (e.g. gl::DEPTH_COMPONENT32F) or depth stencil, instead of the default gl::DEPTH24_STENCIL8
+    pub fn new_format(width: i32, height: i32, samples: i32, internal_format: gl::types::GLenum) -> RenderBuffer {
         let mut renderbuffer = RenderBuffer {
-            id: 0
+            id: 0,
+            samples,
+            internal_format
         };
 
         unsafe {
             // Create renderbuffer
             gl::CreateRenderbuffers(1, &mut renderbuffer.id);
-            gl::NamedRenderbufferStorage(
-                renderbuffer.id,
-                gl::DEPTH24_STENCIL8,
-                width,
-                height
-            );
+            renderbuffer.resize(width, height);
         }
 
         renderbuffer
     }
 
     pub unsafe fn resize(&self, width: i32, height: i32) {
-        gl::NamedRenderbufferStorage(
+        gl::NamedRenderbufferStorageMultisample(
             self.id,
-            gl::DEPTH24_STENCIL8,
+            self.samples,
+            self.internal_format,
             width,
             height
         );
     }
 
+    pub fn get_internal_format(&self) -> gl::types::GLenum {
+        self.internal_format
+    }
+
+    pub fn get_samples(&self) -> i32 {
+        self.samples
+    }
+
     pub fn get_id(&self) -> u32 {
         self.id
     }
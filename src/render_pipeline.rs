@@ -16,6 +16,22 @@ pub trait RenderPipeline {
     fn link_push(&mut self, texture: Rc<Texture>) -> Result<(), GlError>;
     fn unlink(&mut self);
 
+    // Framebuffer the pipeline renders into, so bind_pass can honor its clear_mask
+    fn get_framebuffer(&self) -> Option<&Framebuffer> {
+        None
+    }
+
+    // Binds for a pass, clearing whatever the framebuffer's clear_mask declares so passes
+    // don't accumulate by accident (see Framebuffer::bind_pass). Depth is cleared to the
+    // framebuffer's default_clear_depth, so set that to 0.0 on every pass with reverse-Z.
+    // Without a framebuffer this is bind, which doesn't clear
+    fn bind_pass(&self) {
+        match self.get_framebuffer() {
+            Some(framebuffer) => framebuffer.bind_pass(),
+            None => self.bind()
+        }
+    }
+
     // These are here to simplify and expand usage of render pipeline
 
     // render_pipeline output -> self input
@@ -0,0 +1,482 @@
+use cgmath::{Matrix4, Rad};
+use super::{ModelTrait, ShaderProgram, GlError, StateCache, gl};
+
+// Texture targets saved per unit, as (binding query, target)
+const TEXTURE_TARGETS: [(gl::types::GLenum, gl::types::GLenum); 5] = [
+    (gl::TEXTURE_BINDING_2D, gl::TEXTURE_2D),
+    (gl::TEXTURE_BINDING_2D_ARRAY, gl::TEXTURE_2D_ARRAY),
+    (gl::TEXTURE_BINDING_2D_MULTISAMPLE, gl::TEXTURE_2D_MULTISAMPLE),
+    (gl::TEXTURE_BINDING_3D, gl::TEXTURE_3D),
+    (gl::TEXTURE_BINDING_CUBE_MAP, gl::TEXTURE_CUBE_MAP)
+];
+
+// Tracks global GL state that isn't owned by any single object
+pub struct RenderState {
+    point_size: f32,
+    program_point_size: bool,
+    line_width: f32,
+    depth_func: gl::types::GLenum,
+    depth_clamp: bool,
+    color_mask: bool,
+    depth_mask: bool,
+    front_face: gl::types::GLenum,
+    clear_colors: Vec<[f32; 4]>,
+    reverse_z: bool,
+    alpha_to_coverage: bool
+}
+
+impl RenderState {
+    // Starts from the GL defaults
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> RenderState {
+        RenderState {
+            point_size: 1.0,
+            program_point_size: false,
+            line_width: 1.0,
+            depth_func: gl::LESS,
+            depth_clamp: false,
+            color_mask: true,
+            depth_mask: true,
+            front_face: gl::CCW,
+            clear_colors: Vec::new(),
+            reverse_z: false,
+            alpha_to_coverage: false
+        }
+    }
+
+    // Only used when gl_PointSize is not written by the shader
+    pub fn set_point_size(&mut self, size: f32) {
+        unsafe {
+            gl::PointSize(size);
+        }
+
+        self.point_size = size;
+    }
+
+    // Lets vertex shaders control point size through gl_PointSize
+    pub fn enable_program_point_size(&mut self) {
+        unsafe {
+            gl::Enable(gl::PROGRAM_POINT_SIZE);
+        }
+
+        self.program_point_size = true;
+    }
+
+    pub fn disable_program_point_size(&mut self) {
+        unsafe {
+            gl::Disable(gl::PROGRAM_POINT_SIZE);
+        }
+
+        self.program_point_size = false;
+    }
+
+    // Core profile only guarantees a width of 1.0, wider lines are driver dependent
+    pub fn set_line_width(&mut self, width: f32) {
+        unsafe {
+            gl::LineWidth(width);
+        }
+
+        self.line_width = width;
+    }
+
+    // Skyboxes drawn at the far plane (z = w in the vertex shader) need gl::LEQUAL,
+    // otherwise they fail against a depth buffer cleared to 1.0. Set back to gl::LESS after
+    pub fn set_depth_func(&mut self, func: gl::types::GLenum) {
+        unsafe {
+            gl::DepthFunc(func);
+        }
+
+        self.depth_func = func;
+    }
+
+    // Clamps depth instead of clipping against the near and far planes,
+    // useful for shadow casters behind the light's near plane
+    pub fn set_depth_clamp(&mut self, enabled: bool) {
+        unsafe {
+            if enabled {
+                gl::Enable(gl::DEPTH_CLAMP);
+            } else {
+                gl::Disable(gl::DEPTH_CLAMP);
+            }
+        }
+
+        self.depth_clamp = enabled;
+    }
+
+    // Turns fragment alpha into a sample mask, giving smooth cutout edges (e.g. foliage) without
+    // blending or sorting. Only has an effect while a Framebuffer::new_multisample target is bound
+    pub fn set_alpha_to_coverage(&mut self, enabled: bool) {
+        unsafe {
+            if enabled {
+                gl::Enable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+            } else {
+                gl::Disable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+            }
+        }
+
+        self.alpha_to_coverage = enabled;
+    }
+
+    // Maps window depth to [near, far] instead of [0, 1], e.g. to keep a HUD in front of the scene
+    pub fn set_depth_range(&mut self, near: f32, far: f32) {
+        unsafe {
+            gl::DepthRangef(near, far);
+        }
+    }
+
+    // Reverse-Z stores the near plane at 1 and the far plane at 0, which spreads float depth
+    // precision evenly with distance. Enabling it switches clip space z to [0, 1], the depth
+    // func to gl::GREATER and the glClear depth to 0. The rest has to be changed to match:
+    // - projections come from reverse_z_perspective
+    // - depth values passed to Framebuffer::clear_depth and default_clear_depth come from get_clear_depth
+    // - depth attachments should be float (gl::DEPTH32F_STENCIL8), 24 bit fixed point gains little
+    // - shaders that linearize depth or compare against it (shadows, SSAO) need flipping
+    pub fn set_reverse_z(&mut self, enabled: bool) {
+        let (depth_mode, clear_depth) = if enabled {
+            (gl::ZERO_TO_ONE, 0.0)
+        } else {
+            (gl::NEGATIVE_ONE_TO_ONE, 1.0)
+        };
+
+        unsafe {
+            gl::ClipControl(gl::LOWER_LEFT, depth_mode);
+            gl::ClearDepth(clear_depth);
+        }
+
+        self.reverse_z = enabled;
+        self.set_depth_func(self.get_depth_less());
+    }
+
+    // Depth func that passes nearer fragments, gl::LESS or gl::GREATER with reverse-Z
+    pub fn get_depth_less(&self) -> gl::types::GLenum {
+        if self.reverse_z { gl::GREATER } else { gl::LESS }
+    }
+
+    // Depth of the far plane, what depth buffers should be cleared to
+    pub fn get_clear_depth(&self) -> f32 {
+        if self.reverse_z { 0.0 } else { 1.0 }
+    }
+
+    pub fn is_reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    // Captures the state this crate changes, for sharing a context with other GL code.
+    // The state cache is invalidated since the other code may have bound anything
+    pub fn snapshot() -> SavedState {
+        let get = |name: gl::types::GLenum| {
+            let mut value = 0;
+            unsafe { gl::GetIntegerv(name, &mut value) };
+            value
+        };
+        let get_float = |name: gl::types::GLenum| {
+            let mut value = 0.0;
+            unsafe { gl::GetFloatv(name, &mut value) };
+            value
+        };
+        let enabled = |cap: gl::types::GLenum| unsafe { gl::IsEnabled(cap) == gl::TRUE };
+
+        let active_texture = get(gl::ACTIVE_TEXTURE) as u32;
+        let texture_units = (0..get(gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS) as u32).map(|unit| {
+            unsafe { gl::ActiveTexture(gl::TEXTURE0 + unit) };
+            TEXTURE_TARGETS.map(|(binding, _)| get(binding) as u32)
+        }).collect();
+        unsafe { gl::ActiveTexture(active_texture) };
+
+        let uniform_buffers = (0..get(gl::MAX_UNIFORM_BUFFER_BINDINGS) as u32).map(|index| {
+            let (mut id, mut offset, mut size) = (0, 0, 0);
+            unsafe {
+                gl::GetIntegeri_v(gl::UNIFORM_BUFFER_BINDING, index, &mut id);
+                gl::GetInteger64i_v(gl::UNIFORM_BUFFER_START, index, &mut offset);
+                gl::GetInteger64i_v(gl::UNIFORM_BUFFER_SIZE, index, &mut size);
+            }
+
+            (id as u32, offset as isize, size as isize)
+        }).collect();
+
+        let mut viewport = [0; 4];
+        let mut color_mask = [gl::TRUE; 4];
+        let mut depth_mask = gl::TRUE;
+        let mut clear_depth = 1.0;
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            gl::GetBooleanv(gl::COLOR_WRITEMASK, color_mask.as_mut_ptr());
+            gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_mask);
+            gl::GetDoublev(gl::DEPTH_CLEAR_VALUE, &mut clear_depth);
+        }
+
+        StateCache::invalidate();
+
+        SavedState {
+            program: get(gl::CURRENT_PROGRAM) as u32,
+            vertex_array: get(gl::VERTEX_ARRAY_BINDING) as u32,
+            draw_framebuffer: get(gl::DRAW_FRAMEBUFFER_BINDING) as u32,
+            read_framebuffer: get(gl::READ_FRAMEBUFFER_BINDING) as u32,
+            active_texture,
+            texture_units,
+            array_buffer: get(gl::ARRAY_BUFFER_BINDING) as u32,
+            element_array_buffer: get(gl::ELEMENT_ARRAY_BUFFER_BINDING) as u32,
+            uniform_buffer: get(gl::UNIFORM_BUFFER_BINDING) as u32,
+            uniform_buffers,
+            draw_indirect_buffer: get(gl::DRAW_INDIRECT_BUFFER_BINDING) as u32,
+            viewport,
+            blend: enabled(gl::BLEND),
+            depth_test: enabled(gl::DEPTH_TEST),
+            cull_face: enabled(gl::CULL_FACE),
+            scissor_test: enabled(gl::SCISSOR_TEST),
+            depth_clamp: enabled(gl::DEPTH_CLAMP),
+            program_point_size: enabled(gl::PROGRAM_POINT_SIZE),
+            alpha_to_coverage: enabled(gl::SAMPLE_ALPHA_TO_COVERAGE),
+            point_size: get_float(gl::POINT_SIZE),
+            line_width: get_float(gl::LINE_WIDTH),
+            depth_func: get(gl::DEPTH_FUNC) as u32,
+            front_face: get(gl::FRONT_FACE) as u32,
+            clip_depth_mode: get(gl::CLIP_DEPTH_MODE) as u32,
+            clear_depth,
+            blend_src_rgb: get(gl::BLEND_SRC_RGB) as u32,
+            blend_dst_rgb: get(gl::BLEND_DST_RGB) as u32,
+            blend_src_alpha: get(gl::BLEND_SRC_ALPHA) as u32,
+            blend_dst_alpha: get(gl::BLEND_DST_ALPHA) as u32,
+            color_mask,
+            depth_mask
+        }
+    }
+
+    // Also brings the tracked values of self back in line with the restored state
+    pub fn restore(&mut self, saved: &SavedState) {
+        let set = |cap: gl::types::GLenum, enabled: bool| unsafe {
+            if enabled { gl::Enable(cap) } else { gl::Disable(cap) }
+        };
+
+        unsafe {
+            gl::UseProgram(saved.program);
+            gl::BindVertexArray(saved.vertex_array);
+
+            for (unit, ids) in saved.texture_units.iter().enumerate() {
+                gl::ActiveTexture(gl::TEXTURE0 + unit as u32);
+                for ((_, target), &id) in TEXTURE_TARGETS.iter().zip(ids.iter()) {
+                    gl::BindTexture(*target, id);
+                }
+            }
+
+            // Indexed bindings also set the generic binding, so it goes after them
+            for (index, &(id, offset, size)) in saved.uniform_buffers.iter().enumerate() {
+                if id != 0 && size > 0 {
+                    gl::BindBufferRange(gl::UNIFORM_BUFFER, index as u32, id, offset, size);
+                } else {
+                    gl::BindBufferBase(gl::UNIFORM_BUFFER, index as u32, id);
+                }
+            }
+
+            // The element buffer is part of the vertex array, core profile has none without one
+            if saved.vertex_array != 0 {
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, saved.element_array_buffer);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, saved.array_buffer);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, saved.uniform_buffer);
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, saved.draw_indirect_buffer);
+
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, saved.draw_framebuffer);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, saved.read_framebuffer);
+            gl::ActiveTexture(saved.active_texture);
+            gl::Viewport(saved.viewport[0], saved.viewport[1], saved.viewport[2], saved.viewport[3]);
+            gl::PointSize(saved.point_size);
+            gl::LineWidth(saved.line_width);
+            gl::DepthFunc(saved.depth_func);
+            gl::FrontFace(saved.front_face);
+            gl::ClipControl(gl::LOWER_LEFT, saved.clip_depth_mode);
+            gl::ClearDepth(saved.clear_depth);
+            gl::BlendFuncSeparate(saved.blend_src_rgb, saved.blend_dst_rgb, saved.blend_src_alpha, saved.blend_dst_alpha);
+            gl::ColorMask(saved.color_mask[0], saved.color_mask[1], saved.color_mask[2], saved.color_mask[3]);
+            gl::DepthMask(saved.depth_mask);
+        }
+
+        set(gl::BLEND, saved.blend);
+        set(gl::DEPTH_TEST, saved.depth_test);
+        set(gl::CULL_FACE, saved.cull_face);
+        set(gl::SCISSOR_TEST, saved.scissor_test);
+        set(gl::DEPTH_CLAMP, saved.depth_clamp);
+        set(gl::PROGRAM_POINT_SIZE, saved.program_point_size);
+        set(gl::SAMPLE_ALPHA_TO_COVERAGE, saved.alpha_to_coverage);
+
+        StateCache::invalidate();
+
+        self.point_size = saved.point_size;
+        self.program_point_size = saved.program_point_size;
+        self.line_width = saved.line_width;
+        self.depth_func = saved.depth_func;
+        self.depth_clamp = saved.depth_clamp;
+        self.color_mask = saved.color_mask.iter().all(|&mask| mask == gl::TRUE);
+        self.depth_mask = saved.depth_mask == gl::TRUE;
+        self.front_face = saved.front_face;
+        self.reverse_z = saved.clip_depth_mode == gl::ZERO_TO_ONE;
+        self.alpha_to_coverage = saved.alpha_to_coverage;
+    }
+
+    // gl::CCW by default, gl::CW for assets authored with clockwise winding
+    pub fn set_front_face(&mut self, front_face: gl::types::GLenum) {
+        StateCache::set_front_face(front_face);
+
+        self.front_face = front_face;
+    }
+
+    // For code that has to use glClear, prefer Framebuffer::clear_color which leaves the
+    // global clear color alone. Every push should be matched with a pop_clear_color
+    pub fn push_clear_color(&mut self, color: [f32; 4]) {
+        let mut previous = [0.0; 4];
+
+        unsafe {
+            gl::GetFloatv(gl::COLOR_CLEAR_VALUE, previous.as_mut_ptr());
+            gl::ClearColor(color[0], color[1], color[2], color[3]);
+        }
+
+        self.clear_colors.push(previous);
+    }
+
+    // Restores the clear color from before the last push, does nothing if there wasn't one
+    pub fn pop_clear_color(&mut self) {
+        if let Some(color) = self.clear_colors.pop() {
+            unsafe {
+                gl::ClearColor(color[0], color[1], color[2], color[3]);
+            }
+        }
+    }
+
+    // Disabling stops writes to every channel of every color attachment
+    pub fn set_color_mask(&mut self, enabled: bool) {
+        let mask = if enabled { gl::TRUE } else { gl::FALSE };
+
+        unsafe {
+            gl::ColorMask(mask, mask, mask, mask);
+        }
+
+        self.color_mask = enabled;
+    }
+
+    pub fn set_depth_mask(&mut self, enabled: bool) {
+        unsafe {
+            gl::DepthMask(if enabled { gl::TRUE } else { gl::FALSE });
+        }
+
+        self.depth_mask = enabled;
+    }
+
+    // Fills the depth buffer without shading, so the main pass between begin_depth_equal_pass
+    // and end_depth_equal_pass only shades visible fragments. Only pass opaque models,
+    // masked meshes need the shader to discard using material.alphaCutoff
+    pub fn depth_prepass(&mut self, models: &[&dyn ModelTrait], shader_program: &ShaderProgram) -> Result<(), GlError> {
+        shader_program.use_program();
+
+        self.set_color_mask(false);
+        self.set_depth_mask(true);
+        self.set_depth_func(self.get_depth_less());
+
+        let result = models.iter().try_for_each(|model| model.draw(shader_program));
+
+        self.set_color_mask(true);
+
+        result
+    }
+
+    // Depth is already final after the prepass, so it doesn't need writing again
+    pub fn begin_depth_equal_pass(&mut self) {
+        self.set_depth_func(gl::EQUAL);
+        self.set_depth_mask(false);
+    }
+
+    pub fn end_depth_equal_pass(&mut self) {
+        self.set_depth_func(self.get_depth_less());
+        self.set_depth_mask(true);
+    }
+
+    pub fn get_point_size(&self) -> f32 {
+        self.point_size
+    }
+
+    pub fn is_program_point_size(&self) -> bool {
+        self.program_point_size
+    }
+
+    pub fn get_line_width(&self) -> f32 {
+        self.line_width
+    }
+
+    pub fn get_depth_func(&self) -> gl::types::GLenum {
+        self.depth_func
+    }
+
+    pub fn is_depth_clamp(&self) -> bool {
+        self.depth_clamp
+    }
+
+    pub fn is_alpha_to_coverage(&self) -> bool {
+        self.alpha_to_coverage
+    }
+
+    pub fn is_color_mask(&self) -> bool {
+        self.color_mask
+    }
+
+    pub fn is_depth_mask(&self) -> bool {
+        self.depth_mask
+    }
+
+    pub fn get_front_face(&self) -> gl::types::GLenum {
+        self.front_face
+    }
+}
+
+// Perspective projection for reverse-Z (see RenderState::set_reverse_z), mapping near to depth 1
+// and far to 0. An infinite far plane is used if far is None, which reverse-Z handles without
+// losing precision
+pub fn reverse_z_perspective(fovy: Rad<f32>, aspect: f32, near: f32, far: Option<f32>) -> Matrix4<f32> {
+    let f = 1.0 / (fovy.0 * 0.5).tan();
+    let (a, b) = match far {
+        Some(far) => (near / (far - near), near * far / (far - near)),
+        None => (0.0, near)
+    };
+
+    Matrix4::new(
+        f / aspect, 0.0, 0.0, 0.0,
+        0.0, f, 0.0, 0.0,
+        0.0, 0.0, a, -1.0,
+        0.0, 0.0, b, 0.0
+    )
+}
+
+// GL state captured by RenderState::snapshot
+pub struct SavedState {
+    program: u32,
+    vertex_array: u32,
+    draw_framebuffer: u32,
+    read_framebuffer: u32,
+    active_texture: u32,
+    // Ids bound to each of TEXTURE_TARGETS, per unit
+    texture_units: Vec<[u32; 5]>,
+    array_buffer: u32,
+    element_array_buffer: u32,
+    uniform_buffer: u32,
+    // Id, offset and size of every indexed binding
+    uniform_buffers: Vec<(u32, isize, isize)>,
+    draw_indirect_buffer: u32,
+    viewport: [i32; 4],
+    blend: bool,
+    depth_test: bool,
+    cull_face: bool,
+    scissor_test: bool,
+    depth_clamp: bool,
+    program_point_size: bool,
+    alpha_to_coverage: bool,
+    point_size: f32,
+    line_width: f32,
+    depth_func: gl::types::GLenum,
+    front_face: gl::types::GLenum,
+    clip_depth_mode: gl::types::GLenum,
+    clear_depth: f64,
+    blend_src_rgb: gl::types::GLenum,
+    blend_dst_rgb: gl::types::GLenum,
+    blend_src_alpha: gl::types::GLenum,
+    blend_dst_alpha: gl::types::GLenum,
+    color_mask: [gl::types::GLboolean; 4],
+    depth_mask: gl::types::GLboolean
+}
@@ -0,0 +1,74 @@
+use std::cell::Cell;
+use super::gl;
+
+thread_local! {
+    static RENDER_STATS: Cell<RenderStats> = const { Cell::new(RenderStats::new()) };
+}
+
+// Work submitted through this crate on this thread since the last take_frame_stats.
+// Only counted with the render_stats feature, otherwise every field stays 0 and the
+// record_ calls compile to nothing. Each indirect command counts as a draw call, but its
+// triangles and instances live on the GPU so they aren't counted, and neither are draws
+// whose count comes from a parameter buffer
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    pub instances: u64,
+    pub buffer_bytes_uploaded: u64,
+    pub texture_binds: u32
+}
+
+impl RenderStats {
+    const fn new() -> RenderStats {
+        RenderStats {
+            draw_calls: 0,
+            triangles: 0,
+            instances: 0,
+            buffer_bytes_uploaded: 0,
+            texture_binds: 0
+        }
+    }
+
+    fn update(f: impl FnOnce(&mut RenderStats)) {
+        if cfg!(feature = "render_stats") {
+            RENDER_STATS.with(|stats| {
+                let mut current = stats.get();
+                f(&mut current);
+                stats.set(current);
+            });
+        }
+    }
+
+    // Returns the stats gathered so far and starts counting from 0, call once per frame
+    pub fn take_frame_stats() -> RenderStats {
+        RENDER_STATS.with(|stats| stats.take())
+    }
+
+    // count is the number of vertices or indices drawn per instance
+    pub fn record_draw(mode: gl::types::GLenum, count: i32, instance_count: i32) {
+        let triangles = match mode {
+            gl::TRIANGLES => count / 3,
+            gl::TRIANGLE_STRIP | gl::TRIANGLE_FAN => (count - 2).max(0),
+            _ => 0
+        };
+
+        RenderStats::update(|stats| {
+            stats.draw_calls += 1;
+            stats.triangles += triangles as u64 * instance_count as u64;
+            stats.instances += instance_count as u64;
+        });
+    }
+
+    pub fn record_indirect_draws(draw_count: i32) {
+        RenderStats::update(|stats| stats.draw_calls += draw_count as u32);
+    }
+
+    pub fn record_upload(bytes: usize) {
+        RenderStats::update(|stats| stats.buffer_bytes_uploaded += bytes as u64);
+    }
+
+    pub fn record_texture_binds(count: usize) {
+        RenderStats::update(|stats| stats.texture_binds += count as u32);
+    }
+}
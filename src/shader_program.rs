@@ -1,13 +1,49 @@
-use std::{ffi::CString, fmt::Display};
+use std::{cell::RefCell, collections::HashSet, ffi::CString, fmt::Display, sync::atomic::{AtomicBool, Ordering}};
 use std::ptr;
 use cgmath::{Vector3, Array, Matrix4, Matrix, Vector4};
-use super::{error::GlError, gl};
-
-pub struct ShaderProgram { id: u32 }
+use super::{error::GlError, PendingShaderProgram, StateCache, gl};
+
+// Whether build logs and strict mode warnings are printed, see ShaderProgram::set_print_warnings
+static PRINT_WARNINGS: AtomicBool = AtomicBool::new(false);
+
+pub struct ShaderProgram {
+    id: u32,
+    // Set once GL_LINK_STATUS has been checked to be true
+    linked: bool,
+    // Compile and link logs from a successful build, usually driver warnings
+    info_log: String,
+    // Unsafe setters warn about inactive uniforms when set, see set_strict
+    strict: bool,
+    warned_uniforms: RefCell<HashSet<String>>
+}
 
 impl ShaderProgram {
+    // Compiles in the background when GL_KHR_parallel_shader_compile is supported,
+    // otherwise compiles right away and the first poll gives the result
+    pub fn new_async(shader_bundle: ShaderCodeBundle) -> Result<PendingShaderProgram, GlError> {
+        PendingShaderProgram::new(shader_bundle)
+    }
+
+    // Takes ownership of an already linked program, it is deleted on drop.
+    // The link status is queried, so an unlinked program fails on bind instead of drawing garbage
+    pub fn from_raw(id: u32) -> ShaderProgram {
+        let mut status = gl::FALSE as gl::types::GLint;
+
+        if id != 0 {
+            unsafe { gl::GetProgramiv(id, gl::LINK_STATUS, &mut status) };
+        }
+
+        ShaderProgram {
+            id,
+            linked: status == gl::TRUE as gl::types::GLint,
+            info_log: String::new(),
+            strict: false,
+            warned_uniforms: RefCell::new(HashSet::new())
+        }
+    }
+
     pub fn new(shader_bundle: ShaderCodeBundle) -> Result<ShaderProgram, GlError> {
-        let mut shader_program = ShaderProgram { id: 0 };
+        let mut shader_program = ShaderProgram::from_raw(0);
 
         shader_program.compile_program(shader_bundle)?;
 
@@ -19,7 +55,7 @@ impl ShaderProgram {
 
         for (code, type_) in shader_bundle.get_vec() {
             if let Some(code) = code {
-                shader_ids.push(ShaderProgram::compile_shader(code, type_)?);
+                shader_ids.push((ShaderProgram::compile_shader(code, type_.clone())?, type_));
             }
         }
 
@@ -30,7 +66,7 @@ impl ShaderProgram {
 
             println!("DEBUG::SHADER::PROGRAM::ATTACHING_SHADERS");
 
-            for id in shader_ids.iter() {
+            for (id, _) in shader_ids.iter() {
                 gl::AttachShader(shader_program_id, *id);
             }
 
@@ -41,17 +77,33 @@ impl ShaderProgram {
 
             println!("DEBUG::SHADER::PROGRAM::COMPILATION_COMPLETE");
 
-            for id in shader_ids.iter() {
+            self.id = shader_program_id;
+            self.collect_warnings(&shader_ids);
+
+            for (id, _) in shader_ids.iter() {
                 gl::DeleteShader(*id);
             }
         }
 
-        self.id = shader_program_id;
+        self.linked = true;
 
         Ok(())
     }
 
     pub fn compile_shader(code: &str, type_: ShaderCompileType) -> Result<u32, GlError> {
+        let shader = ShaderProgram::start_compile_shader(code, type_.clone())?;
+
+        unsafe {
+            ShaderProgram::check_compile_errors(shader, type_.clone())?;
+        }
+
+        println!("DEBUG::SHADER::{}::COMPILATION_COMPLETE", type_);
+
+        Ok(shader)
+    }
+
+    // Compile errors aren't checked, so the driver can keep compiling in the background
+    pub fn start_compile_shader(code: &str, type_: ShaderCompileType) -> Result<u32, GlError> {
         // let mut shader_file = File::open(path)?;
         // let mut shader_code = String::new();
 
@@ -76,11 +128,8 @@ impl ShaderProgram {
             println!("DEBUG::SHADER::{}::COMPILING_SHADER", type_);
 
             gl::CompileShader(shader);
-            ShaderProgram::check_compile_errors(shader, type_.clone())?;
         }
 
-        println!("DEBUG::SHADER::{}::COMPILATION_COMPLETE", type_);
-
         Ok(shader)
     }
 
@@ -138,14 +187,82 @@ impl ShaderProgram {
         Ok(())
     }
 
+    // Link info log of a program, empty if the driver had nothing to report
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn get_link_log(id: u32) -> String {
+        let mut len: gl::types::GLint = 0;
+        gl::GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut len);
+
+        let mut buffer = vec![0u8; len.max(1) as usize];
+        gl::GetProgramInfoLog(id, len, ptr::null_mut(), buffer.as_mut_ptr() as *mut gl::types::GLchar);
+
+        info_log_to_string(buffer)
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn get_shader_log(id: u32) -> String {
+        let mut len: gl::types::GLint = 0;
+        gl::GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut len);
+
+        let mut buffer = vec![0u8; len.max(1) as usize];
+        gl::GetShaderInfoLog(id, len, ptr::null_mut(), buffer.as_mut_ptr() as *mut gl::types::GLchar);
+
+        info_log_to_string(buffer)
+    }
+
+    // Off by default. Prints the logs of successful builds, which hold deprecation and
+    // performance warnings (also kept in last_info_log), and the uniforms strict mode finds
+    pub fn set_print_warnings(enabled: bool) {
+        PRINT_WARNINGS.store(enabled, Ordering::Relaxed);
+    }
+
+    // Stores the non empty logs of the shaders and the program after a successful link,
+    // the shaders must not be deleted yet
+    pub(crate) unsafe fn collect_warnings(&mut self, shader_ids: &[(u32, ShaderCompileType)]) {
+        let logs = shader_ids.iter()
+            .map(|(id, type_)| (type_.clone(), ShaderProgram::get_shader_log(*id)))
+            .chain(std::iter::once((ShaderCompileType::Program, ShaderProgram::get_link_log(self.id))))
+            .filter(|(_, log)| !log.trim().is_empty());
+
+        self.info_log.clear();
+        for (type_, log) in logs {
+            if PRINT_WARNINGS.load(Ordering::Relaxed) {
+                println!("WARNING::SHADER::{}::{}", type_, log.trim_end());
+            }
+
+            self.info_log.push_str(&format!("{}: {}\n", type_, log.trim_end()));
+        }
+    }
+
+    // Empty if the last build produced no warnings
+    pub fn last_info_log(&self) -> &str {
+        &self.info_log
+    }
+
     pub fn use_program(&self) {
-        unsafe { gl::UseProgram(self.id) } // Cannot error as program always exists by this point
+        debug_assert!(self.linked, "Shader program {} used without being linked", self.id);
+
+        StateCache::use_program(self.id);
+    }
+
+    // Same as use_program, but an unlinked program gives an error with its link log
+    pub fn bind(&self) -> Result<(), GlError> {
+        if !self.linked {
+            return Err(GlError::ProgramNotLinked(self.id, unsafe { ShaderProgram::get_link_log(self.id) }));
+        }
+
+        StateCache::use_program(self.id);
+
+        Ok(())
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.linked
     }
 
     // Safe functions check whetehr the uniform exists, and passes an error
     pub unsafe fn set_uniform<F: Fn(i32)>(&self, name: &str, uniform_func: F) -> Result<(), GlError> {
-        let cstr = CString::new(name)?;
-        let location = gl::GetUniformLocation(self.id, cstr.as_ptr());
+        let location = self.get_uniform_location(name)?;
 
         if location == -1 {
             return Err(GlError::UniformNotFound(name.to_owned(), self.id));
@@ -204,10 +321,60 @@ impl ShaderProgram {
         }
     }
 
-    // Unsafe versions do not check whether the uniforms are present
-    pub unsafe fn set_uniform_unsafe<F: Fn(i32)>(&self, name: &str, uniform_func: F) -> Result<(), GlError> {
+    // Uploads a whole array in one call, name can be given with or without [0].
+    // Errors if there are more values than the array holds
+    pub fn set_vec3_array(&self, name: &str, values: &[Vector3<f32>]) -> Result<(), GlError> {
+        unsafe {
+            self.set_uniform_array(name, values.len(), |location| {
+                gl::Uniform3fv(location, values.len() as i32, values.as_ptr() as *const f32)
+            })
+        }
+    }
+
+    pub fn set_mat4_array(&self, name: &str, values: &[Matrix4<f32>]) -> Result<(), GlError> {
+        unsafe {
+            self.set_uniform_array(name, values.len(), |location| {
+                gl::UniformMatrix4fv(location, values.len() as i32, gl::FALSE, values.as_ptr() as *const f32)
+            })
+        }
+    }
+
+    pub fn set_float_array(&self, name: &str, values: &[f32]) -> Result<(), GlError> {
+        unsafe {
+            self.set_uniform_array(name, values.len(), |location| {
+                gl::Uniform1fv(location, values.len() as i32, values.as_ptr())
+            })
+        }
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn set_uniform_array<F: Fn(i32)>(&self, name: &str, len: usize, uniform_func: F) -> Result<(), GlError> {
         let cstr = CString::new(name)?;
-        let location = gl::GetUniformLocation(self.id, cstr.as_ptr());
+        let index = gl::GetProgramResourceIndex(self.id, gl::UNIFORM, cstr.as_ptr());
+
+        if index == gl::INVALID_INDEX {
+            return Err(GlError::UniformNotFound(name.to_owned(), self.id));
+        }
+
+        let mut size = 0;
+        gl::GetProgramResourceiv(self.id, gl::UNIFORM, index, 1, &gl::ARRAY_SIZE, 1, std::ptr::null_mut(), &mut size);
+
+        if len > size as usize {
+            return Err(GlError::UniformArrayTooLong(name.to_owned(), len, size));
+        }
+
+        self.set_uniform(name, uniform_func)
+    }
+
+    // Unsafe versions do not check whether the uniforms are present, in strict mode
+    // a missing one is printed the first time it is set if set_print_warnings is on
+    pub unsafe fn set_uniform_unsafe<F: Fn(i32)>(&self, name: &str, uniform_func: F) -> Result<(), GlError> {
+        let location = self.get_uniform_location(name)?;
+
+        let warn = self.strict && PRINT_WARNINGS.load(Ordering::Relaxed);
+        if location == -1 && warn && self.warned_uniforms.borrow_mut().insert(name.to_owned()) {
+            println!("WARNING::SHADER::PROGRAM::UNIFORM_NOT_FOUND '{}' in program {}", name, self.id);
+        }
 
         uniform_func(location);
 
@@ -246,22 +413,156 @@ impl ShaderProgram {
         self.set_uniform_unsafe(name, |location| gl::UniformMatrix4fv(location, 1, gl::FALSE, value.as_ptr()))
     }
 
-    pub fn bind_to_ubo(&self, name: &str) -> Result<(), GlError> {
+    // -1 if the uniform isn't active. Short names are terminated on the stack
+    // so setting uniforms every frame doesn't allocate
+    pub fn get_uniform_location(&self, name: &str) -> Result<i32, GlError> {
+        let mut buffer = [0u8; 128];
+
+        if name.len() < buffer.len() && !name.as_bytes().contains(&0) {
+            buffer[..name.len()].copy_from_slice(name.as_bytes());
+
+            return Ok(unsafe { gl::GetUniformLocation(self.id, buffer.as_ptr() as *const gl::types::GLchar) });
+        }
+
+        let cstr = CString::new(name)?;
+
+        Ok(unsafe { gl::GetUniformLocation(self.id, cstr.as_ptr()) })
+    }
+
+    // Uniforms the compiler found unused are optimized out and silently ignore writes,
+    // strict mode makes the unsafe setters print them (once per name, see set_print_warnings)
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    // For uniforms the program can't work without, errors if it was optimized out or misspelled
+    pub fn expect_uniform(&self, name: &str) -> Result<i32, GlError> {
+        let location = self.get_uniform_location(name)?;
+
+        if location == -1 {
+            return Err(GlError::UniformNotFound(name.to_owned(), self.id));
+        }
+
+        Ok(location)
+    }
+
+    // Same as expect_uniform for vertex attributes
+    pub fn expect_attribute(&self, name: &str) -> Result<i32, GlError> {
         let cstr = CString::new(name)?;
+        let location = unsafe { gl::GetAttribLocation(self.id, cstr.as_ptr()) };
+
+        if location == -1 {
+            return Err(GlError::AttributeNotFound(name.to_owned(), self.id));
+        }
+
+        Ok(location)
+    }
+
+    pub fn bind_to_ubo(&self, name: &str) -> Result<(), GlError> {
+        self.bind_to_ubo_at(name, 0)
+    }
+
+    pub fn bind_to_ubo_at(&self, name: &str, binding: u32) -> Result<(), GlError> {
+        let uniform_block_index = self.get_uniform_block_index(name)?;
 
         unsafe {
-            let uniform_block_index = gl::GetUniformBlockIndex(self.id, cstr.as_ptr());
-            
-            if uniform_block_index == gl::INVALID_INDEX {
-                return Err(GlError::UniformInvalidIndex(name.to_owned(), self.id));
-            }
+            gl::UniformBlockBinding(self.id, uniform_block_index, binding);
+        }
 
-            gl::UniformBlockBinding(self.id, uniform_block_index, 0);
+        Ok(())
+    }
+
+    // For an array of blocks, e.g. uniform Lights { ... } lights[count]. GL treats every element
+    // as its own block named "Lights[i]", which is bound to first_binding + i
+    pub fn bind_to_ubo_array(&self, name: &str, count: u32, first_binding: u32) -> Result<(), GlError> {
+        for i in 0..count {
+            self.bind_to_ubo_at(&format!("{}[{}]", name, i), first_binding + i)?;
         }
 
         Ok(())
     }
 
+    // Binding point the block currently reads from, either from layout(binding = N) or the last bind_to_ubo
+    pub fn get_ubo_binding(&self, name: &str) -> Result<u32, GlError> {
+        let uniform_block_index = self.get_uniform_block_index(name)?;
+        let mut binding = 0;
+
+        unsafe {
+            gl::GetActiveUniformBlockiv(self.id, uniform_block_index, gl::UNIFORM_BLOCK_BINDING, &mut binding);
+        }
+
+        Ok(binding as u32)
+    }
+
+    fn get_uniform_block_index(&self, name: &str) -> Result<u32, GlError> {
+        let cstr = CString::new(name)?;
+        let uniform_block_index = unsafe { gl::GetUniformBlockIndex(self.id, cstr.as_ptr()) };
+
+        if uniform_block_index == gl::INVALID_INDEX {
+            return Err(GlError::UniformInvalidIndex(name.to_owned(), self.id));
+        }
+
+        Ok(uniform_block_index)
+    }
+
+    // Debugging helper, returns (name, type, location) for every active uniform.
+    // Uniforms inside uniform blocks have a location of -1
+    pub fn list_uniforms(&self) -> Vec<(String, gl::types::GLenum, i32)> {
+        unsafe {
+            self.list_active(gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH, |index, max_len, len, size, type_, name| {
+                gl::GetActiveUniform(self.id, index, max_len, len, size, type_, name)
+            }, |name| gl::GetUniformLocation(self.id, name))
+        }
+    }
+
+    // Same as list_uniforms but for vertex attributes
+    pub fn list_attributes(&self) -> Vec<(String, gl::types::GLenum, i32)> {
+        unsafe {
+            self.list_active(gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, |index, max_len, len, size, type_, name| {
+                gl::GetActiveAttrib(self.id, index, max_len, len, size, type_, name)
+            }, |name| gl::GetAttribLocation(self.id, name))
+        }
+    }
+
+    unsafe fn list_active<F, L>(
+        &self,
+        count_param: gl::types::GLenum,
+        max_len_param: gl::types::GLenum,
+        get_active: F,
+        get_location: L
+    ) -> Vec<(String, gl::types::GLenum, i32)>
+    where
+        F: Fn(u32, i32, *mut i32, *mut i32, *mut gl::types::GLenum, *mut gl::types::GLchar),
+        L: Fn(*const gl::types::GLchar) -> i32
+    {
+        let mut count = 0;
+        let mut max_len = 0;
+        gl::GetProgramiv(self.id, count_param, &mut count);
+        gl::GetProgramiv(self.id, max_len_param, &mut max_len);
+
+        let mut result = Vec::new();
+
+        for index in 0..count as u32 {
+            let mut buffer: Vec<u8> = vec![0; max_len as usize + 1];
+            let mut len = 0;
+            let mut size = 0;
+            let mut type_ = 0;
+
+            get_active(index, max_len, &mut len, &mut size, &mut type_, buffer.as_mut_ptr() as *mut gl::types::GLchar);
+
+            let location = get_location(buffer.as_ptr() as *const gl::types::GLchar);
+            buffer.truncate(len as usize);
+
+            result.push((String::from_utf8_lossy(&buffer).into_owned(), type_, location));
+        }
+
+        result
+    }
+
     pub fn get_id(&self) -> u32 {
         self.id
     }
@@ -270,7 +571,7 @@ impl ShaderProgram {
 impl Drop for ShaderProgram {
     fn drop(&mut self) {
         unsafe {
-            gl::UseProgram(0);
+            StateCache::use_program(0);
             gl::DeleteProgram(self.id);
         }
     }
@@ -297,6 +598,13 @@ impl Display for ShaderCompileType {
     }
 }
 
+// Info logs are nul terminated
+fn info_log_to_string(mut buffer: Vec<u8>) -> String {
+    buffer.truncate(buffer.iter().position(|byte| *byte == 0).unwrap_or(buffer.len()));
+
+    String::from_utf8_lossy(&buffer).into_owned()
+}
+
 #[derive(Default)]
 pub struct ShaderCodeBundle {
     pub vertex: Option<String>,
@@ -0,0 +1,64 @@
+use std::collections::{BTreeSet, HashMap};
+use super::{ShaderCodeBundle, ShaderProgram, GlError};
+
+// Uber-shader permutations, each distinct set of #define flags is compiled
+// once on first use and cached
+pub struct ShaderVariant {
+    base: ShaderCodeBundle,
+    variants: HashMap<BTreeSet<String>, ShaderProgram>
+}
+
+impl ShaderVariant {
+    pub fn new(base: ShaderCodeBundle) -> ShaderVariant {
+        ShaderVariant {
+            base,
+            variants: HashMap::new()
+        }
+    }
+
+    // Flag order doesn't matter, ["A", "B"] and ["B", "A"] share a program
+    pub fn get_variant(&mut self, flags: &[&str]) -> Result<&ShaderProgram, GlError> {
+        let key: BTreeSet<String> = flags.iter().map(|flag| flag.to_string()).collect();
+
+        if !self.variants.contains_key(&key) {
+            let defines: String = key.iter().map(|flag| format!("#define {}\n", flag)).collect();
+            let shader_program = ShaderProgram::new(ShaderCodeBundle {
+                vertex: self.base.vertex.as_deref().map(|code| ShaderVariant::insert_defines(code, &defines)),
+                geometry: self.base.geometry.as_deref().map(|code| ShaderVariant::insert_defines(code, &defines)),
+                fragment: self.base.fragment.as_deref().map(|code| ShaderVariant::insert_defines(code, &defines))
+            })?;
+
+            self.variants.insert(key.clone(), shader_program);
+        }
+
+        // Inserted above if it was missing
+        Ok(&self.variants[&key])
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    // Drops all compiled variants, e.g. after the base source changed
+    pub fn clear(&mut self) {
+        self.variants.clear();
+    }
+
+    // Defines have to come after #version, which must be the first statement
+    fn insert_defines(code: &str, defines: &str) -> String {
+        match code.find("#version") {
+            Some(start) => {
+                let line_end = code[start..].find('\n').map_or(code.len(), |end| start + end + 1);
+                let mut result = code[..line_end].to_owned();
+
+                if !result.ends_with('\n') { result.push('\n'); }
+                result.push_str(defines);
+                result.push_str(&code[line_end..]);
+
+                result
+            },
+            None => format!("{}{}", defines, code)
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use cgmath::{Matrix4, Quaternion, Vector3, Zero};
+use super::GlError;
+
+// Local transform of a joint relative to its parent
+#[derive(Clone, Copy)]
+pub struct JointTransform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>
+}
+
+impl JointTransform {
+    pub fn identity() -> JointTransform {
+        JointTransform {
+            translation: Vector3::zero(),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+// Joint hierarchy of a skin, like a glTF skin with its joint nodes
+pub struct Skeleton {
+    parents: Vec<Option<usize>>,
+    // Joints ordered so parents come before their children
+    order: Vec<usize>,
+    inverse_bind_matrices: Vec<Matrix4<f32>>,
+    // Used for joints an animation has no channels for
+    pub rest_pose: Vec<JointTransform>
+}
+
+impl Skeleton {
+    // All three are indexed by joint, parents can be in any order but mustn't form a cycle
+    pub fn new(
+        parents: Vec<Option<usize>>,
+        inverse_bind_matrices: Vec<Matrix4<f32>>,
+        rest_pose: Vec<JointTransform>
+    ) -> Result<Skeleton, GlError> {
+        if inverse_bind_matrices.len() != parents.len() || rest_pose.len() != parents.len() {
+            return Err(GlError::JointCountMismatch(parents.len(), inverse_bind_matrices.len(), rest_pose.len()));
+        }
+
+        let order = Skeleton::parents_first_order(&parents)?;
+
+        Ok(Skeleton { parents, order, inverse_bind_matrices, rest_pose })
+    }
+
+    // Walks up from every joint until reaching one already ordered, running into
+    // a joint of the current walk means the parents loop
+    fn parents_first_order(parents: &[Option<usize>]) -> Result<Vec<usize>, GlError> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Visit { Pending, Walking, Ordered }
+
+        let mut visits = vec![Visit::Pending; parents.len()];
+        let mut order = Vec::with_capacity(parents.len());
+        let mut walk = Vec::new();
+
+        for joint in 0..parents.len() {
+            let mut current = Some(joint);
+
+            while let Some(walked) = current {
+                match visits[walked] {
+                    Visit::Ordered => break,
+                    Visit::Walking => return Err(GlError::JointParentCycle(walked)),
+                    Visit::Pending => ()
+                }
+
+                visits[walked] = Visit::Walking;
+                walk.push(walked);
+
+                current = match parents[walked] {
+                    Some(parent) if parent >= parents.len() => return Err(GlError::InvalidJointParent(walked, parent)),
+                    parent => parent
+                };
+            }
+
+            for walked in walk.drain(..).rev() {
+                visits[walked] = Visit::Ordered;
+                order.push(walked);
+            }
+        }
+
+        Ok(order)
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.parents.len()
+    }
+
+    // Bone matrices taking bind pose vertices to the posed model space, written to palette.
+    // Palette holds the global transforms until every child has used its parent's
+    pub fn compute_palette(&self, pose: &[JointTransform], palette: &mut Vec<Matrix4<f32>>) {
+        palette.resize(self.joint_count(), Matrix4::zero());
+
+        for &joint in self.order.iter() {
+            let local = pose[joint].to_matrix();
+
+            palette[joint] = match self.parents[joint] {
+                Some(parent) => palette[parent] * local,
+                None => local
+            };
+        }
+
+        for (bone, inverse_bind) in palette.iter_mut().zip(self.inverse_bind_matrices.iter()) {
+            *bone = *bone * inverse_bind;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skeleton(parents: Vec<Option<usize>>) -> Result<Skeleton, GlError> {
+        let count = parents.len();
+        Skeleton::new(parents, vec![Matrix4::from_scale(1.0); count], vec![JointTransform::identity(); count])
+    }
+
+    #[test]
+    fn children_can_come_before_parents() {
+        let mut pose = vec![JointTransform::identity(); 3];
+        pose[2].translation = Vector3::new(1.0, 0.0, 0.0);
+        pose[1].translation = Vector3::new(0.0, 2.0, 0.0);
+
+        // 0 -> 1 -> 2 with the root last
+        let skeleton = skeleton(vec![Some(1), Some(2), None]).unwrap();
+        let mut palette = Vec::new();
+        skeleton.compute_palette(&pose, &mut palette);
+
+        assert_eq!(palette[0], Matrix4::from_translation(Vector3::new(1.0, 2.0, 0.0)));
+    }
+
+    #[test]
+    fn parent_cycles_and_missing_parents_error() {
+        assert!(matches!(skeleton(vec![None, Some(2), Some(3), Some(1)]), Err(GlError::JointParentCycle(_))));
+        assert!(matches!(skeleton(vec![Some(0)]), Err(GlError::JointParentCycle(0))));
+        assert!(matches!(skeleton(vec![None, Some(5)]), Err(GlError::InvalidJointParent(1, 5))));
+        assert!(matches!(
+            Skeleton::new(vec![None], Vec::new(), vec![JointTransform::identity()]),
+            Err(GlError::JointCountMismatch(1, 0, 1))
+        ));
+    }
+}
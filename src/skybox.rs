@@ -1,23 +1,25 @@
 use crate::MultiBindModel;
 
-use super::{ModelTrait, ShaderProgram, GlError, gl};
+use super::{ModelTrait, ShaderProgram, GlError, RenderState, gl};
 
 pub struct Skybox {
     pub model: MultiBindModel // Only one skybox at a time is presumed to exist
 }
 
 impl Skybox {
-    pub fn draw(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
-        unsafe {
-            // Change depth func so test values pass when they are equal to the buffer's content
-            gl::DepthFunc(gl::LEQUAL);
+    // The depth func is taken from render_state and set back to it afterwards
+    pub fn draw(&self, shader_program: &ShaderProgram, render_state: &mut RenderState) -> Result<(), GlError> {
+        let depth_func = render_state.get_depth_func();
 
-            shader_program.use_program();
-            self.model.draw(shader_program)?;
+        // Change depth func so test values pass when they are equal to the buffer's content,
+        // the skybox sits at the far plane which is 0 with reverse-Z
+        render_state.set_depth_func(if render_state.is_reverse_z() { gl::GEQUAL } else { gl::LEQUAL });
 
-            gl::DepthFunc(gl::LESS);
-        }
+        shader_program.use_program();
+        let result = self.model.draw(shader_program);
 
-        Ok(())
+        render_state.set_depth_func(depth_func);
+
+        result
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,223 @@
+use std::{cell::RefCell, thread::ThreadId};
+use super::{RenderStats, gl, capabilities};
+
+thread_local! {
+    static STATE_CACHE: RefCell<StateCache> = RefCell::new(StateCache::new());
+}
+
+// Last objects bound through this crate on this thread, so binding the same object again
+// skips the GL call. None means unknown, e.g. after raw GL calls or switching contexts,
+// which need StateCache::invalidate so the cache doesn't skip a needed bind
+pub struct StateCache {
+    vertex_array: Option<u32>,
+    program: Option<u32>,
+    framebuffer: Option<u32>,
+    texture_units: Vec<Option<u32>>,
+    front_face: Option<gl::types::GLenum>,
+    cull_face: Option<bool>,
+    // Whether glBindTextures (GL 4.4) is available, checked on first use
+    multi_bind: Option<bool>,
+    // Token given to make_current, kept across invalidate
+    context: u64
+}
+
+impl StateCache {
+    fn new() -> StateCache {
+        StateCache {
+            vertex_array: None,
+            program: None,
+            framebuffer: None,
+            texture_units: Vec::new(),
+            front_face: None,
+            cull_face: None,
+            multi_bind: None,
+            context: 0
+        }
+    }
+
+    fn with<R>(f: impl FnOnce(&mut StateCache) -> R) -> R {
+        STATE_CACHE.with(|cache| f(&mut cache.borrow_mut()))
+    }
+
+    pub fn bind_vertex_array(id: u32) {
+        if StateCache::with(|cache| cache.vertex_array.replace(id) != Some(id)) {
+            unsafe { gl::BindVertexArray(id) };
+        }
+    }
+
+    pub fn use_program(id: u32) {
+        if StateCache::with(|cache| cache.program.replace(id) != Some(id)) {
+            unsafe { gl::UseProgram(id) };
+        }
+    }
+
+    pub fn set_front_face(front_face: gl::types::GLenum) {
+        if StateCache::with(|cache| cache.front_face.replace(front_face) != Some(front_face)) {
+            unsafe { gl::FrontFace(front_face) };
+        }
+    }
+
+    // Only queried from GL while unknown, so per draw checks don't stall
+    pub fn get_front_face() -> gl::types::GLenum {
+        if let Some(front_face) = StateCache::with(|cache| cache.front_face) {
+            return front_face;
+        }
+
+        let mut front_face = 0;
+        unsafe { gl::GetIntegerv(gl::FRONT_FACE, &mut front_face) };
+        StateCache::with(|cache| cache.front_face = Some(front_face as gl::types::GLenum));
+
+        front_face as gl::types::GLenum
+    }
+
+    pub fn set_cull_face(enabled: bool) {
+        if StateCache::with(|cache| cache.cull_face.replace(enabled) != Some(enabled)) {
+            unsafe {
+                if enabled { gl::Enable(gl::CULL_FACE) } else { gl::Disable(gl::CULL_FACE) }
+            }
+        }
+    }
+
+    // Only queried from GL while unknown, like get_front_face
+    pub fn is_cull_face() -> bool {
+        if let Some(enabled) = StateCache::with(|cache| cache.cull_face) {
+            return enabled;
+        }
+
+        let enabled = unsafe { gl::IsEnabled(gl::CULL_FACE) == gl::TRUE };
+        StateCache::with(|cache| cache.cull_face = Some(enabled));
+
+        enabled
+    }
+
+    // Binds to gl::FRAMEBUFFER, so both the draw and read bindings
+    pub fn bind_framebuffer(id: u32) {
+        if StateCache::with(|cache| cache.framebuffer.replace(id) != Some(id)) {
+            unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, id) };
+        }
+    }
+
+    pub fn bind_texture_unit(unit: u32, id: u32) {
+        let changed = StateCache::with(|cache| {
+            let unit = unit as usize;
+            if cache.texture_units.len() <= unit {
+                cache.texture_units.resize(unit + 1, None);
+            }
+
+            cache.texture_units[unit].replace(id) != Some(id)
+        });
+
+        if changed {
+            unsafe { gl::BindTextureUnit(unit, id) };
+            RenderStats::record_texture_binds(1);
+        }
+    }
+
+    // Binds consecutive units from first in one glBindTextures call when supported,
+    // skipped entirely if every unit already has its texture
+    pub fn bind_texture_units(first: u32, ids: &[u32]) {
+        if ids.is_empty() {
+            return;
+        }
+
+        let (changed, multi_bind) = StateCache::with(|cache| {
+            let end = first as usize + ids.len();
+            if cache.texture_units.len() < end {
+                cache.texture_units.resize(end, None);
+            }
+
+            let mut changed = false;
+            for (unit, &id) in cache.texture_units[first as usize..end].iter_mut().zip(ids.iter()) {
+                changed |= unit.replace(id) != Some(id);
+            }
+
+            let multi_bind = *cache.multi_bind.get_or_insert_with(|| capabilities::supports_version(4, 4));
+
+            (changed, multi_bind)
+        });
+
+        if !changed {
+            return;
+        }
+
+        RenderStats::record_texture_binds(ids.len());
+
+        unsafe {
+            if multi_bind {
+                gl::BindTextures(first, ids.len() as i32, ids.as_ptr());
+            } else {
+                for (unit, &id) in ids.iter().enumerate() {
+                    gl::BindTextureUnit(first + unit as u32, id);
+                }
+            }
+        }
+    }
+
+    // Forgets everything, the next bind of each kind always reaches GL
+    pub fn invalidate() {
+        StateCache::with(|cache| *cache = StateCache { context: cache.context, ..StateCache::new() });
+    }
+
+    // For apps with several contexts on one thread, call after making a context current with
+    // a token unique to it. Also invalidates, since the bindings belong to the old context,
+    // along with the cached capabilities since the new one may be a different version
+    pub fn make_current(token: u64) {
+        StateCache::with(|cache| *cache = StateCache { context: token, ..StateCache::new() });
+        capabilities::invalidate();
+    }
+
+    pub fn current_context() -> ContextId {
+        ContextId {
+            thread: std::thread::current().id(),
+            token: StateCache::with(|cache| cache.context)
+        }
+    }
+
+    // VAOs and framebuffers are containers, which aren't shared between contexts even when
+    // buffers and textures are. Using one from another context binds a different object or
+    // none at all, so debug builds stop at the use rather than at a confusing GL error later
+    pub fn check_context(owner: &ContextId, kind: &str, id: u32) {
+        if cfg!(debug_assertions) {
+            let current = StateCache::current_context();
+            assert!(
+                *owner == current,
+                "{} {} was created in context {:?} but used in {:?}, containers aren't shared between contexts",
+                kind, id, owner, current
+            );
+        }
+    }
+
+    pub fn invalidate_framebuffer() {
+        StateCache::with(|cache| cache.framebuffer = None);
+    }
+
+    // For binds to the active texture unit made with glBindTexture
+    pub fn invalidate_textures() {
+        StateCache::with(|cache| cache.texture_units.clear());
+    }
+
+    // Deleting an object unbinds it, and a new object can be given the same name
+    pub fn forget_vertex_array(id: u32) {
+        StateCache::with(|cache| if cache.vertex_array == Some(id) { cache.vertex_array = Some(0) });
+    }
+
+    pub fn forget_framebuffer(id: u32) {
+        StateCache::with(|cache| if cache.framebuffer == Some(id) { cache.framebuffer = Some(0) });
+    }
+
+    pub fn forget_texture(id: u32) {
+        StateCache::with(|cache| {
+            for unit in cache.texture_units.iter_mut().filter(|unit| **unit == Some(id)) {
+                *unit = Some(0);
+            }
+        });
+    }
+}
+
+// Context an object was created in, the thread it was current on plus the
+// token from StateCache::make_current (0 if never called)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextId {
+    thread: ThreadId,
+    token: u64
+}
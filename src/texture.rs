@@ -1,17 +1,66 @@
-use super::{GlError, GlImage, gl};
+use std::path::Path;
+use super::{GlError, GlImage, Ibl, StateCache, gl, capabilities};
 
 pub struct Texture {
     id: u32,
     target: gl::types::GLenum,
-    can_resize: bool
+    can_resize: bool,
+    // Kept so resizing can re-specify storage with the same format
+    internal_format: gl::types::GLenum,
+    data_format: gl::types::GLenum,
+    data_type: gl::types::GLenum,
+    // 0 unless the texture is multisampled
+    samples: i32,
+    // Mip levels allocated, upload_level checks against it
+    levels: i32,
+    // Only owned textures are deleted on drop
+    owned: bool
 }
 
 impl Texture {
+    // Wraps a texture created elsewhere, it is only deleted on drop if owned is true.
+    // The format is not known, so it is assumed to be RGBA and cannot be resized
+    pub fn from_raw(id: u32, target: gl::types::GLenum, owned: bool) -> Texture {
+        Texture {
+            id,
+            target,
+            can_resize: false,
+            internal_format: gl::RGBA8,
+            data_format: gl::RGBA,
+            data_type: gl::UNSIGNED_BYTE,
+            samples: 0,
+            levels: 1,
+            owned
+        }
+    }
+
+    // Gives up ownership, the texture has to be deleted by the caller
+    pub fn into_raw(mut self) -> u32 {
+        self.owned = false;
+        self.id
+    }
+
+    // Shared or externally owned textures should not be owned, so drop leaves them alone.
+    // Everything created by this crate starts owned
+    pub fn set_owned(&mut self, owned: bool) {
+        self.owned = owned;
+    }
+
+    pub fn is_owned(&self) -> bool {
+        self.owned
+    }
+
     pub fn from_2d(image: GlImage) -> Texture {
         let mut texture = Texture {
             id: 0,
             target: gl::TEXTURE_2D,
-            can_resize: false
+            can_resize: false,
+            internal_format: image.internal_format,
+            data_format: image.data_format,
+            data_type: gl::UNSIGNED_BYTE,
+            samples: 0,
+            levels: 1,
+            owned: true
         };
     
         unsafe {
@@ -51,7 +100,13 @@ impl Texture {
         let mut texture = Texture {
             id: 0,
             target: gl::TEXTURE_2D,
-            can_resize: false
+            can_resize: false,
+            internal_format: image.internal_format,
+            data_format: image.data_format,
+            data_type: gl::UNSIGNED_BYTE,
+            samples: 0,
+            levels: 1,
+            owned: true
         };
     
         unsafe {
@@ -91,7 +146,13 @@ impl Texture {
         let mut texture = Texture {
             id: 0,
             target: gl::TEXTURE_CUBE_MAP,
-            can_resize: false
+            can_resize: false,
+            internal_format: image.internal_format,
+            data_format: image.data_format,
+            data_type: gl::UNSIGNED_BYTE,
+            samples: 0,
+            levels: 1,
+            owned: true
         };
 
         let square_size = image.height / 3;
@@ -147,12 +208,186 @@ impl Texture {
         texture
     }
 
-    // Doesn't need GlError since this only generates gl callback errors
+    // Empty cubemap to render into, e.g. for IBL or dynamic reflections
+    pub fn new_cubemap(size: i32, internal_format: gl::types::GLenum, levels: i32) -> Texture {
+        let mut texture = Texture {
+            id: 0,
+            target: gl::TEXTURE_CUBE_MAP,
+            can_resize: false,
+            internal_format,
+            data_format: gl::RGBA,
+            data_type: gl::FLOAT,
+            samples: 0,
+            levels,
+            owned: true
+        };
+
+        unsafe {
+            gl::CreateTextures(texture.target, 1, &mut texture.id);
+            gl::TextureStorage2D(texture.id, levels, internal_format, size, size);
+
+            let min_filter = if levels > 1 { gl::LINEAR_MIPMAP_LINEAR } else { gl::LINEAR };
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        }
+
+        texture
+    }
+
+    // Face size defaults to a quarter of the map's width, see Ibl::equirectangular_to_cubemap
+    pub fn cubemap_from_equirectangular(equirectangular: &Texture) -> Result<Texture, GlError> {
+        let mut width = 0;
+        unsafe {
+            gl::GetTextureLevelParameteriv(equirectangular.get_id(), 0, gl::TEXTURE_WIDTH, &mut width);
+        }
+
+        Ibl::equirectangular_to_cubemap(equirectangular, (width / 4).max(1))
+    }
+
+    // For sampler2DArray, each layer is uploaded separately with upload_layer
+    pub fn new_array(width: i32, height: i32, layers: i32, internal_format: gl::types::GLenum) -> Texture {
+        let mut texture = Texture {
+            id: 0,
+            target: gl::TEXTURE_2D_ARRAY,
+            can_resize: false,
+            internal_format,
+            data_format: gl::RGBA,
+            data_type: gl::UNSIGNED_BYTE,
+            samples: 0,
+            levels: 1,
+            owned: true
+        };
+
+        unsafe {
+            gl::CreateTextures(texture.target, 1, &mut texture.id);
+
+            gl::TextureStorage3D(
+                texture.id,
+                1,
+                internal_format,
+                width,
+                height,
+                layers
+            );
+
+            gl::TextureParameteri(texture.id, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        }
+
+        texture
+    }
+
+    // For sampler3D, sampled with filtering across depth unlike an array. Written by compute
+    // shaders through bind_image, or one slice at a time with Framebuffer::attach_texture_layer
+    pub fn new_3d(width: i32, height: i32, depth: i32, internal_format: gl::types::GLenum) -> Texture {
+        let mut texture = Texture {
+            id: 0,
+            target: gl::TEXTURE_3D,
+            can_resize: false,
+            internal_format,
+            data_format: gl::RGBA,
+            data_type: gl::UNSIGNED_BYTE,
+            samples: 0,
+            levels: 1,
+            owned: true
+        };
+
+        unsafe {
+            gl::CreateTextures(texture.target, 1, &mut texture.id);
+            gl::TextureStorage3D(texture.id, 1, internal_format, width, height, depth);
+
+            gl::TextureParameteri(texture.id, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        }
+
+        texture
+    }
+
+    // Image needs to be the same size as the array's layers
+    pub fn upload_layer(&self, layer: i32, image: &GlImage) {
+        unsafe {
+            gl::TextureSubImage3D(
+                self.id,
+                0,
+                0,
+                0,
+                layer,
+                image.width,
+                image.height,
+                1,
+                image.data_format,
+                gl::UNSIGNED_BYTE,
+                image.bytes.as_ptr() as *const gl::types::GLvoid
+            );
+        }
+    }
+
+    // Panics if creation fails, see try_new_mut
     pub fn new_mut(width: i32, height: i32) -> Texture {
+        Texture::try_new_mut(width, height).unwrap()
+    }
+
+    // Validates the size against GL_MAX_TEXTURE_SIZE and checks the allocation went through,
+    // e.g. running out of memory on huge shadow maps
+    pub fn try_new_mut(width: i32, height: i32) -> Result<Texture, GlError> {
+        Texture::check_size(width, height)?;
+
+        let texture = Texture::new_mut_format(width, height, gl::RGBA16F, gl::RGBA, gl::UNSIGNED_BYTE);
+
+        // A failed allocation leaves level 0 empty. The error flag is only read then,
+        // so errors from unrelated earlier calls stay queued for whoever checks them
+        let mut allocated_width = 0;
+        unsafe {
+            gl::GetTextureLevelParameteriv(texture.id, 0, gl::TEXTURE_WIDTH, &mut allocated_width);
+        }
+
+        if allocated_width != width {
+            return Err(GlError::GlCallError(unsafe { gl::GetError() }));
+        }
+
+        Ok(texture)
+    }
+
+    // Errors unless both sides are between 1 and GL_MAX_TEXTURE_SIZE
+    pub fn check_size(width: i32, height: i32) -> Result<(), GlError> {
+        let mut max_size = 0;
+
+        unsafe {
+            gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size);
+        }
+
+        if width < 1 || height < 1 || width > max_size || height > max_size {
+            return Err(GlError::InvalidTextureSize(width, height, max_size));
+        }
+
+        Ok(())
+    }
+
+    pub fn new_mut_format(
+        width: i32,
+        height: i32,
+        internal_format: gl::types::GLenum,
+        data_format: gl::types::GLenum,
+        data_type: gl::types::GLenum
+    ) -> Texture {
         let mut texture = Texture {
             id: 0,
             target: gl::TEXTURE_2D,
-            can_resize: true
+            can_resize: true,
+            internal_format,
+            data_format,
+            data_type,
+            samples: 0,
+            levels: 1,
+            owned: true
         };
 
         unsafe {
@@ -163,15 +398,16 @@ impl Texture {
             gl::TexImage2D(
                 texture.target,
                 0,
-                gl::RGBA16F as i32,
+                internal_format as i32,
                 width,
                 height,
                 0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
+                data_format,
+                data_type,
                 std::ptr::null()
             );
             gl::BindTexture(texture.target, 0);
+            StateCache::invalidate_textures();
 
             // Nearest just for simplicity
             gl::TextureParameteri(texture.id, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
@@ -181,12 +417,275 @@ impl Texture {
         texture
     }
 
+    // Sampled as sampler2DMS with texelFetch, get_samples gives the count to average over.
+    // Uses mutable storage so it can be resized with the framebuffer
+    pub fn new_multisample(width: i32, height: i32, samples: i32, internal_format: gl::types::GLenum) -> Texture {
+        let mut texture = Texture {
+            id: 0,
+            target: gl::TEXTURE_2D_MULTISAMPLE,
+            can_resize: true,
+            internal_format,
+            data_format: gl::RGBA,
+            data_type: gl::UNSIGNED_BYTE,
+            samples,
+            levels: 1,
+            owned: true
+        };
+
+        unsafe {
+            gl::CreateTextures(texture.target, 1, &mut texture.id);
+            gl::BindTexture(texture.target, texture.id);
+            gl::TexImage2DMultisample(texture.target, samples, internal_format, width, height, gl::TRUE);
+            gl::BindTexture(texture.target, 0);
+            StateCache::invalidate_textures();
+        }
+
+        texture
+    }
+
+    // Storage can't be re-specified, so resize returns GlError::CannotResize.
+    // Required for bindless and sparse textures
+    pub fn new_immutable(width: i32, height: i32, internal_format: gl::types::GLenum, levels: i32) -> Texture {
+        let mut texture = Texture {
+            id: 0,
+            target: gl::TEXTURE_2D,
+            can_resize: false,
+            internal_format,
+            data_format: gl::RGBA,
+            data_type: gl::UNSIGNED_BYTE,
+            samples: 0,
+            levels,
+            owned: true
+        };
+
+        unsafe {
+            gl::CreateTextures(texture.target, 1, &mut texture.id);
+            gl::TextureStorage2D(texture.id, levels, internal_format, width, height);
+
+            let min_filter = if levels > 1 { gl::LINEAR_MIPMAP_LINEAR } else { gl::LINEAR };
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        }
+
+        texture
+    }
+
+    // Only allocates address space, pages have to be made resident with commit_region
+    // before they are sampled or written. Single level, the format needs sparse page sizes
+    pub fn new_sparse(width: i32, height: i32, internal_format: gl::types::GLenum) -> Result<Texture, GlError> {
+        if !capabilities::has_extension("GL_ARB_sparse_texture") {
+            return Err(GlError::UnsupportedFeature("GL_ARB_sparse_texture".to_owned()));
+        }
+
+        let mut page_sizes = 0;
+        unsafe {
+            gl::GetInternalformativ(gl::TEXTURE_2D, internal_format, gl::NUM_VIRTUAL_PAGE_SIZES_ARB, 1, &mut page_sizes);
+        }
+        if page_sizes == 0 {
+            return Err(GlError::InvalidImageFormat(internal_format));
+        }
+
+        let mut texture = Texture {
+            id: 0,
+            target: gl::TEXTURE_2D,
+            can_resize: false,
+            internal_format,
+            data_format: gl::RGBA,
+            data_type: gl::UNSIGNED_BYTE,
+            samples: 0,
+            levels: 1,
+            owned: true
+        };
+
+        unsafe {
+            gl::CreateTextures(texture.target, 1, &mut texture.id);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_SPARSE_ARB, gl::TRUE as i32);
+            gl::TextureParameteri(texture.id, gl::VIRTUAL_PAGE_SIZE_INDEX_ARB, 0);
+            gl::TextureStorage2D(texture.id, 1, internal_format, width, height);
+
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TextureParameteri(texture.id, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        }
+
+        Ok(texture)
+    }
+
+    // Size of a sparse page in texels, regions passed to commit_region must be aligned to it
+    pub fn get_page_size(&self) -> (i32, i32) {
+        let mut x = 0;
+        let mut y = 0;
+
+        unsafe {
+            gl::GetInternalformativ(self.target, self.internal_format, gl::VIRTUAL_PAGE_SIZE_X_ARB, 1, &mut x);
+            gl::GetInternalformativ(self.target, self.internal_format, gl::VIRTUAL_PAGE_SIZE_Y_ARB, 1, &mut y);
+        }
+
+        (x, y)
+    }
+
+    // Makes the pages covering the region resident, or releases them if resident is false.
+    // Uses the bind-to-edit entry point since the DSA one needs EXT_direct_state_access
+    pub fn commit_region(&self, x: i32, y: i32, width: i32, height: i32, level: i32, resident: bool) {
+        let commit = if resident { gl::TRUE } else { gl::FALSE };
+
+        unsafe {
+            gl::BindTexture(self.target, self.id);
+            gl::TexPageCommitmentARB(self.target, level, x, y, 0, width, height, 1, commit);
+            gl::BindTexture(self.target, 0);
+            StateCache::invalidate_textures();
+        }
+    }
+
+    // Each argument is the source channel for that component, e.g. gl::RED for all of r, g and b
+    // broadcasts a single channel texture. gl::ZERO and gl::ONE are also accepted
+    pub fn set_swizzle(
+        &self,
+        r: gl::types::GLenum,
+        g: gl::types::GLenum,
+        b: gl::types::GLenum,
+        a: gl::types::GLenum
+    ) {
+        let mask = [r as i32, g as i32, b as i32, a as i32];
+
+        unsafe {
+            gl::TextureParameteriv(self.id, gl::TEXTURE_SWIZZLE_RGBA, mask.as_ptr());
+        }
+    }
+
+    // Binds to an image unit for compute shader load/store, access is gl::READ_ONLY,
+    // gl::WRITE_ONLY or gl::READ_WRITE. Array, cube and 3D textures bind all layers
+    pub fn bind_image(
+        &self,
+        unit: u32,
+        level: i32,
+        access: gl::types::GLenum,
+        format: gl::types::GLenum
+    ) -> Result<(), GlError> {
+        if !Texture::is_image_format(format) {
+            return Err(GlError::InvalidImageFormat(format));
+        }
+
+        let layered = matches!(
+            self.target,
+            gl::TEXTURE_2D_ARRAY | gl::TEXTURE_CUBE_MAP | gl::TEXTURE_3D
+        );
+
+        unsafe {
+            gl::BindImageTexture(unit, self.id, level, layered as u8, 0, access, format);
+        }
+
+        Ok(())
+    }
+
+    // Formats from the image load/store format table, RGB formats aren't supported
+    pub fn is_image_format(format: gl::types::GLenum) -> bool {
+        matches!(
+            format,
+            gl::RGBA32F | gl::RGBA16F | gl::RG32F | gl::RG16F | gl::R11F_G11F_B10F | gl::R32F | gl::R16F
+                | gl::RGBA32UI | gl::RGBA16UI | gl::RGB10_A2UI | gl::RGBA8UI | gl::RG32UI | gl::RG16UI
+                | gl::RG8UI | gl::R32UI | gl::R16UI | gl::R8UI
+                | gl::RGBA32I | gl::RGBA16I | gl::RGBA8I | gl::RG32I | gl::RG16I | gl::RG8I
+                | gl::R32I | gl::R16I | gl::R8I
+                | gl::RGBA16 | gl::RGB10_A2 | gl::RGBA8 | gl::RG16 | gl::RG8 | gl::R16 | gl::R8
+                | gl::RGBA16_SNORM | gl::RGBA8_SNORM | gl::RG16_SNORM | gl::RG8_SNORM
+                | gl::R16_SNORM | gl::R8_SNORM
+        )
+    }
+
+    // Data format and type TexImage2D accepts for internal_format when allocating without data,
+    // integer and depth formats reject the usual gl::RGBA
+    pub fn empty_data_format(internal_format: gl::types::GLenum) -> (gl::types::GLenum, gl::types::GLenum) {
+        match internal_format {
+            gl::DEPTH_COMPONENT16 | gl::DEPTH_COMPONENT24 | gl::DEPTH_COMPONENT32 | gl::DEPTH_COMPONENT32F => {
+                (gl::DEPTH_COMPONENT, gl::FLOAT)
+            },
+            gl::DEPTH24_STENCIL8 => (gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8),
+            gl::DEPTH32F_STENCIL8 => (gl::DEPTH_STENCIL, gl::FLOAT_32_UNSIGNED_INT_24_8_REV),
+            gl::RGBA32UI | gl::RGBA16UI | gl::RGB10_A2UI | gl::RGBA8UI | gl::RG32UI | gl::RG16UI
+                | gl::RG8UI | gl::R32UI | gl::R16UI | gl::R8UI
+                | gl::RGBA32I | gl::RGBA16I | gl::RGBA8I | gl::RG32I | gl::RG16I | gl::RG8I
+                | gl::R32I | gl::R16I | gl::R8I => (gl::RGBA_INTEGER, gl::UNSIGNED_BYTE),
+            _ => (gl::RGBA, gl::UNSIGNED_BYTE)
+        }
+    }
+
     pub fn ready_texture(&self, num: u32) {
+        StateCache::bind_texture_unit(num, self.id);
+    }
+
+    // Reads mip level 0 back as 8 bit RGBA, rows top to bottom. Float textures are tone mapped
+    // with Reinhard so HDR values stay visible, depth textures come out as grey RGBA.
+    // Integer formats give UnsupportedPixelFormat. Only single sample TEXTURE_2D textures can be
+    // read, others (cubemaps, arrays, multisampled) give UnsupportedTextureTarget
+    pub fn read_image(&self) -> Result<GlImage, GlError> {
+        if self.samples > 0 || self.target != gl::TEXTURE_2D {
+            return Err(GlError::UnsupportedTextureTarget(self.id, self.target));
+        }
+
+        let get_param = |param: gl::types::GLenum| {
+            let mut value = 0;
+            unsafe { gl::GetTextureLevelParameteriv(self.id, 0, param, &mut value) };
+            value
+        };
+        let (width, height) = (get_param(gl::TEXTURE_WIDTH), get_param(gl::TEXTURE_HEIGHT));
+        let is_depth = get_param(gl::TEXTURE_DEPTH_TYPE) as u32 != gl::NONE;
+        let component_type = get_param(if is_depth { gl::TEXTURE_DEPTH_TYPE } else { gl::TEXTURE_RED_TYPE }) as u32;
+
+        let tone_map = match component_type {
+            gl::FLOAT => !is_depth,
+            gl::UNSIGNED_NORMALIZED | gl::SIGNED_NORMALIZED => false,
+            _ => return Err(GlError::UnsupportedPixelFormat(self.internal_format))
+        };
+
+        // GL converts any non integer format to floats, missing channels read as 0 (1 for alpha)
+        let (format, channels) = if is_depth { (gl::DEPTH_COMPONENT, 1) } else { (gl::RGBA, 4) };
+        let mut pixels = vec![0.0f32; (width * height) as usize * channels];
+
         unsafe {
-            gl::BindTextureUnit(num, self.id);
+            gl::GetTextureImage(
+                self.id,
+                0,
+                format,
+                gl::FLOAT,
+                std::mem::size_of_val(pixels.as_slice()) as i32,
+                pixels.as_mut_ptr() as *mut gl::types::GLvoid
+            );
+        }
+
+        let to_byte = |value: f32| {
+            let value = if tone_map { value.max(0.0) / (1.0 + value.max(0.0)) } else { value };
+            (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+        };
+
+        // GL rows start at the bottom
+        let mut bytes = Vec::with_capacity((width * height * 4) as usize);
+        for row in pixels.chunks_exact(width as usize * channels).rev() {
+            for pixel in row.chunks_exact(channels) {
+                if is_depth {
+                    bytes.extend_from_slice(&[to_byte(pixel[0]), to_byte(pixel[0]), to_byte(pixel[0]), 255]);
+                } else {
+                    // Alpha is coverage, not light, so it is never tone mapped
+                    bytes.extend_from_slice(&[to_byte(pixel[0]), to_byte(pixel[1]), to_byte(pixel[2])]);
+                    bytes.push((pixel[3].clamp(0.0, 1.0) * 255.0 + 0.5) as u8);
+                }
+            }
         }
+
+        Ok(GlImage {
+            bytes,
+            internal_format: gl::RGBA8,
+            data_format: gl::RGBA,
+            width,
+            height
+        })
+    }
+
+    // For inspecting render targets such as G-buffers or shadow maps, see read_image
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), GlError> {
+        self.read_image()?.save_png(path)
     }
 
+    // Only textures with mutable storage (new_mut) can be resized
     // Unsafe because it doesn't need to be marked as mutable, which would interfere with RC
     // TODO: CHANGE WHEN WRITING RESOURCE MANAGER!
     pub unsafe fn resize(&self, width: i32, height: i32) -> Result<(), GlError> {
@@ -194,21 +693,31 @@ impl Texture {
             return Err(GlError::CannotResize(self.id));
         }
 
+        if self.samples > 0 {
+            gl::BindTexture(self.target, self.id);
+            gl::TexImage2DMultisample(self.target, self.samples, self.internal_format, width, height, gl::TRUE);
+            gl::BindTexture(self.target, 0);
+            StateCache::invalidate_textures();
+
+            return Ok(());
+        }
+
         unsafe {
             gl::BindTexture(self.target, self.id);
-            // Resizes texture on same ID
+            // Resizes texture on same ID, keeping the format it was created with
             gl::TexImage2D(
                 self.target,
                 0,
-                gl::RGBA16F as i32,
+                self.internal_format as i32,
                 width,
                 height,
                 0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
+                self.data_format,
+                self.data_type,
                 std::ptr::null()
             );
             gl::BindTexture(self.target, 0);
+            StateCache::invalidate_textures();
         }
 
         Ok(())
@@ -218,6 +727,118 @@ impl Texture {
         self.id
     }
 
+    // Level data has to match the level's size, which halves with each level.
+    // Rows are padded to GL_UNPACK_ALIGNMENT (4 unless changed), data is checked to hold them
+    pub fn upload_level(
+        &self,
+        level: i32,
+        width: i32,
+        height: i32,
+        data: &[u8],
+        data_format: gl::types::GLenum
+    ) -> Result<(), GlError> {
+        if level < 0 || level >= self.levels {
+            return Err(GlError::InvalidMipLevel(level, self.levels));
+        }
+
+        let mut level_width = 0;
+        let mut level_height = 0;
+        let mut alignment = 4;
+
+        unsafe {
+            gl::GetTextureLevelParameteriv(self.id, level, gl::TEXTURE_WIDTH, &mut level_width);
+            gl::GetTextureLevelParameteriv(self.id, level, gl::TEXTURE_HEIGHT, &mut level_height);
+            gl::GetIntegerv(gl::UNPACK_ALIGNMENT, &mut alignment);
+        }
+
+        if width < 1 || height < 1 || (width, height) != (level_width, level_height) {
+            return Err(GlError::InvalidLevelSize(level, width, height, level_width, level_height));
+        }
+
+        let pixel_size = Texture::bytes_per_pixel(data_format, self.data_type)
+            .ok_or(GlError::UnsupportedPixelFormat(data_format))?;
+        let row_size = width as usize * pixel_size;
+        let row_stride = row_size.div_ceil(alignment as usize) * alignment as usize;
+        let needed = row_stride * (height as usize - 1) + row_size;
+
+        if data.len() < needed {
+            return Err(GlError::ImageDataTooShort(data.len(), needed));
+        }
+
+        unsafe {
+            gl::TextureSubImage2D(
+                self.id,
+                level,
+                0,
+                0,
+                width,
+                height,
+                data_format,
+                self.data_type,
+                data.as_ptr() as *const gl::types::GLvoid
+            );
+        }
+
+        Ok(())
+    }
+
+    // Size of one pixel of client data, None for combinations GL wouldn't accept
+    pub fn bytes_per_pixel(data_format: gl::types::GLenum, data_type: gl::types::GLenum) -> Option<usize> {
+        // Packed types hold every component in one value
+        match data_type {
+            gl::UNSIGNED_BYTE_3_3_2 | gl::UNSIGNED_BYTE_2_3_3_REV => return Some(1),
+            gl::UNSIGNED_SHORT_5_6_5 | gl::UNSIGNED_SHORT_5_6_5_REV | gl::UNSIGNED_SHORT_4_4_4_4
+                | gl::UNSIGNED_SHORT_4_4_4_4_REV | gl::UNSIGNED_SHORT_5_5_5_1 | gl::UNSIGNED_SHORT_1_5_5_5_REV => return Some(2),
+            gl::UNSIGNED_INT_8_8_8_8 | gl::UNSIGNED_INT_8_8_8_8_REV | gl::UNSIGNED_INT_10_10_10_2
+                | gl::UNSIGNED_INT_2_10_10_10_REV | gl::UNSIGNED_INT_24_8 | gl::UNSIGNED_INT_10F_11F_11F_REV
+                | gl::UNSIGNED_INT_5_9_9_9_REV => return Some(4),
+            gl::FLOAT_32_UNSIGNED_INT_24_8_REV => return Some(8),
+            _ => ()
+        }
+
+        let components = match data_format {
+            gl::RED | gl::GREEN | gl::BLUE | gl::RED_INTEGER | gl::GREEN_INTEGER | gl::BLUE_INTEGER
+                | gl::DEPTH_COMPONENT | gl::STENCIL_INDEX => 1,
+            gl::RG | gl::RG_INTEGER | gl::DEPTH_STENCIL => 2,
+            gl::RGB | gl::BGR | gl::RGB_INTEGER | gl::BGR_INTEGER => 3,
+            gl::RGBA | gl::BGRA | gl::RGBA_INTEGER | gl::BGRA_INTEGER => 4,
+            _ => return None
+        };
+
+        let component_size = match data_type {
+            gl::UNSIGNED_BYTE | gl::BYTE => 1,
+            gl::UNSIGNED_SHORT | gl::SHORT | gl::HALF_FLOAT => 2,
+            gl::UNSIGNED_INT | gl::INT | gl::FLOAT => 4,
+            _ => return None
+        };
+
+        Some(components * component_size)
+    }
+
+    pub fn mip_levels(&self) -> i32 {
+        self.levels
+    }
+
+    pub fn get_samples(&self) -> i32 {
+        self.samples
+    }
+
+    pub fn can_resize(&self) -> bool {
+        self.can_resize
+    }
+
+    pub fn get_internal_format(&self) -> gl::types::GLenum {
+        self.internal_format
+    }
+
+    pub fn get_data_format(&self) -> gl::types::GLenum {
+        self.data_format
+    }
+
+    pub fn get_data_type(&self) -> gl::types::GLenum {
+        self.data_type
+    }
+
     // Requires GL_ARB_bindless_texture
     // Modifies texture to be immutable, but not its contents
     pub unsafe fn get_handle(&self) -> u64 {
@@ -237,6 +858,10 @@ impl Texture {
 
 impl Drop for Texture {
     fn drop(&mut self) {
+        if !self.owned { return; }
+
+        StateCache::forget_texture(self.id);
+
         unsafe {
             gl::DeleteTextures(1, &self.id);
         }
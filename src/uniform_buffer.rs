@@ -1,19 +1,39 @@
+use std::marker::PhantomData;
 use super::ShaderProgram;
-use super::{GlError, gl};
+use super::{GlError, gl, capabilities};
 
 // UBO can have multiple types of data, so it doesn't have a type
 pub struct UniformBuffer {
     id: u32,
     name: String,
-    buffer_size: isize
+    buffer_size: isize,
+    binding: u32,
+    // Set for block arrays, elements are stride bytes apart with element i on binding + i
+    array_len: Option<u32>,
+    stride: isize
 }
 
 impl UniformBuffer {
     pub fn new(shader_programs: Vec<&ShaderProgram>, name: &str, buffer_size: isize) -> Result<UniformBuffer, GlError> {
+        UniformBuffer::new_at_binding(shader_programs, name, buffer_size, 0)
+    }
+
+    // Same as new, on a binding point other than 0 so several UBOs can be bound at once.
+    // The shaders are only validated, so their blocks need a matching layout(binding = N),
+    // use rebind_shader_program for shaders that don't declare one
+    pub fn new_at_binding(
+        shader_programs: Vec<&ShaderProgram>,
+        name: &str,
+        buffer_size: isize,
+        binding: u32
+    ) -> Result<UniformBuffer, GlError> {
         let mut uniform_buffer = UniformBuffer {
             id: 0,
             name: String::from(name),
-            buffer_size
+            buffer_size,
+            binding,
+            array_len: None,
+            stride: buffer_size
         };
 
         for shader_program in shader_programs.iter() {
@@ -25,22 +45,168 @@ impl UniformBuffer {
         Ok(uniform_buffer)
     }
 
+    // One buffer feeding every element of a block array, e.g. per-cluster light lists.
+    // Element i is bound to binding first_binding + i, and starts at byte i * get_stride(),
+    // element_size rounded up to UNIFORM_BUFFER_OFFSET_ALIGNMENT (often 256). So the
+    // bindings first_binding..first_binding + count are all taken by this buffer
+    pub fn new_array(
+        shader_programs: Vec<&ShaderProgram>,
+        name: &str,
+        element_size: isize,
+        count: u32,
+        first_binding: u32
+    ) -> Result<UniformBuffer, GlError> {
+        let alignment = capabilities::uniform_buffer_offset_alignment();
+        let stride = (element_size + alignment - 1) / alignment * alignment;
+
+        let mut uniform_buffer = UniformBuffer {
+            id: 0,
+            name: String::from(name),
+            buffer_size: stride * count as isize,
+            binding: first_binding,
+            array_len: Some(count),
+            stride
+        };
+
+        for shader_program in shader_programs.iter() {
+            uniform_buffer.register_shader_program(shader_program)?;
+        }
+
+        uniform_buffer.create_ubo();
+
+        Ok(uniform_buffer)
+    }
+
+    // Seeds the UBO with initial contents, buffer_size is taken from the data length
+    pub fn new_with_data(shader_programs: Vec<&ShaderProgram>, name: &str, data: &[u8]) -> Result<UniformBuffer, GlError> {
+        let uniform_buffer = UniformBuffer::new(shader_programs, name, data.len() as isize)?;
+
+        uniform_buffer.write_bytes(data, 0);
+
+        Ok(uniform_buffer)
+    }
+
+    // Sized to hold exactly one T, see TypedUniformBuffer
+    pub fn new_typed<T>(shader_programs: Vec<&ShaderProgram>, name: &str) -> Result<TypedUniformBuffer<T>, GlError> {
+        let buffer = UniformBuffer::new(shader_programs, name, std::mem::size_of::<T>() as isize)?;
+
+        Ok(TypedUniformBuffer { buffer, marker: PhantomData })
+    }
+
+    // Checks the shader's block already reads from this UBO's binding point, either from
+    // layout(binding = N) or an earlier rebind. The shader's binding is left untouched
     pub fn register_shader_program(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
-        shader_program.bind_to_ubo(self.name.as_str())
+        self.validate_shader_program(shader_program)
+    }
+
+    // Points the shader's block at this UBO's binding point, overriding any layout(binding = N)
+    pub fn rebind_shader_program(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+        match self.array_len {
+            Some(count) => shader_program.bind_to_ubo_array(self.name.as_str(), count, self.binding)?,
+            None => shader_program.bind_to_ubo_at(self.name.as_str(), self.binding)?
+        }
+
+        self.validate_shader_program(shader_program)
+    }
+
+    // Checks the shader's block still reads from this UBO's binding point. A mismatch doesn't
+    // error in GL, the block just reads whatever is bound there (usually zeros)
+    pub fn validate_shader_program(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+        for (block_name, expected) in self.block_bindings() {
+            let binding = shader_program.get_ubo_binding(block_name.as_str())?;
+
+            if binding != expected {
+                return Err(GlError::UniformBindingMismatch(block_name, shader_program.get_id(), expected, binding));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Name and binding point of every block this UBO feeds
+    fn block_bindings(&self) -> Vec<(String, u32)> {
+        match self.array_len {
+            Some(count) => (0..count).map(|i| (format!("{}[{}]", self.name, i), self.binding + i)).collect(),
+            None => vec![(self.name.clone(), self.binding)]
+        }
     }
 
     pub fn create_ubo(&mut self) {
         unsafe {
+            // Zeroed so nothing uninitialised is read before the first write
+            let zeroed = vec![0u8; self.buffer_size as usize];
+
             gl::CreateBuffers(1, &mut self.id);
-            gl::NamedBufferData(self.id, self.buffer_size, std::ptr::null(), gl::DYNAMIC_DRAW);
-            gl::BindBufferRange(gl::UNIFORM_BUFFER, 0, self.id, 0, self.buffer_size);
+            gl::NamedBufferData(self.id, self.buffer_size, zeroed.as_ptr() as *const gl::types::GLvoid, gl::DYNAMIC_DRAW);
         }
+
+        self.bind_ubo();
     }
 
     pub fn bind_ubo(&self) {
+        for i in 0..self.array_len.unwrap_or(1) {
+            unsafe {
+                gl::BindBufferRange(gl::UNIFORM_BUFFER, self.binding + i, self.id, i as isize * self.stride, self.stride);
+            }
+        }
+    }
+
+    // Reallocates the UBO with a new size, keeping its id and binding point so registered
+    // shaders don't need rebinding. Contents are zeroed unless keep_data is set, in which
+    // case as much of the old data as fits is copied over. For block arrays new_size is
+    // the whole buffer and gets split evenly between the elements, each keeping its own
+    // data, so it has to be at least count * UNIFORM_BUFFER_OFFSET_ALIGNMENT
+    pub fn resize(&mut self, new_size: isize, keep_data: bool) -> Result<(), GlError> {
+        let count = self.array_len.unwrap_or(1).max(1) as isize;
+        let (new_stride, minimum) = match self.array_len {
+            Some(_) => {
+                let alignment = capabilities::uniform_buffer_offset_alignment();
+
+                (new_size / count / alignment * alignment, count * alignment)
+            },
+            None => (new_size, 1)
+        };
+
+        if new_stride < 1 {
+            return Err(GlError::UniformBufferTooSmall(self.name.clone(), new_size, minimum));
+        }
+
+        let mut data = vec![0u8; new_size as usize];
+
         unsafe {
-            gl::BindBufferRange(gl::UNIFORM_BUFFER, 0, self.id, 0, self.buffer_size);
+            if keep_data {
+                let mut old_data = vec![0u8; self.buffer_size as usize];
+                gl::GetNamedBufferSubData(self.id, 0, self.buffer_size, old_data.as_mut_ptr() as *mut gl::types::GLvoid);
+
+                // Elements move to their new offsets, cut off at the smaller stride
+                let kept = self.stride.min(new_stride) as usize;
+                for i in 0..count as usize {
+                    let (old_start, new_start) = (i * self.stride as usize, i * new_stride as usize);
+                    data[new_start..new_start + kept].copy_from_slice(&old_data[old_start..old_start + kept]);
+                }
+            }
+
+            gl::NamedBufferData(self.id, new_size, data.as_ptr() as *const gl::types::GLvoid, gl::DYNAMIC_DRAW);
         }
+
+        self.buffer_size = new_size;
+        self.stride = new_stride;
+        self.bind_ubo();
+
+        Ok(())
+    }
+
+    pub fn get_size(&self) -> isize {
+        self.buffer_size
+    }
+
+    pub fn get_binding(&self) -> u32 {
+        self.binding
+    }
+
+    // Byte distance between block array elements, the whole buffer otherwise
+    pub fn get_stride(&self) -> isize {
+        self.stride
     }
 
     pub fn write_data<T>(&self, data: *const gl::types::GLvoid, offset: u32) {
@@ -49,6 +215,29 @@ impl UniformBuffer {
         }
     }
 
+    pub fn write_bytes(&self, data: &[u8], offset: u32) {
+        let len = data.len().min((self.buffer_size as usize).saturating_sub(offset as usize));
+
+        unsafe {
+            gl::NamedBufferSubData(self.id, offset as isize, len as isize, data.as_ptr() as *const gl::types::GLvoid);
+        }
+    }
+
+    // Writes into element index of a block array, offset being relative to the element.
+    // Errors if index is past the last element
+    pub fn write_element_bytes(&self, index: u32, data: &[u8], offset: u32) -> Result<(), GlError> {
+        let count = self.array_len.unwrap_or(1);
+
+        if index >= count {
+            return Err(GlError::UniformArrayIndex(self.name.clone(), index, count));
+        }
+
+        let len = data.len().min((self.stride as usize).saturating_sub(offset as usize));
+        self.write_bytes(&data[..len], (index as isize * self.stride) as u32 + offset);
+
+        Ok(())
+    }
+
     pub fn get_id(&self) -> u32 {
         self.id
     }
@@ -60,4 +249,48 @@ impl Drop for UniformBuffer {
             gl::DeleteBuffers(1, &self.id);
         }
     }
-}
\ No newline at end of file
+}
+
+// UBO holding a single struct, uploaded whole. T has to be #[repr(C)] and padded
+// to match the std140 layout of the block, e.g. vec3 members take 16 bytes
+pub struct TypedUniformBuffer<T> {
+    buffer: UniformBuffer,
+    marker: PhantomData<T>
+}
+
+impl<T> TypedUniformBuffer<T> {
+    pub fn set(&self, value: &T) {
+        unsafe {
+            gl::NamedBufferSubData(
+                self.buffer.get_id(),
+                0,
+                std::mem::size_of::<T>() as isize,
+                value as *const T as *const gl::types::GLvoid
+            );
+        }
+    }
+
+    pub fn register_shader_program(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+        self.buffer.register_shader_program(shader_program)
+    }
+
+    pub fn rebind_shader_program(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+        self.buffer.rebind_shader_program(shader_program)
+    }
+
+    pub fn validate_shader_program(&self, shader_program: &ShaderProgram) -> Result<(), GlError> {
+        self.buffer.validate_shader_program(shader_program)
+    }
+
+    pub fn bind_ubo(&self) {
+        self.buffer.bind_ubo();
+    }
+
+    pub fn get_buffer(&self) -> &UniformBuffer {
+        &self.buffer
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.buffer.get_id()
+    }
+}
@@ -1,5 +1,6 @@
 use cgmath::{Vector3, Vector2, Zero};
 
+#[derive(Clone, Copy)]
 #[repr(C, packed)]
 pub struct Vertex {
     pub position: Vector3<f32>,
@@ -19,4 +20,62 @@ impl Default for Vertex {
             bitangent: Vector3::zero()
         }
     }
-}
\ No newline at end of file
+}
+
+// Vertex types tangents can be calculated for, see Model::compute_tangents
+pub trait TangentVertex {
+    fn get_position(&self) -> Vector3<f32>;
+    fn get_tex_coord(&self) -> Vector2<f32>;
+    fn set_tangents(&mut self, tangent: Vector3<f32>, bitangent: Vector3<f32>);
+}
+
+impl TangentVertex for Vertex {
+    fn get_position(&self) -> Vector3<f32> { self.position }
+    fn get_tex_coord(&self) -> Vector2<f32> { self.tex_coord }
+
+    fn set_tangents(&mut self, tangent: Vector3<f32>, bitangent: Vector3<f32>) {
+        self.tangent = tangent;
+        self.bitangent = bitangent;
+    }
+}
+
+// Vertex with a second uv set for baked lighting, used with Model::new_custom
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct LightmapVertex {
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub tex_coord: Vector2<f32>,
+    pub tangent: Vector3<f32>,
+    pub bitangent: Vector3<f32>,
+    pub tex_coord2: Vector2<f32>
+}
+
+impl LightmapVertex {
+    pub fn from_vertex(vertex: Vertex, tex_coord2: Vector2<f32>) -> Self {
+        LightmapVertex {
+            position: vertex.position,
+            normal: vertex.normal,
+            tex_coord: vertex.tex_coord,
+            tangent: vertex.tangent,
+            bitangent: vertex.bitangent,
+            tex_coord2
+        }
+    }
+}
+
+impl Default for LightmapVertex {
+    fn default() -> Self {
+        LightmapVertex::from_vertex(Vertex::default(), Vector2::zero())
+    }
+}
+
+impl TangentVertex for LightmapVertex {
+    fn get_position(&self) -> Vector3<f32> { self.position }
+    fn get_tex_coord(&self) -> Vector2<f32> { self.tex_coord }
+
+    fn set_tangents(&mut self, tangent: Vector3<f32>, bitangent: Vector3<f32>) {
+        self.tangent = tangent;
+        self.bitangent = bitangent;
+    }
+}
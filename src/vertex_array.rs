@@ -1,15 +1,16 @@
-use super::{Buffer, gl};
+use super::{Buffer, VertexDescribe, StateCache, ContextId, RenderStats, GlError, capabilities, gl};
 
 pub struct VertexArray {
     id: u32,
     attrib_index: u32,
-    buffer_index: u32
+    buffer_index: u32,
+    context: ContextId
 }
 
 impl VertexArray {
     pub fn new() -> VertexArray {
         let mut vert_array = VertexArray {
-            id: 0, attrib_index: 0, buffer_index: 0
+            id: 0, attrib_index: 0, buffer_index: 0, context: StateCache::current_context()
         };
 
         unsafe {
@@ -34,45 +35,95 @@ impl VertexArray {
     }
 
     pub fn add_attrib<T>(&mut self, buffer: &mut Buffer<T>, size: i32, offset: u32, type_: gl::types::GLenum) {
+        self.set_attrib(buffer, self.attrib_index, size, offset, type_);
+
+        self.attrib_index += 1;
+    }
+
+    // Sources an attribute location from a buffer without moving on to the next location,
+    // replacing whatever that location was read from before
+    pub fn set_attrib<T>(&mut self, buffer: &mut Buffer<T>, location: u32, size: i32, offset: u32, type_: gl::types::GLenum) {
         unsafe {
-            gl::EnableVertexArrayAttrib(self.id, self.attrib_index);
+            gl::EnableVertexArrayAttrib(self.id, location);
             gl::VertexArrayAttribFormat(
                 self.id,
-                self.attrib_index,
+                location,
                 size,
                 type_,
                 gl::FALSE,
                 offset
             );
 
+            buffer.bind_to_vao_attrib(self.id, location);
+        }
+    }
+
+    // Read as int/uint in the shader rather than being converted to float
+    pub fn add_attrib_int<T>(&mut self, buffer: &mut Buffer<T>, size: i32, offset: u32, type_: gl::types::GLenum) {
+        unsafe {
+            gl::EnableVertexArrayAttrib(self.id, self.attrib_index);
+            gl::VertexArrayAttribIFormat(self.id, self.attrib_index, size, type_, offset);
+
             buffer.bind_to_vao_attrib(self.id, self.attrib_index);
         }
 
         self.attrib_index += 1;
     }
 
-    // For adding things like mat4 (types that are larger than 4*f32s but are multiples of it)
+    // Adds every attribute of the vertex type in order
+    pub fn add_layout<V: VertexDescribe>(&mut self, buffer: &mut Buffer<V>) {
+        for attribute in V::layout().attributes {
+            self.add_attrib(buffer, attribute.size, attribute.offset, attribute.type_);
+        }
+    }
+
+    // Sets the vertex type's attributes at consecutive locations from location
+    pub fn set_layout<V: VertexDescribe>(&mut self, buffer: &mut Buffer<V>, location: u32) {
+        for (i, attribute) in V::layout().attributes.into_iter().enumerate() {
+            self.set_attrib(buffer, location + i as u32, attribute.size, attribute.offset, attribute.type_);
+        }
+    }
+
+    // Leaves attribute locations unused so later attributes keep the same locations as another VAO
+    pub fn skip_attribs(&mut self, count: u32) {
+        self.attrib_index += count;
+    }
+
+    // For adding things like mat4 (types that are larger than 4*f32s but are multiples of it),
+    // advanced once per instance
     pub fn add_attrib_divisor<T>(&mut self, buffer: &mut Buffer<T>, rows: i32) {
+        self.set_attrib_divisor(buffer, self.attrib_index, rows, 1);
+
+        self.attrib_index += rows as u32;
+    }
+
+    // Per-instance attribute of rows vec4s at consecutive locations from location, advanced
+    // every divisor instances, e.g. 2 for data shared by pairs of instances. The divisor is
+    // set on the buffer's binding, so it applies to every attribute read from that buffer
+    pub fn set_attrib_divisor<T>(&mut self, buffer: &mut Buffer<T>, location: u32, rows: i32, divisor: u32) {
         // Row size is constant in OpenGL
         let size_vec4 = 16;
 
         for i in 0..rows {
-            self.add_attrib(buffer, 4, (i * size_vec4) as u32, gl::FLOAT);
+            self.set_attrib(buffer, location + i as u32, 4, (i * size_vec4) as u32, gl::FLOAT);
         }
 
         unsafe {
-            buffer.set_divisor(self.id, 1);
+            buffer.set_divisor(self.id, divisor);
         }
     }
 
+    // Skipped if the VAO is already bound, see StateCache
     pub unsafe fn bind(&self) {
-        gl::BindVertexArray(self.id);
+        StateCache::check_context(&self.context, "Vertex array", self.id);
+        StateCache::bind_vertex_array(self.id);
     }
 
     // Get count and instance_count from in-built buffer objects
     pub fn draw_elements(&self, count: i32, instance_count: i32) {
         unsafe {
-            gl::BindVertexArray(self.id);
+            self.bind();
+            RenderStats::record_draw(gl::TRIANGLES, count, instance_count);
             gl::DrawElementsInstanced(
                 gl::TRIANGLES,
                 count,
@@ -80,15 +131,17 @@ impl VertexArray {
                 std::ptr::null(),
                 instance_count
             );
-            gl::BindVertexArray(0);
+            StateCache::bind_vertex_array(0);
         }
     }
 
     // Requires VAO to be bound already
-    pub fn draw_elements_offset(&self, count: i32, offset: usize, instance_count: i32) {
+    pub fn draw_elements_offset(&self, mode: gl::types::GLenum, count: i32, offset: usize, instance_count: i32) {
+        RenderStats::record_draw(mode, count, instance_count);
+
         unsafe {
             gl::DrawElementsInstanced(
-                gl::TRIANGLES,
+                mode,
                 count,
                 gl::UNSIGNED_INT,
                 std::ptr::null::<u32>().add(offset) as *const gl::types::GLvoid,
@@ -97,12 +150,112 @@ impl VertexArray {
         }
     }
 
+    // Requires VAO to be bound already
+    // base_vertex is added to each index fetched from the element buffer
+    pub fn draw_elements_base_vertex(&self, mode: gl::types::GLenum, count: i32, offset: usize, base_vertex: i32, instance_count: i32) {
+        RenderStats::record_draw(mode, count, instance_count);
+
+        unsafe {
+            gl::DrawElementsInstancedBaseVertex(
+                mode,
+                count,
+                gl::UNSIGNED_INT,
+                std::ptr::null::<u32>().add(offset) as *const gl::types::GLvoid,
+                instance_count,
+                base_vertex
+            );
+        }
+    }
+
+    // Requires VAO to be bound already
+    // Instanced attributes start reading at base_instance, so a window of a larger
+    // per-instance buffer can be drawn without rebinding it. Requires GL 4.2
+    pub fn draw_elements_offset_base_instance(
+        &self,
+        mode: gl::types::GLenum,
+        count: i32,
+        offset: usize,
+        instance_count: i32,
+        base_instance: u32
+    ) -> Result<(), GlError> {
+        if !capabilities::supports_version(4, 2) && !capabilities::has_extension("GL_ARB_base_instance") {
+            return Err(GlError::UnsupportedFeature("GL_ARB_base_instance".to_owned()));
+        }
+
+        RenderStats::record_draw(mode, count, instance_count);
+
+        unsafe {
+            gl::DrawElementsInstancedBaseInstance(
+                mode,
+                count,
+                gl::UNSIGNED_INT,
+                std::ptr::null::<u32>().add(offset) as *const gl::types::GLvoid,
+                instance_count,
+                base_instance
+            );
+        }
+
+        Ok(())
+    }
+
+    // For index-less draws such as point clouds, binds the VAO itself
+    pub fn draw_arrays(&self, mode: gl::types::GLenum, first: i32, count: i32, instance_count: i32) {
+        unsafe {
+            self.bind();
+            RenderStats::record_draw(mode, count, instance_count);
+            gl::DrawArraysInstanced(mode, first, count, instance_count);
+            StateCache::bind_vertex_array(0);
+        }
+    }
+
+    // Requires VAO to be bound already, the draw_elements_offset of non indexed meshes
+    pub fn draw_arrays_offset(&self, mode: gl::types::GLenum, first: i32, count: i32, instance_count: i32) {
+        RenderStats::record_draw(mode, count, instance_count);
+
+        unsafe {
+            gl::DrawArraysInstanced(mode, first, count, instance_count);
+        }
+    }
+
+    // Primitive restart is global GL state, not per VAO, so it stays enabled until disabled.
+    // The convention is to use the max value of the index type (0xFFFFFFFF for u32 indices)
+    pub fn set_primitive_restart(&self, index: u32) {
+        unsafe {
+            gl::Enable(gl::PRIMITIVE_RESTART);
+            gl::PrimitiveRestartIndex(index);
+        }
+    }
+
+    pub fn disable_primitive_restart(&self) {
+        unsafe {
+            gl::Disable(gl::PRIMITIVE_RESTART);
+        }
+    }
+
+    // Requires VAO to be bound already
+    // Each occurrence of the restart index in the element buffer starts a new strip
+    pub fn draw_strip_elements(&self, count: i32, offset: usize) {
+        RenderStats::record_draw(gl::TRIANGLE_STRIP, count, 1);
+
+        unsafe {
+            gl::DrawElements(
+                gl::TRIANGLE_STRIP,
+                count,
+                gl::UNSIGNED_INT,
+                std::ptr::null::<u32>().add(offset) as *const gl::types::GLvoid
+            );
+        }
+    }
+
     // Need to generate and bind commands array beforehand
     // GL_DRAW_INDIRECT_BUFFER must be bound
-    pub fn draw_elements_multi_indirect(&self, command_count: i32) {
+    // Every command is drawn with the same mode
+    pub fn draw_elements_multi_indirect(&self, mode: gl::types::GLenum, command_count: i32) {
+        RenderStats::record_indirect_draws(command_count);
+
         unsafe {
             gl::MultiDrawElementsIndirect(
-                gl::TRIANGLES,
+                mode,
                 gl::UNSIGNED_INT,
                 std::ptr::null(),
                 command_count,
@@ -111,6 +264,21 @@ impl VertexArray {
         }
     }
 
+    // Draw count is read from GL_PARAMETER_BUFFER at offset 0, capped at max_command_count.
+    // Requires GL 4.6 or ARB_indirect_parameters, and GL_DRAW_INDIRECT_BUFFER to be bound
+    pub fn draw_elements_multi_indirect_count(&self, mode: gl::types::GLenum, max_command_count: i32) {
+        unsafe {
+            gl::MultiDrawElementsIndirectCount(
+                mode,
+                gl::UNSIGNED_INT,
+                std::ptr::null(),
+                0,
+                max_command_count,
+                0 // Draw commands are tightly packed
+            )
+        }
+    }
+
     pub fn get_id(&self) -> u32 {
         self.id
     }
@@ -119,7 +287,7 @@ impl VertexArray {
 impl Drop for VertexArray {
     fn drop(&mut self) {
         unsafe {
-            gl::BindVertexArray(0);
+            StateCache::forget_vertex_array(self.id);
             gl::DeleteVertexArrays(1, &self.id);
         }
     }
@@ -127,6 +295,7 @@ impl Drop for VertexArray {
 
 // To be used with glMultiDrawElementsIndirect,
 // Longer name is DrawElementsIndirectCommand
+#[derive(Clone, Copy)]
 #[repr(C, packed)]
 pub struct DrawCommand {
     count: u32,
@@ -134,4 +303,20 @@ pub struct DrawCommand {
     first_index: u32,
     base_vertex: i32,
     base_instance: u32
+}
+
+impl DrawCommand {
+    pub fn new(count: u32, instance_count: u32, first_index: u32, base_vertex: i32, base_instance: u32) -> DrawCommand {
+        DrawCommand {
+            count,
+            instance_count,
+            first_index,
+            base_vertex,
+            base_instance
+        }
+    }
+
+    pub fn set_instance_count(&mut self, instance_count: u32) {
+        self.instance_count = instance_count;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,61 @@
+use memoffset::offset_of;
+use super::{Vertex, LightmapVertex, gl};
+
+pub struct VertexAttribute {
+    // Component count, 1 to 4
+    pub size: i32,
+    // Byte offset into the vertex struct
+    pub offset: u32,
+    pub type_: gl::types::GLenum
+}
+
+// Attributes in location order, the first one is expected to be the position
+pub struct VertexLayout {
+    pub attributes: Vec<VertexAttribute>
+}
+
+impl VertexLayout {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> VertexLayout {
+        VertexLayout { attributes: Vec::new() }
+    }
+
+    pub fn with(mut self, size: i32, offset: usize, type_: gl::types::GLenum) -> VertexLayout {
+        self.attributes.push(VertexAttribute { size, offset: offset as u32, type_ });
+        self
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.attributes.len()
+    }
+}
+
+// Implemented by vertex structs that can be used in a Model, they should be #[repr(C)]
+pub trait VertexDescribe {
+    fn layout() -> VertexLayout;
+}
+
+impl VertexDescribe for Vertex {
+    fn layout() -> VertexLayout {
+        VertexLayout::new()
+            .with(3, offset_of!(Vertex, position), gl::FLOAT)
+            .with(3, offset_of!(Vertex, normal), gl::FLOAT)
+            .with(2, offset_of!(Vertex, tex_coord), gl::FLOAT)
+            .with(3, offset_of!(Vertex, tangent), gl::FLOAT)
+            .with(3, offset_of!(Vertex, bitangent), gl::FLOAT)
+    }
+}
+
+// Same locations as Vertex, with the second uv set at location 5
+impl VertexDescribe for LightmapVertex {
+    fn layout() -> VertexLayout {
+        VertexLayout::new()
+            .with(3, offset_of!(LightmapVertex, position), gl::FLOAT)
+            .with(3, offset_of!(LightmapVertex, normal), gl::FLOAT)
+            .with(2, offset_of!(LightmapVertex, tex_coord), gl::FLOAT)
+            .with(3, offset_of!(LightmapVertex, tangent), gl::FLOAT)
+            .with(3, offset_of!(LightmapVertex, bitangent), gl::FLOAT)
+            .with(2, offset_of!(LightmapVertex, tex_coord2), gl::FLOAT)
+    }
+}
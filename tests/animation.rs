@@ -0,0 +1,48 @@
+mod common;
+
+use std::rc::Rc;
+use cgmath::{vec3, Matrix4, SquareMatrix};
+use silver_gl::{gl, Animation, AnimationPlayer, Channel, ChannelValues, GlError, Interpolation, JointTransform, Skeleton};
+
+fn slide() -> Channel {
+    Channel {
+        joint: 0,
+        times: vec![0.0, 1.0],
+        values: ChannelValues::Translation(vec![vec3(0.0, 0.0, 0.0), vec3(2.0, 0.0, 0.0)]),
+        interpolation: Interpolation::Linear
+    }
+}
+
+#[test]
+fn update_writes_palette_to_bone_buffer() {
+    common::with_context(|| {
+        let skeleton = Skeleton::new(vec![None], vec![Matrix4::identity()], vec![JointTransform::identity()]).unwrap();
+        let animation = Animation::new(vec![slide()]).unwrap();
+        let mut player = AnimationPlayer::new(Rc::new(skeleton), Rc::new(animation));
+
+        player.update(0.5);
+        assert_eq!(player.get_palette()[0], Matrix4::from_translation(vec3(1.0, 0.0, 0.0)));
+
+        player.bind(0);
+        let mut bones = [Matrix4::<f32>::identity(); 1];
+        unsafe {
+            let mut id = 0;
+            gl::GetIntegeri_v(gl::SHADER_STORAGE_BUFFER_BINDING, 0, &mut id);
+            gl::GetNamedBufferSubData(
+                id as u32,
+                0,
+                std::mem::size_of_val(&bones) as isize,
+                bones.as_mut_ptr() as *mut gl::types::GLvoid
+            );
+        }
+        assert_eq!(bones[0], player.get_palette()[0]);
+    });
+}
+
+#[test]
+fn keyframe_counts_are_checked() {
+    let mut channel = slide();
+    channel.times.push(2.0);
+
+    assert!(matches!(Animation::new(vec![channel]), Err(GlError::KeyframeCountMismatch(0, 3, 2))));
+}
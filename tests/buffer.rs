@@ -0,0 +1,78 @@
+mod common;
+
+use std::ops::Range;
+use silver_gl::{Buffer, GlError};
+
+#[test]
+fn read_back_skips_spare_capacity() {
+    common::with_context(|| {
+        let mut buffer = Buffer::new();
+        buffer.set_growth(true);
+        buffer.set_data_mut((0..8u32).collect());
+        buffer.set_data_mut(vec![7u32, 8, 9]);
+
+        assert_eq!(buffer.capacity(), 8);
+        assert_eq!(buffer.read_back(), vec![7, 8, 9]);
+
+        buffer.release_data();
+        assert_eq!(buffer.read_back(), vec![7, 8, 9]);
+
+        // Wrapped buffers don't know what was sent, so the whole storage is read
+        let raw = Buffer::<u32>::from_raw(buffer.get_id(), false);
+        assert_eq!(raw.read_back().len(), 8);
+    });
+}
+
+#[test]
+fn map_write_checks_range() {
+    common::with_context(|| {
+        let mut buffer = Buffer::new();
+        buffer.set_data_mut(vec![0u32; 4]);
+
+        assert!(buffer.map_write(2, 3).is_none());
+        assert!(buffer.map_write(usize::MAX, 2).is_none());
+
+        {
+            let mut mapped = buffer.map_write(2, 2).unwrap();
+            mapped[0] = 5;
+            mapped[1] = 6;
+        }
+        assert_eq!(buffer.read_back(), vec![0, 0, 5, 6]);
+    });
+}
+
+#[test]
+fn compact_validates_ranges() {
+    common::with_context(|| {
+        let mut buffer = Buffer::new();
+        buffer.set_data_mut((0..8u32).collect());
+
+        let range = |start, end| Range { start, end };
+        assert!(matches!(buffer.compact(&[range(2, 10)]), Err(GlError::InvalidBufferRange(_, 2, 10, 8))));
+        assert!(matches!(buffer.compact(&[range(5, 3)]), Err(GlError::InvalidBufferRange(_, 5, 3, 8))));
+        assert!(matches!(buffer.compact(&[4..6, 0..5]), Err(GlError::OverlappingBufferRanges(_, 5, 4))));
+        assert_eq!(buffer.get_data(), &(0..8).collect::<Vec<u32>>());
+
+        assert_eq!(buffer.compact(&[6..8, 1..3]).unwrap(), vec![2, 0]);
+        assert_eq!(buffer.read_back(), vec![1, 2, 6, 7]);
+
+        buffer.release_data();
+        assert!(matches!(buffer.compact(&[range(0, 2)]), Err(GlError::BufferDataReleased(_))));
+    });
+}
+
+#[test]
+fn send_slice_mut_leaves_cpu_copy() {
+    common::with_context(|| {
+        let mut buffer = Buffer::new();
+        buffer.set_growth(true);
+        let lines: Vec<u32> = (0..8).collect();
+
+        buffer.send_slice_mut(&lines);
+        buffer.send_slice_mut(&lines[..3]);
+
+        assert!(buffer.get_data().is_empty());
+        assert_eq!(buffer.capacity(), 8);
+        assert_eq!(buffer.read_back(), vec![0, 1, 2]);
+    });
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use std::ffi::CStr;
+use silver_gl::{capabilities, gl, StateCache};
+
+#[test]
+fn cached_capabilities_match_queries() {
+    common::with_context(|| {
+        let mut count = 0;
+        unsafe { gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count) };
+        let first = unsafe { CStr::from_ptr(gl::GetStringi(gl::EXTENSIONS, 0) as *const _) }.to_str().unwrap().to_owned();
+
+        // Second calls come from the cache, and survive a context switch being announced
+        for _ in 0..2 {
+            assert!(count > 0 && capabilities::has_extension(&first));
+            assert!(!capabilities::has_extension("GL_SILVER_not_an_extension"));
+            assert!(capabilities::supports_version(4, 5));
+            assert!(!capabilities::supports_version(9, 0));
+        }
+
+        StateCache::make_current(1);
+        assert!(capabilities::has_extension(&first) && capabilities::supports_version(4, 5));
+        StateCache::make_current(0);
+    });
+}
@@ -0,0 +1,181 @@
+// Headless GL context for integration tests, made with EGL without a window or display
+// server (Mesa's surfaceless platform, llvmpipe works). EGL is loaded at runtime, so
+// machines without it still build the tests, and with_context skips the test body there
+#![allow(dead_code)]
+
+use std::{ffi::{c_void, CStr, CString}, sync::Once};
+use silver_gl::{gl, Framebuffer, ShaderCodeBundle, ShaderProgram};
+
+type EglDisplay = *mut c_void;
+type EglConfig = *mut c_void;
+type EglContext = *mut c_void;
+
+const EGL_NONE: i32 = 0x3038;
+const EGL_SURFACE_TYPE: i32 = 0x3033;
+const EGL_PBUFFER_BIT: i32 = 0x0001;
+const EGL_RENDERABLE_TYPE: i32 = 0x3040;
+const EGL_OPENGL_BIT: i32 = 0x0008;
+const EGL_OPENGL_API: u32 = 0x30A2;
+const EGL_CONTEXT_MAJOR_VERSION: i32 = 0x3098;
+const EGL_CONTEXT_MINOR_VERSION: i32 = 0x30FB;
+const EGL_CONTEXT_OPENGL_PROFILE_MASK: i32 = 0x30FD;
+const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT: i32 = 0x0001;
+const EGL_PLATFORM_SURFACELESS_MESA: u32 = 0x31DD;
+
+type GetPlatformDisplay = unsafe extern "C" fn(u32, *mut c_void, *const isize) -> EglDisplay;
+type Initialize = unsafe extern "C" fn(EglDisplay, *mut i32, *mut i32) -> u32;
+type BindApi = unsafe extern "C" fn(u32) -> u32;
+type ChooseConfig = unsafe extern "C" fn(EglDisplay, *const i32, *mut EglConfig, i32, *mut i32) -> u32;
+type CreateContext = unsafe extern "C" fn(EglDisplay, EglConfig, EglContext, *const i32) -> EglContext;
+type MakeCurrent = unsafe extern "C" fn(EglDisplay, *mut c_void, *mut c_void, EglContext) -> u32;
+type GetProcAddress = unsafe extern "C" fn(*const libc::c_char) -> *const c_void;
+
+struct Egl {
+    get_platform_display: GetPlatformDisplay,
+    initialize: Initialize,
+    bind_api: BindApi,
+    choose_config: ChooseConfig,
+    create_context: CreateContext,
+    make_current: MakeCurrent,
+    get_proc_address: GetProcAddress
+}
+
+impl Egl {
+    unsafe fn load() -> Option<Egl> {
+        let library = [&b"libEGL.so.1\0"[..], &b"libEGL.so\0"[..]]
+            .iter()
+            .map(|name| libc::dlopen(name.as_ptr() as *const libc::c_char, libc::RTLD_NOW))
+            .find(|library| !library.is_null())?;
+
+        macro_rules! load {
+            ($name:literal, $type:ty) => {{
+                let symbol = libc::dlsym(library, concat!($name, "\0").as_ptr() as *const libc::c_char);
+                if symbol.is_null() { return None; }
+                std::mem::transmute::<*mut c_void, $type>(symbol)
+            }};
+        }
+
+        Some(Egl {
+            get_platform_display: load!("eglGetPlatformDisplay", GetPlatformDisplay),
+            initialize: load!("eglInitialize", Initialize),
+            bind_api: load!("eglBindAPI", BindApi),
+            choose_config: load!("eglChooseConfig", ChooseConfig),
+            create_context: load!("eglCreateContext", CreateContext),
+            make_current: load!("eglMakeCurrent", MakeCurrent),
+            get_proc_address: load!("eglGetProcAddress", GetProcAddress)
+        })
+    }
+
+    // GL 4.5 core is enough for the DSA calls the crate makes
+    unsafe fn create_current_context(&self) -> Option<()> {
+        let display = (self.get_platform_display)(EGL_PLATFORM_SURFACELESS_MESA, std::ptr::null_mut(), std::ptr::null());
+        if display.is_null() || (self.initialize)(display, std::ptr::null_mut(), std::ptr::null_mut()) == 0 {
+            return None;
+        }
+
+        if (self.bind_api)(EGL_OPENGL_API) == 0 {
+            return None;
+        }
+
+        // Surfaceless displays have no window configs, which is what EGL looks for by default
+        let config_attribs = [EGL_SURFACE_TYPE, EGL_PBUFFER_BIT, EGL_RENDERABLE_TYPE, EGL_OPENGL_BIT, EGL_NONE];
+        let mut config = std::ptr::null_mut();
+        let mut config_count = 0;
+        if (self.choose_config)(display, config_attribs.as_ptr(), &mut config, 1, &mut config_count) == 0 || config_count < 1 {
+            return None;
+        }
+
+        let context_attribs = [
+            EGL_CONTEXT_MAJOR_VERSION, 4,
+            EGL_CONTEXT_MINOR_VERSION, 5,
+            EGL_CONTEXT_OPENGL_PROFILE_MASK, EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            EGL_NONE
+        ];
+        let context = (self.create_context)(display, config, std::ptr::null_mut(), context_attribs.as_ptr());
+        if context.is_null() || (self.make_current)(display, std::ptr::null_mut(), std::ptr::null_mut(), context) == 0 {
+            return None;
+        }
+
+        // Function pointers are global, so they're only loaded by the first thread
+        static LOAD: Once = Once::new();
+        LOAD.call_once(|| gl::load_with(|name| {
+            let name = CString::new(name).unwrap();
+            (self.get_proc_address)(name.as_ptr())
+        }));
+
+        Some(())
+    }
+}
+
+thread_local! {
+    static CONTEXT: bool = unsafe { Egl::load().and_then(|egl| egl.create_current_context()).is_some() };
+}
+
+// Runs test with a GL context current on this thread (each test thread gets its own).
+// Without one the test is skipped with a note, so headless CI without EGL stays green
+pub fn with_context<F: FnOnce()>(test: F) {
+    if !CONTEXT.with(|created| *created) {
+        eprintln!("skipped, no OpenGL 4.5 context could be created through EGL");
+        return;
+    }
+
+    test();
+
+    let error = unsafe { gl::GetError() };
+    assert_eq!(error, gl::NO_ERROR, "GL error {:#X} left after the test", error);
+}
+
+// Whether the context is at least major.minor, for tests of shaders with a newer #version.
+// Prints a skip note when it isn't. On llvmpipe (4.5) such tests can still be run with
+// MESA_GL_VERSION_OVERRIDE=4.6 MESA_GLSL_VERSION_OVERRIDE=460
+pub fn requires_version(major: i32, minor: i32) -> bool {
+    let (mut context_major, mut context_minor) = (0, 0);
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut context_major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut context_minor);
+    }
+
+    let supported = (context_major, context_minor) >= (major, minor);
+    if !supported {
+        eprintln!("skipped, needs OpenGL {}.{} but the context is {}.{}", major, minor, context_major, context_minor);
+    }
+
+    supported
+}
+
+pub fn gl_string(name: gl::types::GLenum) -> String {
+    unsafe { CStr::from_ptr(gl::GetString(name) as *const libc::c_char).to_string_lossy().into_owned() }
+}
+
+// Positions from Vertex at location 0 moved by the instance transform at location 5,
+// every fragment is written as white
+pub fn flat_shader() -> ShaderProgram {
+    ShaderProgram::new(ShaderCodeBundle {
+        vertex: Some(String::from("#version 450 core
+            layout(location = 0) in vec3 position;
+            layout(location = 5) in mat4 model;
+            void main() { gl_Position = model * vec4(position, 1.0); }")),
+        geometry: None,
+        fragment: Some(String::from("#version 450 core
+            out vec4 color;
+            void main() { color = vec4(1.0); }"))
+    }).unwrap()
+}
+
+// Binds the framebuffer with a viewport covering it and clears it to black
+pub fn bind_cleared(framebuffer: &Framebuffer) {
+    let (width, height) = framebuffer.get_size();
+
+    framebuffer.bind();
+    framebuffer.clear_all_colors([0.0, 0.0, 0.0, 0.0]);
+    unsafe { gl::Viewport(0, 0, width, height) };
+}
+
+// Whether the pixel at x, y (counted from the bottom left like GL) of color attachment 0 isn't black
+pub fn is_lit(framebuffer: &Framebuffer, x: i32, y: i32) -> bool {
+    let image = framebuffer.get(0).unwrap().read_image().unwrap();
+    let row = image.height - 1 - y;
+    let offset = ((row * image.width + x) * 4) as usize;
+
+    image.bytes[offset..offset + 3].iter().any(|channel| *channel > 0)
+}
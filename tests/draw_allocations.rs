@@ -0,0 +1,63 @@
+mod common;
+
+use std::{alloc::{GlobalAlloc, Layout, System}, cell::Cell, rc::Rc};
+use cgmath::Matrix4;
+use silver_gl::{model_utils::create_quad, Framebuffer, ModelTrait, MultiBindModel, Texture};
+
+// Counts allocations made by the current thread, so other test threads don't interfere
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations_during<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(|count| count.get());
+    f();
+    ALLOCATIONS.with(|count| count.get()) - before
+}
+
+#[test]
+fn draw_does_not_allocate() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        let framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+        let mut quad: MultiBindModel = create_quad(vec![Matrix4::from_scale(0.5); 3]);
+
+        // Two textures per slot so the indexed sampler names are built every draw
+        let mesh = &mut quad.get_meshes_mut()[0];
+        for _ in 0..2 {
+            mesh.diffuse_textures.push(Rc::new(Texture::new_mut(4, 4)));
+            mesh.specular_textures.push(Rc::new(Texture::new_mut(4, 4)));
+        }
+
+        shader.use_program();
+        common::bind_cleared(&framebuffer);
+
+        // The first draw may fill lazily created state such as the texture unit cache
+        quad.draw(&shader).unwrap();
+
+        assert_eq!(allocations_during(|| quad.draw(&shader).unwrap()), 0);
+
+        // Subset scratch buffers are created by the first call and reused after
+        quad.draw_instances_indexed(&shader, &[2, 0]).unwrap();
+        assert_eq!(allocations_during(|| quad.draw_instances_indexed(&shader, &[1]).unwrap()), 0);
+        assert!(common::is_lit(&framebuffer, 8, 8));
+
+        Framebuffer::unbind();
+    });
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use std::rc::Rc;
+use silver_gl::{gl, Framebuffer, FramebufferBuilder, GlError, ResizeResult, ShaderCodeBundle, ShaderProgram, Texture};
+
+fn level_parameter(texture: &Texture, parameter: gl::types::GLenum) -> i32 {
+    let mut value = 0;
+    unsafe { gl::GetTextureLevelParameteriv(texture.get_id(), 0, parameter, &mut value) };
+    value
+}
+
+#[test]
+fn resize_keeps_attachment_formats() {
+    common::with_context(|| {
+        let mut framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+        framebuffer.gen_depth_texture();
+
+        framebuffer.set_size(40, 24).unwrap();
+
+        let color = framebuffer.get(0).unwrap();
+        assert_eq!(level_parameter(&color, gl::TEXTURE_INTERNAL_FORMAT) as u32, gl::RGBA16F);
+        assert_eq!((level_parameter(&color, gl::TEXTURE_WIDTH), level_parameter(&color, gl::TEXTURE_HEIGHT)), (40, 24));
+
+        let depth = framebuffer.get_depth_texture().unwrap();
+        assert_eq!(level_parameter(&depth, gl::TEXTURE_INTERNAL_FORMAT) as u32, gl::DEPTH_COMPONENT32F);
+        assert_eq!(level_parameter(&depth, gl::TEXTURE_WIDTH), 40);
+    });
+}
+
+#[test]
+fn zero_size_resize_is_deferred() {
+    common::with_context(|| {
+        let mut framebuffer = Framebuffer::new(16, 16, 1, true).unwrap();
+
+        // Minimized window, the attachments keep their size and the framebuffer stays complete
+        assert_eq!(framebuffer.set_size(0, 720).unwrap(), ResizeResult::Deferred);
+        assert_eq!(framebuffer.set_size(1280, 0).unwrap(), ResizeResult::Deferred);
+        assert!(framebuffer.is_resize_pending());
+        assert_eq!(framebuffer.get_size(), (16, 16));
+        assert_eq!(level_parameter(&framebuffer.get(0).unwrap(), gl::TEXTURE_WIDTH), 16);
+        framebuffer.check_status().unwrap();
+
+        // Restored
+        assert_eq!(framebuffer.set_size(32, 8).unwrap(), ResizeResult::Resized);
+        assert!(!framebuffer.is_resize_pending());
+        assert_eq!(framebuffer.get_size(), (32, 8));
+        framebuffer.check_status().unwrap();
+    });
+}
+
+#[test]
+fn depth_only_framebuffer_is_complete() {
+    common::with_context(|| {
+        let framebuffer = Framebuffer::new_depth_only(64, 64).unwrap();
+
+        assert_eq!(framebuffer.len(), 0);
+        assert!(framebuffer.get_depth_texture().is_some());
+        assert!(framebuffer.active_draw_buffers().is_empty());
+        framebuffer.check_status().unwrap();
+
+        let mut draw_buffer = 0;
+        let mut read_buffer = 0;
+        framebuffer.bind();
+        unsafe {
+            gl::GetIntegerv(gl::DRAW_BUFFER0, &mut draw_buffer);
+            gl::GetIntegerv(gl::READ_BUFFER, &mut read_buffer);
+        }
+        Framebuffer::unbind();
+        assert_eq!((draw_buffer as u32, read_buffer as u32), (gl::NONE, gl::NONE));
+    });
+}
+
+#[test]
+fn zero_size_framebuffer_errors() {
+    common::with_context(|| {
+        assert!(matches!(Framebuffer::new(0, 16, 1, false), Err(GlError::InvalidTextureSize(0, 16, _))));
+        assert!(matches!(Framebuffer::new_depth_only(0, 0), Err(GlError::InvalidTextureSize(0, 0, _))));
+        assert!(matches!(FramebufferBuilder::new(16, 0).build(), Err(GlError::InvalidTextureSize(16, 0, _))));
+    });
+}
+
+#[test]
+fn try_new_mut_keeps_earlier_errors() {
+    common::with_context(|| {
+        unsafe { gl::Enable(gl::NONE) };
+
+        assert!(Texture::try_new_mut(4, 4).is_ok());
+        assert_eq!(unsafe { gl::GetError() }, gl::INVALID_ENUM);
+    });
+}
+
+// Copies the first linked texture, which is bound to unit 0
+fn copy_shader() -> ShaderProgram {
+    ShaderProgram::new(ShaderCodeBundle {
+        vertex: Some(String::from("#version 450 core
+            layout(location = 0) in vec3 position;
+            void main() { gl_Position = vec4(position, 1.0); }")),
+        geometry: None,
+        fragment: Some(String::from("#version 450 core
+            layout(binding = 0) uniform sampler2D linked;
+            out vec4 color;
+            void main() { color = texelFetch(linked, ivec2(gl_FragCoord.xy), 0); }"))
+    }).unwrap()
+}
+
+#[test]
+fn links_before_first_draw_reach_quad() {
+    common::with_context(|| {
+        let shader = copy_shader();
+        shader.use_program();
+
+        let white = Framebuffer::new(16, 16, 1, false).unwrap();
+        white.bind();
+        white.clear_all_colors([1.0, 1.0, 1.0, 1.0]);
+        let black = Framebuffer::new(16, 16, 1, false).unwrap();
+        black.bind();
+        black.clear_all_colors([0.0, 0.0, 0.0, 0.0]);
+
+        // Linked while the quad doesn't exist yet
+        let mut framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+        framebuffer.link_to_fb(&white);
+
+        common::bind_cleared(&framebuffer);
+        framebuffer.draw(&shader).unwrap();
+        assert!(common::is_lit(&framebuffer, 8, 8));
+
+        // Relinked after the quad was made
+        framebuffer.unlink();
+        framebuffer.link_to_fb(&black);
+        framebuffer.draw(&shader).unwrap();
+        assert!(!common::is_lit(&framebuffer, 8, 8));
+
+        Framebuffer::unbind();
+    });
+}
+
+#[test]
+fn set_attachment_past_end_errors() {
+    common::with_context(|| {
+        let mut framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+        let texture = Rc::new(Texture::new_mut(16, 16));
+
+        assert!(matches!(
+            framebuffer.set_attachment(2, Rc::clone(&texture)),
+            Err(GlError::InvalidAttachmentIndex(_, 2, 1))
+        ));
+        assert_eq!(framebuffer.len(), 1);
+
+        framebuffer.set_attachment(1, texture).unwrap();
+        assert_eq!(framebuffer.len(), 2);
+    });
+}
+
+#[test]
+fn clears_reach_inactive_attachments() {
+    common::with_context(|| {
+        let mut framebuffer = Framebuffer::new(4, 4, 3, false).unwrap();
+        framebuffer.set_draw_buffers(&[2]);
+
+        let lit = |index: usize| framebuffer.get(index).unwrap().read_image().unwrap().bytes[0] > 0;
+
+        framebuffer.clear_all_colors([1.0, 1.0, 1.0, 1.0]);
+        assert!(lit(0) && lit(1) && lit(2));
+
+        framebuffer.clear_color(0, [0.0, 0.0, 0.0, 0.0]);
+        assert!(!lit(0) && lit(2));
+
+        // The partial set is still the one drawn to
+        let mut draw_buffer = 0;
+        framebuffer.bind();
+        unsafe { gl::GetIntegerv(gl::DRAW_BUFFER0, &mut draw_buffer) };
+        Framebuffer::unbind();
+        assert_eq!(draw_buffer as u32, gl::COLOR_ATTACHMENT2);
+        assert_eq!(framebuffer.active_draw_buffers(), &[gl::COLOR_ATTACHMENT2]);
+    });
+}
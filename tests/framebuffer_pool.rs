@@ -0,0 +1,34 @@
+mod common;
+
+use silver_gl::{gl, FramebufferDesc, FramebufferPool};
+
+fn desc(size: i32) -> FramebufferDesc {
+    FramebufferDesc { width: size, height: size, color_attachments: 1, color_format: gl::RGBA8, render_buffer: false, samples: 0 }
+}
+
+#[test]
+fn released_framebuffers_are_filed_by_what_they_are() {
+    common::with_context(|| {
+        let pool = FramebufferPool::new();
+
+        let mut framebuffer = pool.acquire(desc(16)).unwrap();
+        assert_eq!(framebuffer.get(0).unwrap().get_internal_format(), gl::RGBA8);
+
+        framebuffer.set_size(32, 32).unwrap();
+        let id = framebuffer.get_id();
+        drop(framebuffer);
+
+        // Filed under its new size, so a 16x16 request doesn't get a 32x32 framebuffer
+        let small = pool.acquire(desc(16)).unwrap();
+        assert_ne!(small.get_id(), id);
+        assert_eq!(small.get_size(), (16, 16));
+        assert_eq!(pool.acquire(desc(32)).unwrap().get_id(), id);
+
+        // A depth texture can't be described, so it isn't pooled
+        let mut framebuffer = pool.acquire(desc(8)).unwrap();
+        framebuffer.gen_depth_texture();
+        let free = pool.free_count();
+        drop(framebuffer);
+        assert_eq!(pool.free_count(), free);
+    });
+}
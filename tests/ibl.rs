@@ -0,0 +1,50 @@
+mod common;
+
+use silver_gl::{gl, Framebuffer, Ibl, Texture};
+
+fn get_integer(parameter: gl::types::GLenum) -> i32 {
+    let mut value = 0;
+    unsafe { gl::GetIntegerv(parameter, &mut value) };
+    value
+}
+
+#[test]
+fn capture_restores_state() {
+    common::with_context(|| {
+        // The IBL shaders are #version 460
+        if !common::requires_version(4, 6) {
+            return;
+        }
+
+        let draw = Framebuffer::new(8, 8, 1, false).unwrap();
+        let read = Framebuffer::new(8, 8, 1, false).unwrap();
+        let equirectangular = Texture::new_immutable(8, 4, gl::RGBA8, 1);
+
+        unsafe {
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, draw.get_id());
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, read.get_id());
+            gl::Viewport(1, 2, 3, 4);
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Enable(gl::CULL_FACE);
+            gl::Disable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+        }
+
+        Ibl::equirectangular_to_cubemap(&equirectangular, 4).unwrap();
+
+        let mut viewport = [0; 4];
+        unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+
+        assert_eq!(get_integer(gl::DRAW_FRAMEBUFFER_BINDING) as u32, draw.get_id());
+        assert_eq!(get_integer(gl::READ_FRAMEBUFFER_BINDING) as u32, read.get_id());
+        assert_eq!(viewport, [1, 2, 3, 4]);
+        unsafe {
+            assert_eq!(gl::IsEnabled(gl::DEPTH_TEST), gl::TRUE);
+            assert_eq!(gl::IsEnabled(gl::CULL_FACE), gl::TRUE);
+            assert_eq!(gl::IsEnabled(gl::TEXTURE_CUBE_MAP_SEAMLESS), gl::FALSE);
+
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::CULL_FACE);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    });
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use cgmath::{vec3, Matrix4};
+use silver_gl::{
+    gl, model_utils::create_quad, Framebuffer, GlError, ModelTrait, MultiBindModel, ShaderCodeBundle, ShaderProgram, StateCache
+};
+
+// Instance i squeezed into column i of 4 across the target
+fn column_transforms(count: usize) -> Vec<Matrix4<f32>> {
+    (0..count)
+        .map(|i| Matrix4::from_translation(vec3(-0.75 + 0.5 * i as f32, 0.0, 0.0)) * Matrix4::from_nonuniform_scale(0.25, 1.0, 1.0))
+        .collect()
+}
+
+#[test]
+fn instance_count_follows_set_transforms() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        shader.use_program();
+        let framebuffer = Framebuffer::new(64, 16, 1, false).unwrap();
+        let mut quad: MultiBindModel = create_quad(column_transforms(2));
+
+        // Growing past and shrinking below the first allocation, one frame each
+        for count in [2, 4, 1, 3, 0, 2] {
+            quad.set_transforms(column_transforms(count));
+
+            common::bind_cleared(&framebuffer);
+            quad.draw(&shader).unwrap();
+
+            for column in 0..4 {
+                assert_eq!(common::is_lit(&framebuffer, column * 16 + 8, 8), column < count as i32, "{} instances", count);
+            }
+        }
+
+        Framebuffer::unbind();
+    });
+}
+
+// Like flat_shader, but only instances with material id 7 (location 9, after the transform) are white
+fn material_shader() -> ShaderProgram {
+    ShaderProgram::new(ShaderCodeBundle {
+        vertex: Some(String::from("#version 450 core
+            layout(location = 0) in vec3 position;
+            layout(location = 5) in mat4 model;
+            layout(location = 9) in uint material;
+            flat out uint instance_material;
+            void main() { gl_Position = model * vec4(position, 1.0); instance_material = material; }")),
+        geometry: None,
+        fragment: Some(String::from("#version 450 core
+            flat in uint instance_material;
+            out vec4 color;
+            void main() { color = vec4(instance_material == 7u ? 1.0 : 0.0); }"))
+    }).unwrap()
+}
+
+#[test]
+fn draw_instances_indexed_gathers_transforms_and_materials() {
+    common::with_context(|| {
+        let shader = material_shader();
+        shader.use_program();
+        let framebuffer = Framebuffer::new(64, 16, 1, false).unwrap();
+        let mut quad: MultiBindModel = create_quad(column_transforms(4));
+        quad.enable_instance_materials(vec![0, 7, 0, 7]);
+
+        // Drawn in subset order, so ungathered materials would light column 2 instead of 1
+        for (indices, lit) in [(&[1, 2][..], [false, true, false, false]), (&[3, 0][..], [false, false, false, true])] {
+            common::bind_cleared(&framebuffer);
+            quad.draw_instances_indexed(&shader, indices).unwrap();
+
+            for column in 0..4 {
+                assert_eq!(common::is_lit(&framebuffer, column * 16 + 8, 8), lit[column as usize], "{:?}", indices);
+            }
+        }
+
+        // The full buffers are bound again afterwards
+        common::bind_cleared(&framebuffer);
+        quad.draw(&shader).unwrap();
+        assert!(common::is_lit(&framebuffer, 24, 8) && common::is_lit(&framebuffer, 56, 8));
+
+        assert!(matches!(quad.draw_instances_indexed(&shader, &[0, 4]), Err(GlError::InvalidInstanceIndex(4, 4))));
+
+        Framebuffer::unbind();
+    });
+}
+
+#[test]
+fn base_instance_draw_uses_mode() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        shader.use_program();
+        let framebuffer = Framebuffer::new(64, 16, 1, false).unwrap();
+        let quad: MultiBindModel = create_quad(column_transforms(4));
+
+        // Instances 1 and 2 as triangles, then only the corner points of instance 3
+        for (mode, instance_count, base_instance, lit) in [
+            (gl::TRIANGLES, 2, 1, [false, true, true, false]),
+            (gl::POINTS, 1, 3, [false; 4])
+        ] {
+            common::bind_cleared(&framebuffer);
+            unsafe { quad.vertex_array.bind() };
+            quad.vertex_array.draw_elements_offset_base_instance(mode, 6, 0, instance_count, base_instance).unwrap();
+            StateCache::bind_vertex_array(0);
+
+            for column in 0..4 {
+                assert_eq!(common::is_lit(&framebuffer, column * 16 + 8, 8), lit[column as usize], "{:#X}", mode);
+            }
+        }
+
+        Framebuffer::unbind();
+    });
+}
@@ -0,0 +1,314 @@
+mod common;
+
+use cgmath::{vec2, vec3, Matrix4, Vector2, Vector3, Zero};
+use silver_gl::{
+    capabilities, gl, model_utils::create_quad, BindlessModel, DrawCommand, Framebuffer, GlError, LightmapVertex, Mesh,
+    Model, ModelCreateTrait, ModelTrait, MultiBindModel, RenderState, StateCache, Tangents, Vertex
+};
+
+#[test]
+fn bindless_model_transform_array_len() {
+    common::with_context(|| {
+        let mut quad: BindlessModel = create_quad(vec![Matrix4::from_scale(1.0); 3]);
+        assert_eq!(quad.get_transform_array().len(), 3);
+
+        quad.set_transforms(vec![Matrix4::from_scale(1.0); 5]);
+        let model: &dyn ModelTrait = &quad;
+        assert_eq!(model.get_transform_array().len(), 5);
+    });
+}
+
+#[test]
+fn merge_keeps_mirrored_instances_facing_forward() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        shader.use_program();
+        let framebuffer = Framebuffer::new(32, 16, 1, false).unwrap();
+
+        // Left half as is, right half mirrored on x
+        let quad: MultiBindModel = create_quad(vec![
+            Matrix4::from_translation(vec3(-0.5, 0.0, 0.0)) * Matrix4::from_nonuniform_scale(0.5, 1.0, 1.0),
+            Matrix4::from_translation(vec3(0.5, 0.0, 0.0)) * Matrix4::from_nonuniform_scale(-0.5, 1.0, 1.0)
+        ]);
+        let merged = MultiBindModel::merge(vec![quad]);
+
+        common::bind_cleared(&framebuffer);
+        StateCache::set_cull_face(true);
+        merged.draw(&shader).unwrap();
+        StateCache::set_cull_face(false);
+
+        assert!(common::is_lit(&framebuffer, 8, 8));
+        assert!(common::is_lit(&framebuffer, 24, 8));
+
+        Framebuffer::unbind();
+    });
+}
+
+// One triangle with uvs running along x and y, so its tangent points along x
+fn uv_triangle() -> (Vec<Vertex>, Vec<u32>) {
+    let vertex = |x: f32, y: f32| Vertex {
+        position: vec3(x, y, 0.0),
+        normal: vec3(0.0, 0.0, 1.0),
+        tex_coord: vec2(x, y),
+        ..Vertex::default()
+    };
+
+    (vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)], vec![0, 1, 2])
+}
+
+fn first_tangent(vertices: &[Vertex]) -> Vector3<f32> {
+    vertices[0].tangent
+}
+
+#[test]
+fn tangents_default_to_every_mesh() {
+    common::with_context(|| {
+        let (vertices, indices) = uv_triangle();
+        let model = MultiBindModel::new(vertices.clone(), indices.clone(), vec![Matrix4::from_scale(1.0)], vec![Mesh::new(0, 3)]);
+        assert!(first_tangent(&model.get_vertices_cpu()).x > 0.0);
+
+        // No normal texture, so skipped until asked for
+        let mut model = MultiBindModel::new_with_tangents(
+            vertices,
+            indices,
+            vec![Matrix4::from_scale(1.0)],
+            vec![Mesh::new(0, 3)],
+            Tangents::NormalMapped
+        );
+        assert!(first_tangent(&model.get_vertices_cpu()).is_zero());
+
+        model.compute_tangents(Tangents::All);
+        assert!(first_tangent(&model.get_vertices_cpu()).x > 0.0);
+    });
+}
+
+#[test]
+fn compute_tangents_on_custom_vertices() {
+    common::with_context(|| {
+        let (vertices, indices) = uv_triangle();
+        let vertices = vertices.into_iter().map(|vertex| LightmapVertex::from_vertex(vertex, Vector2::zero())).collect();
+        let mut model = Model::new_custom(vertices, indices, vec![Matrix4::from_scale(1.0)], vec![Mesh::new(0, 3)]);
+
+        model.compute_tangents(Tangents::All);
+        assert!({ model.get_vertices_cpu()[0].tangent }.x > 0.0);
+    });
+}
+
+#[test]
+fn batch_by_material_keeps_mirrored_strips_facing_forward() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        shader.use_program();
+        let framebuffer = Framebuffer::new(32, 16, 1, false).unwrap();
+
+        // The quad's corners as one counter clockwise strip
+        let quad: MultiBindModel = create_quad(vec![Matrix4::from_scale(1.0)]);
+        let vertices = quad.get_vertices_cpu().into_owned();
+        let strip = MultiBindModel::new(
+            vertices,
+            vec![1, 2, 0, 3],
+            vec![
+                Matrix4::from_translation(vec3(-0.5, 0.0, 0.0)) * Matrix4::from_nonuniform_scale(0.5, 1.0, 1.0),
+                Matrix4::from_translation(vec3(0.5, 0.0, 0.0)) * Matrix4::from_nonuniform_scale(-0.5, 1.0, 1.0)
+            ],
+            vec![Mesh::with_topology(0, 4, gl::TRIANGLE_STRIP)]
+        );
+
+        let batches = MultiBindModel::batch_by_material(vec![strip]);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].meshes[0].topology, gl::TRIANGLES);
+
+        common::bind_cleared(&framebuffer);
+        StateCache::set_cull_face(true);
+        batches[0].draw(&shader).unwrap();
+        StateCache::set_cull_face(false);
+
+        assert!(common::is_lit(&framebuffer, 8, 8));
+        assert!(common::is_lit(&framebuffer, 24, 8));
+
+        Framebuffer::unbind();
+    });
+}
+
+#[test]
+fn draw_range_uses_mode() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        shader.use_program();
+        let framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+
+        // As a list only the bottom left triangle of the strip would be drawn
+        let quad: MultiBindModel = create_quad(vec![Matrix4::from_scale(1.0)]);
+        let vertices = quad.get_vertices_cpu().into_owned();
+        let strip = MultiBindModel::new(
+            vertices,
+            vec![1, 2, 0, 3],
+            vec![Matrix4::from_scale(1.0)],
+            vec![Mesh::with_topology(0, 4, gl::TRIANGLE_STRIP)]
+        );
+
+        common::bind_cleared(&framebuffer);
+        strip.draw_range(gl::TRIANGLE_STRIP, 0, 4, 0, 1).unwrap();
+
+        assert!(common::is_lit(&framebuffer, 2, 2));
+        assert!(common::is_lit(&framebuffer, 13, 13));
+
+        Framebuffer::unbind();
+    });
+}
+
+#[test]
+fn draw_indirect_count_rejects_mixed_topologies() {
+    common::with_context(|| {
+        if !capabilities::supports_version(4, 6) && !capabilities::has_extension("GL_ARB_indirect_parameters") {
+            eprintln!("skipped, needs GL_ARB_indirect_parameters");
+            return;
+        }
+
+        let shader = common::flat_shader();
+        let quad: MultiBindModel = create_quad(vec![Matrix4::from_scale(1.0)]);
+        let mut model = BindlessModel::new(
+            quad.get_vertices_cpu().into_owned(),
+            vec![0, 1, 2, 1, 2, 0, 3],
+            vec![Matrix4::from_scale(1.0)],
+            vec![Mesh::new(0, 3), Mesh::with_topology(3, 4, gl::TRIANGLE_STRIP)]
+        );
+        model.enable_count_buffer();
+
+        assert!(matches!(
+            model.draw_indirect_count(&shader),
+            Err(GlError::MixedTopologies(gl::TRIANGLES, gl::TRIANGLE_STRIP))
+        ));
+    });
+}
+
+#[test]
+fn flip_winding_restores_tracked_front_face() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        shader.use_program();
+        let framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+
+        // Mirrored so it winds clockwise, only drawn with culling because the mesh flips the winding
+        let quad: MultiBindModel = create_quad(vec![Matrix4::from_scale(1.0)]);
+        let mut mirrored = MultiBindModel::new(
+            quad.get_vertices_cpu().into_owned(),
+            quad.get_indices_cpu().into_owned(),
+            vec![Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0)],
+            vec![Mesh::new(0, 6)]
+        );
+        mirrored.meshes[0].flip_winding = true;
+
+        let mut render_state = RenderState::new();
+        render_state.set_front_face(gl::CCW);
+
+        common::bind_cleared(&framebuffer);
+        StateCache::set_cull_face(true);
+        mirrored.draw(&shader).unwrap();
+        StateCache::set_cull_face(false);
+
+        assert!(common::is_lit(&framebuffer, 8, 8));
+        assert_eq!(StateCache::get_front_face(), gl::CCW);
+
+        let mut front_face = 0;
+        unsafe { gl::GetIntegerv(gl::FRONT_FACE, &mut front_face) };
+        assert_eq!(front_face as u32, gl::CCW);
+
+        Framebuffer::unbind();
+    });
+}
+
+// Left and right halves of the screen as separate meshes
+fn halves<T: ModelCreateTrait>() -> T {
+    let vertex = |x: f32, y: f32| Vertex { position: vec3(x, y, 0.0), ..Vertex::default() };
+    let vertices = [-1.0, 0.0]
+        .iter()
+        .flat_map(|&left| [vertex(left, -1.0), vertex(left + 1.0, -1.0), vertex(left + 1.0, 1.0), vertex(left, 1.0)])
+        .collect();
+
+    T::new(
+        vertices,
+        vec![0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7],
+        vec![Matrix4::from_scale(1.0)],
+        vec![Mesh::new(0, 6), Mesh::new(6, 6)]
+    )
+}
+
+#[test]
+fn set_transforms_keeps_culled_commands() {
+    common::with_context(|| {
+        if !capabilities::supports_version(4, 6) && !capabilities::has_extension("GL_ARB_indirect_parameters") {
+            eprintln!("skipped, needs GL_ARB_indirect_parameters");
+            return;
+        }
+
+        let shader = common::flat_shader();
+        shader.use_program();
+        let framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+
+        let mut model: BindlessModel = halves();
+        model.enable_count_buffer();
+
+        // Only the right half is visible
+        model.set_commands(&[DrawCommand::new(6, 1, 6, 0, 0)]).unwrap();
+        model.set_transforms(vec![Matrix4::from_scale(1.0)]);
+
+        common::bind_cleared(&framebuffer);
+        model.draw_indirect_count(&shader).unwrap();
+
+        assert!(!common::is_lit(&framebuffer, 4, 8));
+        assert!(common::is_lit(&framebuffer, 12, 8));
+
+        Framebuffer::unbind();
+    });
+}
+
+#[test]
+fn double_sided_meshes_restore_tracked_culling() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        shader.use_program();
+        let framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+
+        // Mirrored so only its back face is visible
+        let quad: MultiBindModel = create_quad(vec![Matrix4::from_scale(1.0)]);
+        let mut mirrored = MultiBindModel::new(
+            quad.get_vertices_cpu().into_owned(),
+            quad.get_indices_cpu().into_owned(),
+            vec![Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0)],
+            vec![Mesh::new(0, 6)]
+        );
+        mirrored.meshes[0].double_sided = true;
+
+        common::bind_cleared(&framebuffer);
+        StateCache::set_cull_face(true);
+        mirrored.draw(&shader).unwrap();
+
+        assert!(common::is_lit(&framebuffer, 8, 8));
+        assert!(StateCache::is_cull_face());
+        assert_eq!(unsafe { gl::IsEnabled(gl::CULL_FACE) }, gl::TRUE);
+
+        StateCache::set_cull_face(false);
+        Framebuffer::unbind();
+    });
+}
+
+#[test]
+fn invalid_mesh_ranges_leave_bounds_unset() {
+    common::with_context(|| {
+        let (vertices, mut indices) = uv_triangle();
+        indices.extend([0, 1, 7]);
+
+        // In range, past the end of the indices, and referencing a missing vertex
+        let model: Model = Model::new(
+            vertices,
+            indices,
+            vec![Matrix4::from_scale(1.0)],
+            vec![Mesh::new(0, 3), Mesh::new(3, 6), Mesh::new(3, 3)]
+        );
+
+        assert!(model.meshes[0].get_aabb().is_some());
+        assert!(model.meshes[0].bounding_sphere().is_some());
+        assert!(model.meshes[1..].iter().all(|mesh| mesh.get_aabb().is_none() && mesh.bounding_sphere().is_none()));
+    });
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::rc::Rc;
+use silver_gl::{gl, Framebuffer, GlError, RenderPipeline, Texture};
+
+// Single pass pipeline rendering into its own framebuffer
+struct Pass {
+    framebuffer: Framebuffer
+}
+
+impl RenderPipeline for Pass {
+    fn bind(&self) {
+        self.framebuffer.bind();
+    }
+
+    fn draw(&mut self) -> Result<(), GlError> {
+        Ok(())
+    }
+
+    fn get_height(&self) -> (i32, i32) {
+        self.framebuffer.get_size()
+    }
+
+    fn set_size(&mut self, width: i32, height: i32) -> Result<(), GlError> {
+        self.framebuffer.set_size(width, height).map(|_| ())
+    }
+
+    fn get_link(&self) -> Result<Vec<Rc<Texture>>, GlError> {
+        Ok(self.framebuffer.get_link())
+    }
+
+    fn link_to(&mut self, output: Vec<Rc<Texture>>) -> Result<(), GlError> {
+        self.framebuffer.link_to(output);
+        Ok(())
+    }
+
+    fn link_push(&mut self, texture: Rc<Texture>) -> Result<(), GlError> {
+        self.framebuffer.link_push(texture);
+        Ok(())
+    }
+
+    fn unlink(&mut self) {
+        self.framebuffer.unlink();
+    }
+
+    fn get_framebuffer(&self) -> Option<&Framebuffer> {
+        Some(&self.framebuffer)
+    }
+}
+
+#[test]
+fn bind_pass_honors_clear_mask() {
+    common::with_context(|| {
+        let mut pass = Pass { framebuffer: Framebuffer::new(4, 4, 1, false).unwrap() };
+        unsafe { gl::ClearColor(0.0, 0.0, 0.0, 0.0) };
+
+        // Nothing declared, the previous contents are kept
+        pass.framebuffer.clear_all_colors([1.0; 4]);
+        pass.bind_pass();
+        assert!(common::is_lit(&pass.framebuffer, 0, 0));
+
+        pass.framebuffer.clear_mask = gl::COLOR_BUFFER_BIT;
+        pass.bind_pass();
+        assert!(!common::is_lit(&pass.framebuffer, 0, 0));
+
+        Framebuffer::unbind();
+    });
+}
+
+#[test]
+fn bind_pass_clears_to_default_depth() {
+    common::with_context(|| {
+        let mut framebuffer = Framebuffer::new(4, 4, 1, false).unwrap();
+        framebuffer.gen_depth_texture();
+        framebuffer.clear_mask = gl::DEPTH_BUFFER_BIT;
+        framebuffer.default_clear_depth = 0.0;
+
+        let pass = Pass { framebuffer };
+        let mut depth = [0.5f32; 16];
+        let mut global_clear_depth = 0.0;
+        unsafe { gl::ClearDepth(1.0) };
+
+        pass.bind_pass();
+
+        unsafe {
+            gl::GetTextureImage(
+                pass.framebuffer.get_depth_texture().unwrap().get_id(),
+                0,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                std::mem::size_of_val(&depth) as i32,
+                depth.as_mut_ptr() as *mut gl::types::GLvoid
+            );
+            gl::GetFloatv(gl::DEPTH_CLEAR_VALUE, &mut global_clear_depth);
+        }
+
+        assert!(depth.iter().all(|value| *value == 0.0));
+        assert_eq!(global_clear_depth, 1.0);
+
+        Framebuffer::unbind();
+    });
+}
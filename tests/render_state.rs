@@ -0,0 +1,63 @@
+mod common;
+
+use silver_gl::{gl, RenderState, StateCache, Texture};
+
+fn get(name: gl::types::GLenum) -> u32 {
+    let mut value = 0;
+    unsafe { gl::GetIntegerv(name, &mut value) };
+    value as u32
+}
+
+#[test]
+fn restore_brings_back_bindings_and_tracking() {
+    common::with_context(|| {
+        let host_texture = Texture::new_mut(4, 4);
+        let crate_texture = Texture::new_mut(4, 4);
+        let mut buffers = [0; 2];
+        unsafe {
+            gl::CreateBuffers(2, buffers.as_mut_ptr());
+            gl::NamedBufferData(buffers[0], 256, std::ptr::null(), gl::STATIC_DRAW);
+            gl::NamedBufferData(buffers[1], 256, std::ptr::null(), gl::STATIC_DRAW);
+
+            // What the host application had bound
+            gl::ActiveTexture(gl::TEXTURE3);
+            gl::BindTexture(gl::TEXTURE_2D, host_texture.get_id());
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, 2, buffers[0]);
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffers[0]);
+        }
+
+        let mut render_state = RenderState::new();
+        let saved = RenderState::snapshot();
+
+        StateCache::bind_texture_unit(3, crate_texture.get_id());
+        render_state.set_depth_func(gl::GREATER);
+        render_state.set_point_size(4.0);
+        unsafe {
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, 2, buffers[1]);
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffers[1]);
+            gl::DepthFunc(gl::EQUAL);
+        }
+
+        render_state.restore(&saved);
+
+        unsafe { gl::ActiveTexture(gl::TEXTURE3) };
+        assert_eq!(get(gl::TEXTURE_BINDING_2D), host_texture.get_id());
+        unsafe { gl::ActiveTexture(gl::TEXTURE0) };
+
+        let mut uniform_buffer = 0;
+        unsafe { gl::GetIntegeri_v(gl::UNIFORM_BUFFER_BINDING, 2, &mut uniform_buffer) };
+        assert_eq!(uniform_buffer as u32, buffers[0]);
+        assert_eq!(get(gl::ARRAY_BUFFER_BINDING), buffers[0]);
+
+        assert_eq!(get(gl::DEPTH_FUNC), gl::LESS);
+        assert_eq!(render_state.get_depth_func(), gl::LESS);
+        assert_eq!(render_state.get_point_size(), 1.0);
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, 2, 0);
+            gl::DeleteBuffers(2, buffers.as_ptr());
+        }
+    });
+}
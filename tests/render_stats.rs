@@ -0,0 +1,67 @@
+// Tests counting GL work through RenderStats, run with --features render_stats
+#![cfg(feature = "render_stats")]
+
+mod common;
+
+use std::rc::Rc;
+use cgmath::Matrix4;
+use silver_gl::{model_utils::create_quad, Framebuffer, Mesh, ModelTrait, MultiBindModel, RenderStats, Texture};
+
+#[test]
+fn empty_model_issues_no_draw_calls() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        let framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+        let mut quad: MultiBindModel = create_quad(Vec::new());
+
+        shader.use_program();
+        common::bind_cleared(&framebuffer);
+        RenderStats::take_frame_stats();
+
+        quad.draw(&shader).unwrap();
+        assert_eq!(RenderStats::take_frame_stats().draw_calls, 0);
+
+        // Meshes with nothing to draw are skipped as well
+        quad.set_transforms(vec![Matrix4::from_scale(1.0)]);
+        quad.get_meshes_mut().push(Mesh::new(0, 0));
+        quad.draw(&shader).unwrap();
+        assert_eq!(RenderStats::take_frame_stats().draw_calls, 1);
+
+        Framebuffer::unbind();
+    });
+}
+
+#[test]
+fn sorting_by_material_reduces_texture_binds() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        let framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+        let mut quad: MultiBindModel = create_quad(vec![Matrix4::from_scale(1.0)]);
+
+        // Four meshes alternating between two materials
+        let materials = [Rc::new(Texture::new_mut(4, 4)), Rc::new(Texture::new_mut(4, 4))];
+        let meshes = quad.get_meshes_mut();
+        meshes.clear();
+        for i in 0..4 {
+            let mut mesh = Mesh::new(0, 6);
+            mesh.diffuse_textures.push(Rc::clone(&materials[i % 2]));
+            meshes.push(mesh);
+        }
+
+        shader.use_program();
+        common::bind_cleared(&framebuffer);
+        RenderStats::take_frame_stats();
+
+        quad.draw(&shader).unwrap();
+        let unsorted = RenderStats::take_frame_stats().texture_binds;
+
+        quad.sort_meshes_by_material();
+        quad.draw(&shader).unwrap();
+        let sorted = RenderStats::take_frame_stats().texture_binds;
+
+        assert_eq!(unsorted, 4);
+        assert_eq!(sorted, 2);
+
+        Framebuffer::unbind();
+    });
+}
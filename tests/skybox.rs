@@ -0,0 +1,35 @@
+mod common;
+
+use cgmath::Matrix4;
+use silver_gl::{gl, model_utils::create_quad, Framebuffer, RenderState, Skybox};
+
+#[test]
+fn skybox_passes_at_reverse_z_far_plane() {
+    common::with_context(|| {
+        let shader = common::flat_shader();
+        let mut framebuffer = Framebuffer::new(16, 16, 1, false).unwrap();
+        framebuffer.gen_depth_texture();
+
+        let mut render_state = RenderState::new();
+        render_state.set_reverse_z(true);
+
+        // The quad sits at z = 0, which is the far plane with reverse-Z
+        let skybox = Skybox { model: create_quad(vec![Matrix4::from_scale(1.0)]) };
+
+        common::bind_cleared(&framebuffer);
+        framebuffer.clear_depth(render_state.get_clear_depth());
+        unsafe { gl::Enable(gl::DEPTH_TEST) };
+        skybox.draw(&shader, &mut render_state).unwrap();
+        unsafe { gl::Disable(gl::DEPTH_TEST) };
+
+        assert!(common::is_lit(&framebuffer, 8, 8));
+        assert_eq!(render_state.get_depth_func(), gl::GREATER);
+
+        let mut depth_func = 0;
+        unsafe { gl::GetIntegerv(gl::DEPTH_FUNC, &mut depth_func) };
+        assert_eq!(depth_func as u32, gl::GREATER);
+
+        render_state.set_reverse_z(false);
+        Framebuffer::unbind();
+    });
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use silver_gl::{gl, GlError, Texture};
+
+#[test]
+fn upload_level_checks_size_and_data_length() {
+    common::with_context(|| {
+        let texture = Texture::new_immutable(8, 8, gl::RGBA8, 4);
+
+        texture.upload_level(1, 4, 4, &[255; 64], gl::RGBA).unwrap();
+
+        assert!(matches!(texture.upload_level(1, 4, 4, &[255; 63], gl::RGBA), Err(GlError::ImageDataTooShort(63, 64))));
+        assert!(matches!(texture.upload_level(1, 8, 8, &[255; 256], gl::RGBA), Err(GlError::InvalidLevelSize(1, 8, 8, 4, 4))));
+        assert!(matches!(texture.upload_level(4, 1, 1, &[255; 4], gl::RGBA), Err(GlError::InvalidMipLevel(4, 4))));
+
+        // Rows of a 1 pixel wide RGB level are padded to 4 bytes, except the last one
+        let rgb = Texture::new_immutable(1, 3, gl::RGB8, 1);
+        rgb.upload_level(0, 1, 3, &[255; 11], gl::RGB).unwrap();
+        assert!(matches!(rgb.upload_level(0, 1, 3, &[255; 10], gl::RGB), Err(GlError::ImageDataTooShort(10, 11))));
+    });
+}
+
+#[test]
+fn read_image_rejects_other_targets() {
+    common::with_context(|| {
+        let cubemap = Texture::new_cubemap(4, gl::RGBA8, 1);
+        let multisample = Texture::new_multisample(4, 4, 2, gl::RGBA8);
+
+        assert!(matches!(cubemap.read_image(), Err(GlError::UnsupportedTextureTarget(_, gl::TEXTURE_CUBE_MAP))));
+        assert!(matches!(multisample.read_image(), Err(GlError::UnsupportedTextureTarget(_, gl::TEXTURE_2D_MULTISAMPLE))));
+        assert_eq!(Texture::new_immutable(4, 4, gl::RGBA8, 1).read_image().unwrap().bytes.len(), 64);
+    });
+}
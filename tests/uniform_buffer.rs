@@ -0,0 +1,70 @@
+mod common;
+
+use silver_gl::{capabilities, gl, GlError, ShaderCodeBundle, ShaderProgram, UniformBuffer};
+
+// Fragment shader reading a block declared on binding 2
+fn block_shader() -> ShaderProgram {
+    ShaderProgram::new(ShaderCodeBundle {
+        vertex: Some(String::from("#version 450 core
+            layout(location = 0) in vec3 position;
+            void main() { gl_Position = vec4(position, 1.0); }")),
+        geometry: None,
+        fragment: Some(String::from("#version 450 core
+            layout(std140, binding = 2) uniform Tint { vec4 tint; };
+            out vec4 color;
+            void main() { color = tint; }"))
+    }).unwrap()
+}
+
+#[test]
+fn register_validates_without_rebinding() {
+    common::with_context(|| {
+        let shader = block_shader();
+
+        assert!(UniformBuffer::new_at_binding(vec![&shader], "Tint", 16, 2).is_ok());
+        assert!(matches!(
+            UniformBuffer::new_at_binding(vec![&shader], "Tint", 16, 3),
+            Err(GlError::UniformBindingMismatch(_, _, 3, 2))
+        ));
+        assert_eq!(shader.get_ubo_binding("Tint").unwrap(), 2);
+
+        let buffer = UniformBuffer::new_at_binding(vec![], "Tint", 16, 3).unwrap();
+        buffer.rebind_shader_program(&shader).unwrap();
+        assert_eq!(shader.get_ubo_binding("Tint").unwrap(), 3);
+        assert!(buffer.register_shader_program(&shader).is_ok());
+    });
+}
+
+fn buffer_bytes(buffer: &UniformBuffer) -> Vec<u8> {
+    let mut bytes = vec![0u8; buffer.get_size() as usize];
+    unsafe { gl::GetNamedBufferSubData(buffer.get_id(), 0, buffer.get_size(), bytes.as_mut_ptr() as *mut gl::types::GLvoid) };
+    bytes
+}
+
+#[test]
+fn array_resize_keeps_elements() {
+    common::with_context(|| {
+        let mut buffer = UniformBuffer::new_array(vec![], "Lights", 16, 3, 0).unwrap();
+        let old_stride = buffer.get_stride() as usize;
+
+        for index in 0..3 {
+            buffer.write_element_bytes(index, &[index as u8 + 1; 16], 0).unwrap();
+        }
+        assert!(matches!(buffer.write_element_bytes(3, &[0; 16], 0), Err(GlError::UniformArrayIndex(_, 3, 3))));
+
+        buffer.resize(buffer.get_size() * 2, true).unwrap();
+        let (stride, bytes) = (buffer.get_stride() as usize, buffer_bytes(&buffer));
+
+        assert_eq!(stride, old_stride * 2);
+        for index in 0..3 {
+            let element = &bytes[index * stride..(index + 1) * stride];
+            assert!(element[..16].iter().all(|byte| *byte == index as u8 + 1));
+            assert!(element[16..].iter().all(|byte| *byte == 0));
+        }
+
+        // Every element needs at least one aligned slot
+        let alignment = capabilities::uniform_buffer_offset_alignment();
+        assert!(matches!(buffer.resize(3 * alignment - 1, true), Err(GlError::UniformBufferTooSmall(_, _, minimum)) if minimum == 3 * alignment));
+        assert_eq!(buffer.get_stride() as usize, stride);
+    });
+}